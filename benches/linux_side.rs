@@ -0,0 +1,171 @@
+// Copyright (c) Max Kagamine
+// Licensed under the Apache License, Version 2.0
+
+// Times the Linux side of `recycle --rm` and `--use-linux-trash` with different numbers of worker
+// threads, on a tmpfs so that the disk doesn't factor in:
+//
+//   cargo bench --bench linux_side [-- DIR]
+//
+// DIR defaults to /dev/shm. The trash benchmark points $XDG_DATA_HOME inside it, so that the items
+// go to a throwaway home trash rather than the real one.
+
+#[cfg(unix)]
+mod bench {
+    use std::{
+        env,
+        fs::{self, File},
+        io::Write,
+        path::{Path, PathBuf},
+        time::{Duration, Instant},
+    };
+    use wsl_tools::{
+        freedesktop_trash::{Trash, TrashFallback},
+        recycle_backend::{FreedesktopTrash, RecycleBackend, RecycleItem},
+        remove_tree::{self, REMOVE_RECURSIVE},
+    };
+
+    const RUNS: usize = 5;
+    const WORKERS: [usize; 4] = [1, 2, 4, 8];
+
+    /// Something like a `node_modules`: lots of packages, each a few levels deep.
+    fn create_tree(root: &Path) {
+        for package in 0..300 {
+            let package = root.join(format!("package-{package}"));
+            for dir in ["", "lib", "lib/internal", "dist", "dist/esm", "types"] {
+                let dir = package.join(dir);
+                fs::create_dir_all(&dir).unwrap();
+                for file in 0..10 {
+                    File::create(dir.join(format!("{file}.js")))
+                        .unwrap()
+                        .write_all(b"module.exports = {};\n")
+                        .unwrap();
+                }
+            }
+        }
+    }
+
+    /// Runs `f` on a fresh copy of the fixture `RUNS` times, returning the median time.
+    fn time(setup: impl Fn() -> PathBuf, f: impl Fn(&Path)) -> Duration {
+        let mut times: Vec<Duration> = (0..RUNS)
+            .map(|_| {
+                let root = setup();
+                let start = Instant::now();
+                f(&root);
+                let elapsed = start.elapsed();
+                let _ = fs::remove_dir_all(&root);
+                elapsed
+            })
+            .collect();
+        times.sort();
+        times[RUNS / 2]
+    }
+
+    fn plural(n: usize) -> &'static str {
+        if n == 1 { "" } else { "s" }
+    }
+
+    fn print_row(label: &str, elapsed: Duration, baseline: Duration) {
+        println!(
+            "  {label:<24} {:>8.1} ms  {:>5.2}x",
+            elapsed.as_secs_f64() * 1000.0,
+            baseline.as_secs_f64() / elapsed.as_secs_f64()
+        );
+    }
+
+    fn bench_remove(dir: &Path) {
+        let setup = || {
+            let root = dir.join("remove");
+            create_tree(&root.join("node_modules"));
+            root
+        };
+        let remove = |root: &Path, workers: Option<usize>| {
+            let paths = [root.join("node_modules").to_string_lossy().into_owned()];
+            let result = match workers {
+                None => remove_tree::remove_with_callback(paths, REMOVE_RECURSIVE, |_, _| {}),
+                Some(n) => remove_tree::remove_parallel(paths, REMOVE_RECURSIVE, n, |_, _| {}),
+            };
+            result.unwrap();
+        };
+
+        println!("remove_tree, one node_modules tree (1,801 directories, 18,000 files):");
+        let baseline = time(setup, |root| remove(root, None));
+        print_row("remove_with_callback", baseline, baseline);
+        for workers in WORKERS {
+            let elapsed = time(setup, |root| remove(root, Some(workers)));
+            print_row(&format!("remove_parallel, {workers}"), elapsed, baseline);
+        }
+    }
+
+    fn bench_trash(dir: &Path) {
+        // SAFETY: Nothing else is running yet
+        unsafe {
+            env::set_var("XDG_DATA_HOME", dir.join("trash-home"));
+        }
+
+        let setup = || {
+            let _ = fs::remove_dir_all(dir.join("trash-home"));
+            let root = dir.join("trash");
+            fs::create_dir_all(&root).unwrap();
+            for i in 0..2000 {
+                if i % 2 == 0 {
+                    File::create(root.join(format!("file-{i}.txt"))).unwrap();
+                } else {
+                    fs::create_dir_all(root.join(format!("dir-{i}/sub"))).unwrap();
+                }
+            }
+            root
+        };
+        let trash = |root: &Path, workers: usize| {
+            let mut items: Vec<RecycleItem> = fs::read_dir(root)
+                .unwrap()
+                .map(|x| RecycleItem {
+                    path: x.unwrap().path().to_string_lossy().into_owned(),
+                    windows_path: String::new(),
+                    is_dir: false,
+                })
+                .collect();
+            items.sort_by(|a, b| a.path.cmp(&b.path));
+
+            let mut backend =
+                FreedesktopTrash::new(Trash::new(TrashFallback::Refuse).unwrap(), false, false)
+                    .workers(workers);
+            assert_eq!(backend.recycle(items, &mut |_| {}), 0);
+        };
+
+        println!("FreedesktopTrash, 2,000 items:");
+        let mut baseline = None;
+        for workers in WORKERS {
+            let elapsed = time(setup, |root| trash(root, workers));
+            print_row(
+                &format!("{workers} worker{}", plural(workers)),
+                elapsed,
+                *baseline.get_or_insert(elapsed),
+            );
+        }
+    }
+
+    pub fn main() {
+        let dir = env::args()
+            .skip(1)
+            .find(|x| !x.starts_with('-')) // cargo bench passes --bench
+            .map_or_else(|| PathBuf::from("/dev/shm"), PathBuf::from)
+            .join(format!("wsl-tools-bench-{}", std::process::id()));
+
+        let cpus = std::thread::available_parallelism().map_or(1, std::num::NonZeroUsize::get);
+        println!("{cpus} CPU{}, in {}\n", plural(cpus), dir.display());
+
+        bench_remove(&dir);
+        println!();
+        bench_trash(&dir);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
+
+#[cfg(unix)]
+fn main() {
+    bench::main();
+}
+
+#[cfg(not(unix))]
+fn main() {}
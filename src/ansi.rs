@@ -0,0 +1,549 @@
+// Copyright (c) Max Kagamine
+// Licensed under the Apache License, Version 2.0
+
+// Converts terminal output containing ANSI escape sequences (`cargo test`, `git diff`, `ls
+// --color`, etc.) to HTML and RTF, so that it keeps its colors when pasted into Word or Teams. Only
+// SGR ("Select Graphic Rendition", `ESC [ ... m`) sequences are interpreted; everything else, like
+// cursor movement or OSC 8 hyperlinks, is stripped. Of the SGR attributes, dim, blink, and
+// concealed text are shown as normal text.
+
+use std::fmt::Write;
+
+const ESC: char = '\x1b';
+const BEL: char = '\x07';
+
+/// The 16 standard and bright colors, from Windows Terminal's default scheme ("Campbell").
+const PALETTE: [(u8, u8, u8); 16] = [
+    (0x0c, 0x0c, 0x0c),
+    (0xc5, 0x0f, 0x1f),
+    (0x13, 0xa1, 0x0e),
+    (0xc1, 0x9c, 0x00),
+    (0x00, 0x37, 0xda),
+    (0x88, 0x17, 0x98),
+    (0x3a, 0x96, 0xdd),
+    (0xcc, 0xcc, 0xcc),
+    (0x76, 0x76, 0x76),
+    (0xe7, 0x48, 0x56),
+    (0x16, 0xc6, 0x0c),
+    (0xf9, 0xf1, 0xa5),
+    (0x3b, 0x78, 0xff),
+    (0xb4, 0x00, 0x9e),
+    (0x61, 0xd6, 0xd6),
+    (0xf2, 0xf2, 0xf2),
+];
+
+/// A color as given in an SGR sequence.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Color {
+    /// One of the 256 xterm colors: 0-15 are the standard and bright colors, 16-231 a 6×6×6 cube,
+    /// and 232-255 a grayscale ramp.
+    Indexed(u8),
+    /// A 24-bit "truecolor".
+    Rgb(u8, u8, u8),
+}
+
+impl Color {
+    #[must_use]
+    pub fn to_rgb(self) -> (u8, u8, u8) {
+        const CUBE: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+        match self {
+            Color::Rgb(r, g, b) => (r, g, b),
+            Color::Indexed(i @ 0..16) => PALETTE[i as usize],
+            Color::Indexed(i @ 16..232) => {
+                let i = (i - 16) as usize;
+                (CUBE[i / 36], CUBE[i / 6 % 6], CUBE[i % 6])
+            }
+            Color::Indexed(i) => {
+                let level = 8 + (i - 232) * 10;
+                (level, level, level)
+            }
+        }
+    }
+}
+
+/// The colors and attributes of a span of text. `None` means the terminal's default color.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct Style {
+    pub foreground: Option<Color>,
+    pub background: Option<Color>,
+    pub bold: bool,
+    pub italic: bool,
+    pub underline: bool,
+    pub strikethrough: bool,
+    /// Swaps the foreground and background colors (used by e.g. `git diff` to highlight
+    /// whitespace errors).
+    pub reverse: bool,
+}
+
+impl Style {
+    /// Gets the foreground and background colors to display, taking `reverse` into account. As the
+    /// output is meant to be pasted into documents, the default colors are taken to be black on
+    /// white rather than the terminal's.
+    #[must_use]
+    pub fn colors(self) -> (Option<Color>, Option<Color>) {
+        if self.reverse {
+            (
+                Some(self.background.unwrap_or(Color::Rgb(0xff, 0xff, 0xff))),
+                Some(self.foreground.unwrap_or(Color::Rgb(0, 0, 0))),
+            )
+        } else {
+            (self.foreground, self.background)
+        }
+    }
+}
+
+/// A run of text in a single style.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Span {
+    pub text: String,
+    pub style: Style,
+}
+
+/// Splits `input` into styled spans, removing all escape sequences.
+#[must_use]
+pub fn parse(input: &str) -> Vec<Span> {
+    let mut spans: Vec<Span> = Vec::new();
+    let mut style = Style::default();
+    let mut text = String::new();
+    let mut chars = input.chars().peekable();
+
+    let mut flush = |text: &mut String, style: Style| {
+        if text.is_empty() {
+            return;
+        }
+        match spans.last_mut() {
+            Some(last) if last.style == style => last.text.push_str(text),
+            _ => spans.push(Span {
+                text: text.clone(),
+                style,
+            }),
+        }
+        text.clear();
+    };
+
+    while let Some(c) = chars.next() {
+        if c != ESC {
+            text.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            // CSI: parameter bytes, then intermediate bytes, then a final byte
+            Some('[') => {
+                let mut params = String::new();
+                let mut is_sgr = false;
+                for c in chars.by_ref() {
+                    if ('\x40'..='\x7e').contains(&c) {
+                        is_sgr = c == 'm';
+                        break;
+                    }
+                    params.push(c);
+                }
+
+                // Private sequences (starting with `<=>?`) and intermediates aren't SGR
+                if is_sgr
+                    && params
+                        .chars()
+                        .all(|c| c.is_ascii_digit() || c == ';' || c == ':')
+                {
+                    flush(&mut text, style);
+                    apply_sgr(&params, &mut style);
+                }
+            }
+            // OSC and other strings, terminated by BEL or ST (`ESC \`)
+            Some(']' | 'P' | 'X' | '^' | '_') => {
+                while let Some(c) = chars.next() {
+                    if c == BEL || (c == ESC && chars.next_if_eq(&'\\').is_some()) {
+                        break;
+                    }
+                }
+            }
+            // Anything else is a two-character sequence, possibly with intermediates
+            Some('\x20'..='\x2f') => while chars.next().is_some_and(|c| c <= '\x2f') {},
+            _ => {}
+        }
+    }
+
+    flush(&mut text, style);
+    spans
+}
+
+#[allow(clippy::cast_possible_truncation)] // The color codes are matched by range below
+fn apply_sgr(params: &str, style: &mut Style) {
+    let mut params = params.split(';');
+
+    while let Some(param) = params.next() {
+        // Subparameters are separated by colons, e.g. `38:2::255:0:0`
+        let mut subparams = param.split(':').map(|x| x.parse::<u16>().unwrap_or(0));
+        let code = subparams.next().unwrap_or(0);
+
+        match code {
+            0 => *style = Style::default(),
+            1 => style.bold = true,
+            3 => style.italic = true,
+            4 => style.underline = subparams.next() != Some(0), // 4:0 is "no underline"
+            7 => style.reverse = true,
+            9 => style.strikethrough = true,
+            22 => style.bold = false,
+            23 => style.italic = false,
+            24 => style.underline = false,
+            27 => style.reverse = false,
+            29 => style.strikethrough = false,
+            30..=37 => style.foreground = Some(Color::Indexed((code - 30) as u8)),
+            38 | 48 => {
+                let color = if param.contains(':') {
+                    extended_color(&subparams.collect::<Vec<_>>())
+                } else {
+                    extended_color_from_params(&mut params)
+                };
+                if let Some(color) = color {
+                    if code == 38 {
+                        style.foreground = Some(color);
+                    } else {
+                        style.background = Some(color);
+                    }
+                }
+            }
+            39 => style.foreground = None,
+            40..=47 => style.background = Some(Color::Indexed((code - 40) as u8)),
+            49 => style.background = None,
+            90..=97 => style.foreground = Some(Color::Indexed((code - 90 + 8) as u8)),
+            100..=107 => style.background = Some(Color::Indexed((code - 100 + 8) as u8)),
+            _ => {}
+        }
+    }
+}
+
+/// Parses the subparameters of a colon-separated `38` or `48`: `5:n`, `2:r:g:b`, or `2:cs:r:g:b`
+/// with a (usually empty) color space ID.
+fn extended_color(values: &[u16]) -> Option<Color> {
+    let byte = |i: usize| values.get(i).and_then(|&x| u8::try_from(x).ok());
+
+    match values.first()? {
+        5 => Some(Color::Indexed(byte(1)?)),
+        2 => {
+            let i = if values.len() > 4 { 2 } else { 1 };
+            Some(Color::Rgb(byte(i)?, byte(i + 1)?, byte(i + 2)?))
+        }
+        _ => None,
+    }
+}
+
+/// Consumes the parameters following a semicolon-separated `38` or `48`: `5;n` or `2;r;g;b`.
+fn extended_color_from_params<'a>(params: &mut impl Iterator<Item = &'a str>) -> Option<Color> {
+    let mut next = || params.next().and_then(|x| x.parse::<u8>().ok());
+
+    match next()? {
+        5 => Some(Color::Indexed(next()?)),
+        2 => Some(Color::Rgb(next()?, next()?, next()?)),
+        _ => None,
+    }
+}
+
+/// Returns the text without any styling.
+#[must_use]
+pub fn to_plain(spans: &[Span]) -> String {
+    spans.iter().map(|x| x.text.as_str()).collect()
+}
+
+/// Converts the spans to a `<pre>` containing styled `<span>`s.
+#[must_use]
+pub fn to_html(spans: &[Span]) -> String {
+    let mut html = String::from("<pre style=\"font-family: Consolas, monospace\">");
+
+    for span in spans {
+        let css = css(span.style);
+        if css.is_empty() {
+            escape_html(&mut html, &span.text);
+        } else {
+            write!(html, "<span style=\"{css}\">").unwrap();
+            escape_html(&mut html, &span.text);
+            html.push_str("</span>");
+        }
+    }
+
+    html.push_str("</pre>");
+    html
+}
+
+fn css(style: Style) -> String {
+    let mut props = Vec::new();
+    let (foreground, background) = style.colors();
+
+    if let Some(color) = foreground {
+        props.push(format!("color: {}", hex(color)));
+    }
+    if let Some(color) = background {
+        props.push(format!("background-color: {}", hex(color)));
+    }
+    if style.bold {
+        props.push("font-weight: bold".to_string());
+    }
+    if style.italic {
+        props.push("font-style: italic".to_string());
+    }
+    match (style.underline, style.strikethrough) {
+        (true, true) => props.push("text-decoration: underline line-through".to_string()),
+        (true, false) => props.push("text-decoration: underline".to_string()),
+        (false, true) => props.push("text-decoration: line-through".to_string()),
+        (false, false) => {}
+    }
+
+    props.join("; ")
+}
+
+fn hex(color: Color) -> String {
+    let (r, g, b) = color.to_rgb();
+    format!("#{r:02x}{g:02x}{b:02x}")
+}
+
+fn escape_html(html: &mut String, text: &str) {
+    for c in text.chars() {
+        match c {
+            '&' => html.push_str("&amp;"),
+            '<' => html.push_str("&lt;"),
+            '>' => html.push_str("&gt;"),
+            '"' => html.push_str("&quot;"),
+            '\r' => {}
+            c => html.push(c),
+        }
+    }
+}
+
+/// Converts the spans to an RTF document in a monospace font. Lines are separated with `\line`
+/// rather than `\par`, since Word would otherwise add paragraph spacing between them.
+#[must_use]
+pub fn to_rtf(spans: &[Span]) -> String {
+    // The color table is indexed from 1; 0 is the default color
+    let mut colors: Vec<(u8, u8, u8)> = Vec::new();
+    let mut color_index = |color: Option<Color>| {
+        let rgb = color?.to_rgb();
+        let i = colors.iter().position(|&x| x == rgb).unwrap_or_else(|| {
+            colors.push(rgb);
+            colors.len() - 1
+        });
+        Some(i + 1)
+    };
+
+    let mut body = String::new();
+    for span in spans {
+        let mut controls = String::new();
+        let (foreground, background) = span.style.colors();
+        if let Some(i) = color_index(foreground) {
+            write!(controls, "\\cf{i}").unwrap();
+        }
+        if let Some(i) = color_index(background) {
+            write!(controls, "\\chcbpat{i}").unwrap();
+        }
+        if span.style.bold {
+            controls.push_str("\\b");
+        }
+        if span.style.italic {
+            controls.push_str("\\i");
+        }
+        if span.style.underline {
+            controls.push_str("\\ul");
+        }
+        if span.style.strikethrough {
+            controls.push_str("\\strike");
+        }
+
+        if controls.is_empty() {
+            escape_rtf(&mut body, &span.text);
+        } else {
+            write!(body, "{{{controls} ").unwrap();
+            escape_rtf(&mut body, &span.text);
+            body.push('}');
+        }
+    }
+
+    let mut rtf =
+        String::from("{\\rtf1\\ansi\\deff0{\\fonttbl{\\f0\\fmodern Consolas;}}{\\colortbl ;");
+    for (r, g, b) in colors {
+        write!(rtf, "\\red{r}\\green{g}\\blue{b};").unwrap();
+    }
+    rtf.push_str("}\n\\f0\\fs20 ");
+    rtf.push_str(&body);
+    rtf.push('}');
+    rtf
+}
+
+fn escape_rtf(rtf: &mut String, text: &str) {
+    for c in text.chars() {
+        match c {
+            '\\' | '{' | '}' => {
+                rtf.push('\\');
+                rtf.push(c);
+            }
+            '\n' => rtf.push_str("\\line\n"),
+            '\t' => rtf.push_str("\\tab "),
+            '\x20'..='\x7e' => rtf.push(c),
+            c if c.is_control() => {}
+            // Everything else as signed UTF-16 code units, with '?' for readers that don't support
+            // Unicode
+            c => {
+                for unit in c.encode_utf16(&mut [0; 2]) {
+                    #[allow(clippy::cast_possible_wrap)]
+                    write!(rtf, "\\u{}?", *unit as i16).unwrap();
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn span(text: &str, style: Style) -> Span {
+        Span {
+            text: text.to_string(),
+            style,
+        }
+    }
+
+    fn fg(color: Color) -> Style {
+        Style {
+            foreground: Some(color),
+            ..Style::default()
+        }
+    }
+
+    #[test]
+    fn parses_16_colors_and_attributes() {
+        let spans =
+            parse("test foo ... \x1b[32mok\x1b[0m\n\x1b[1;91merror\x1b[22m: \x1b[4mx\x1b[m");
+
+        assert_eq!(
+            spans,
+            [
+                span("test foo ... ", Style::default()),
+                span("ok", fg(Color::Indexed(2))),
+                span("\n", Style::default()),
+                span(
+                    "error",
+                    Style {
+                        bold: true,
+                        ..fg(Color::Indexed(9))
+                    }
+                ),
+                span(": ", fg(Color::Indexed(9))),
+                span(
+                    "x",
+                    Style {
+                        underline: true,
+                        ..fg(Color::Indexed(9))
+                    }
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_256_and_truecolor() {
+        let spans = parse(
+            "\x1b[38;5;208ma\x1b[48;2;10;20;30mb\x1b[0;38:2::1:2:3mc\x1b[38:5:33;48:2:4:5:6md",
+        );
+
+        assert_eq!(
+            spans,
+            [
+                span("a", fg(Color::Indexed(208))),
+                span(
+                    "b",
+                    Style {
+                        background: Some(Color::Rgb(10, 20, 30)),
+                        ..fg(Color::Indexed(208))
+                    }
+                ),
+                span("c", fg(Color::Rgb(1, 2, 3))),
+                span(
+                    "d",
+                    Style {
+                        background: Some(Color::Rgb(4, 5, 6)),
+                        ..fg(Color::Indexed(33))
+                    }
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn strips_other_escape_sequences() {
+        let input = "\x1b[2K\x1b[?25lCompiling\x1b[1G \x1b]8;;https://example.com\x1b\\link\x1b]8;;\x07\x1b(B!";
+        let spans = parse(input);
+
+        assert_eq!(to_plain(&spans), "Compiling link!");
+        assert_eq!(spans.len(), 1);
+    }
+
+    #[test]
+    fn merges_spans_with_the_same_style() {
+        let spans = parse("\x1b[31ma\x1b[32m\x1b[31mb\x1b[0m\x1b[0m");
+
+        assert_eq!(spans, [span("ab", fg(Color::Indexed(1)))]);
+    }
+
+    #[test]
+    fn converts_indexed_colors_to_rgb() {
+        assert_eq!(Color::Indexed(1).to_rgb(), (0xc5, 0x0f, 0x1f));
+        assert_eq!(Color::Indexed(16).to_rgb(), (0, 0, 0));
+        assert_eq!(Color::Indexed(208).to_rgb(), (255, 135, 0));
+        assert_eq!(Color::Indexed(231).to_rgb(), (255, 255, 255));
+        assert_eq!(Color::Indexed(232).to_rgb(), (8, 8, 8));
+        assert_eq!(Color::Indexed(255).to_rgb(), (238, 238, 238));
+    }
+
+    #[test]
+    fn converts_to_html() {
+        let html = to_html(&parse(
+            "\x1b[1;32m+\x1b[m if a < b && c\r\n\x1b[48;5;196;4m\"x\"\x1b[m",
+        ));
+
+        assert_eq!(
+            html,
+            "<pre style=\"font-family: Consolas, monospace\">\
+            <span style=\"color: #13a10e; font-weight: bold\">+</span> if a &lt; b &amp;&amp; c\n\
+            <span style=\"background-color: #ff0000; text-decoration: underline\">&quot;x&quot;</span>\
+            </pre>"
+        );
+    }
+
+    #[test]
+    fn reverses_colors_and_strikes_through() {
+        // git diff's whitespace errors, then a red foreground on a blue background, reversed
+        let spans = parse("a\x1b[7m \x1b[27m\x1b[9mb\x1b[31;44;7mc\x1b[m");
+        let html = to_html(&spans);
+
+        assert_eq!(
+            html,
+            "<pre style=\"font-family: Consolas, monospace\">a\
+            <span style=\"color: #ffffff; background-color: #000000\"> </span>\
+            <span style=\"text-decoration: line-through\">b</span>\
+            <span style=\"color: #0037da; background-color: #c50f1f; \
+            text-decoration: line-through\">c</span></pre>"
+        );
+
+        assert!(
+            to_rtf(&spans).ends_with("{\\cf1\\chcbpat2  }{\\strike b}{\\cf3\\chcbpat4\\strike c}}")
+        );
+    }
+
+    #[test]
+    fn converts_to_rtf() {
+        let rtf = to_rtf(&parse(
+            "\x1b[31m{a}\x1b[m\tb\\\n\x1b[1;31;42m鏡音\x1b[3m🍊\x1b[m",
+        ));
+
+        assert_eq!(
+            rtf,
+            "{\\rtf1\\ansi\\deff0{\\fonttbl{\\f0\\fmodern Consolas;}}\
+            {\\colortbl ;\\red197\\green15\\blue31;\\red19\\green161\\blue14;}\n\
+            \\f0\\fs20 {\\cf1 \\{a\\}}\\tab b\\\\\\line\n\
+            {\\cf1\\chcbpat2\\b \\u-27679?\\u-26637?}\
+            {\\cf1\\chcbpat2\\b\\i \\u-10180?\\u-8374?}}"
+        );
+    }
+}
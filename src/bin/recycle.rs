@@ -3,6 +3,7 @@
 
 use clap::Parser;
 use std::fs;
+use wsl_tools::recycle_result::{ErrorCode, Outcome, RecycleResult};
 
 #[derive(Parser)]
 #[command(
@@ -35,7 +36,9 @@ struct Args {
     // IMPORTANT! Any new args added here MUST be replicated in the Linux main() below. (Clap
     // doesn't give us a way to stringify args.)
     //
-    #[arg(required(true), help = if cfg!(unix) {
+    #[cfg_attr(unix, arg(required_unless_present_any(["undo", "files_from"])))]
+    #[cfg_attr(windows, arg(required_unless_present_any(["files_from", "print_sid"])))]
+    #[arg(help = if cfg!(unix) {
         "Files/directories to recycle, relative to the current directory. Linux paths are \
         automatically converted to Windows paths."
     } else {
@@ -43,6 +46,16 @@ struct Args {
     })]
     paths: Vec<String>,
 
+    #[arg(long, value_name = "FILE", help = "\
+        Also read paths from FILE (or stdin if -), one per line. All of the paths are recycled in \
+        a single operation, avoiding the batches that xargs would split them into.")]
+    files_from: Option<String>,
+
+    #[arg(short = '0', long, requires = "files_from", help = "\
+        Paths in --files-from are separated by NUL characters instead of newlines, as with find \
+        -print0.")]
+    null: bool,
+
     #[arg(short, long, help = "Ignore nonexistent files.")]
     force: bool,
 
@@ -61,6 +74,18 @@ struct Args {
     })]
     rm: bool,
 
+    #[arg(long, conflicts_with = "rm", help = if cfg!(unix) {
+        "Like --rm, but refuse anything the shell can't recycle instead of letting it delete it \
+        permanently. Slower if any items can't be recycled. Files in the WSL filesystem can't be \
+        recycled, so they are refused unless using the Linux trash. Items too big for the Recycle \
+        Bin are refused as with --rm."
+    } else {
+        "Like --rm, but refuse anything the shell can't recycle instead of letting it delete it \
+        permanently. Slower if any items can't be recycled. The shell can't detect items too big \
+        for the Recycle Bin, so a dialog will be shown for those."
+    })]
+    safe: bool,
+
     // Clap can't show aliases on the same line: https://github.com/clap-rs/clap/issues/5459
     #[arg(short, short_alias = 'R', long, help = "\
         Allow recycling and deleting directories when --rm is used. No effect without --rm (the \
@@ -70,6 +95,33 @@ struct Args {
     #[arg(short, long, help = "Show recycle progress in the terminal.")]
     verbose: bool,
 
+    #[arg(long, overrides_with = "no_connected_elements", help = "\
+        Let the shell also delete \"connected\" files, such as the foo_files directory saved \
+        alongside foo.html.")]
+    connected_elements: bool,
+
+    #[arg(long, help = "Only delete the given files/directories (default).")]
+    no_connected_elements: bool,
+
+    #[arg(long, help = "\
+        Don't add to Explorer's undo history. Implied by --rm and --safe.")]
+    no_explorer_undo: bool,
+
+    #[arg(long, help = "\
+        If an item requires admin to delete, show a UAC prompt right away instead of asking \
+        whether to continue as admin.")]
+    require_elevation: bool,
+
+    #[arg(long, help = "\
+        With --rm, show a dialog instead of deleting permanently if an item is too big for the \
+        Recycle Bin. Has no effect on drives without a Recycle Bin.")]
+    nuke_warning: bool,
+
+    #[arg(long, help = "\
+        Output the result for each item as a line of JSON on stdout instead of the usual \
+        messages.")]
+    json: bool,
+
     #[cfg(unix)]
     #[arg(long, overrides_with = "no_use_linux_trash", help = "\
         Use the Freedesktop.org trash can when recycling files in the WSL filesystem.")]
@@ -79,59 +131,173 @@ struct Args {
     #[arg(long, help = "\
         Delete files in the WSL filesystem permanently (with a dialog if not --rm).")]
     no_use_linux_trash: bool,
+
+    #[cfg(unix)]
+    #[arg(long, help = "\
+        When deleting directories in the WSL filesystem, skip any directories on a different file \
+        system.")]
+    one_file_system: bool,
+
+    #[cfg(unix)]
+    #[arg(long, help = "\
+        Allow removing paths matched by the protect rules in wsl-tools.ini.")]
+    no_protect: bool,
+
+    #[cfg(unix)]
+    #[arg(long, help = "\
+        With --rm or --safe, allow recycling items that are likely too big for the Recycle Bin, \
+        which the shell will silently delete permanently instead (or ask, with --safe). Without \
+        this, such items are refused (see max_recycle_size in wsl-tools.ini).")]
+    allow_nuke: bool,
+
+    #[cfg(unix)]
+    #[arg(long, overrides_with = "no_git_safe", help = "\
+        Before removing anything in a git work tree, list any modified or untracked files that \
+        would be lost and ask for confirmation (or pass --force). Also refuses to remove a .git \
+        directory except along with the whole repo. Can be enabled by default with git_safe in \
+        wsl-tools.ini.")]
+    git_safe: bool,
+
+    #[cfg(unix)]
+    #[arg(long, help = "Don't check for uncommitted changes, even if git_safe is set.")]
+    no_git_safe: bool,
+
+    #[cfg(unix)]
+    #[arg(long, help = "\
+        After recycling, look for each item in the Recycle Bin to check whether it was actually \
+        recycled or deleted permanently by the shell, and warn about the latter.")]
+    verify: bool,
+
+    #[cfg(unix)]
+    #[arg(long, value_name = "N", num_args = 0..=1, default_missing_value = "1",
+        conflicts_with_all = ["paths", "files_from"], help = "\
+        Restore the items removed by the last N invocations (default 1) from the Recycle Bin or \
+        Linux trash. Items deleted permanently cannot be restored.")]
+    undo: Option<usize>,
+
+    // Internal: --verify uses this to find the current user's folder in the Recycle Bin, which is
+    // named after their SID. (Not needed Linux-side, as only the exe runs it.)
+    #[cfg(windows)]
+    #[arg(long, hide = true)]
+    print_sid: bool,
 }
 
 #[cfg(windows)]
 fn main() {
-    use wsl_tools::recycle_bin::{
-        self, RECYCLE_DANGEROUSLY_IN_BACKGROUND, RECYCLE_IGNORE_NOT_FOUND, RecycleError,
-        RecycleOptions,
+    use wsl_tools::{
+        recycle_bin::{self, RecycleError, RecycleOptions},
+        recycle_report::ItemOutcome,
+        recycle_result::Backend,
     };
 
-    let args = Args::parse();
-
-    let mut options: RecycleOptions = 0;
+    let mut args = Args::parse();
 
-    if args.force {
-        options |= RECYCLE_IGNORE_NOT_FOUND;
+    if args.print_sid {
+        match recycle_bin::current_user_sid() {
+            Ok(sid) => println!("{sid}"),
+            Err(err) => {
+                eprintln!("recycle: {err}");
+                std::process::exit(1);
+            }
+        }
+        return;
     }
 
-    if args.rm {
-        options |= RECYCLE_DANGEROUSLY_IN_BACKGROUND;
+    read_files_from(&mut args);
+    let reporter = Reporter::new(&args);
+
+    let options = RecycleOptions::new()
+        .ignore_not_found(args.force)
+        .dangerously_in_background(args.rm)
+        .safe_background(args.safe)
+        .connected_elements(args.connected_elements)
+        .undo(!args.rm && !args.safe && !args.no_explorer_undo)
+        .require_elevation(args.require_elevation)
+        .nuke_warning(args.nuke_warning);
 
+    if args.rm {
         // Make sure we won't be deleting any directories if not --recursive
         if !args.recursive {
             for path in &args.paths {
                 // Don't follow symlinks
                 if fs::symlink_metadata(path).is_ok_and(|m| m.is_dir()) {
-                    eprintln!("recycle: Cannot remove \"{path}\": Is a directory.");
+                    reporter.report(&RecycleResult::failed(
+                        path.clone(),
+                        Some(path.clone()),
+                        Backend::WindowsBin,
+                        ErrorCode::IsDirectory,
+                        format!("Cannot remove \"{path}\": Is a directory."),
+                    ));
                     std::process::exit(1);
                 }
             }
         }
     }
 
+    let report = recycle_bin::recycle_with_report(&args.paths, options).unwrap_or_else(|err| {
+        match &err {
+            RecycleError::NotFound(path) | RecycleError::InvalidPath(path, _) => {
+                reporter.report(&RecycleResult::failed(
+                    path.clone(),
+                    Some(path.clone()),
+                    Backend::WindowsBin,
+                    (&err).into(),
+                    err.to_string(),
+                ));
+            }
+            _ => eprintln!("recycle: {err}"),
+        }
+
+        std::process::exit(1);
+    });
+
     let mut errored = false;
-    let result = recycle_bin::recycle_with_callback(&args.paths, options, |item, err| match err {
-        None => {
-            if args.verbose {
+    for item in report.items {
+        let windows_path = Some(item.absolute_path.to_string_lossy().into_owned());
+        let (error, path) = match item.outcome {
+            ItemOutcome::Recycled => {
                 // There's no way to know for sure if the item was actually recycled or deleted
                 // permanently (`dwflags` can lie), so our verbage here should reflect that.
-                println!("recycle: Removed \"{item}\"");
+                reporter.report(&RecycleResult::removed(
+                    item.path,
+                    windows_path,
+                    Backend::WindowsBin,
+                ));
+                continue;
             }
-        }
-        Some(e) => {
-            errored = true;
-            eprintln!("recycle: Failed to recycle \"{item}\": {e}");
-        }
-    });
+            ItemOutcome::Skipped => {
+                reporter.report(&RecycleResult::skipped(
+                    item.path,
+                    windows_path,
+                    Backend::WindowsBin,
+                ));
+                continue;
+            }
+            // Items the shell never got to after being canceled; covered by the message below
+            ItemOutcome::Failed(RecycleError::Canceled) if item.hresult.is_none() => continue,
+            ItemOutcome::Failed(err) => (err, item.path),
+            ItemOutcome::StillExists => (RecycleError::Unknown, item.path),
+        };
+
+        errored = true;
+        reporter.report(&RecycleResult::failed(
+            path.clone(),
+            windows_path,
+            Backend::WindowsBin,
+            (&error).into(),
+            format!("Failed to recycle \"{path}\": {error}"),
+        ));
+    }
 
-    if let Err(err) = result {
+    if report.aborted {
         // No need to print "The operation was canceled." if there were specific errors.
-        if !matches!(err, RecycleError::Canceled) || !errored {
-            eprintln!("recycle: {err}");
+        if !errored {
+            eprintln!("recycle: {}", RecycleError::Canceled);
         }
+        std::process::exit(1);
+    }
 
+    if errored {
         std::process::exit(1);
     }
 }
@@ -140,10 +306,20 @@ fn main() {
 #[allow(clippy::too_many_lines)] // Willie hears ya, Willie don't care
 fn main() {
     use clap::{CommandFactory, FromArgMatches};
-    use std::{
-        cell::LazyCell, fs::Metadata, io::ErrorKind, os::linux::fs::MetadataExt, process::Stdio,
+    use std::{collections::HashMap, io::IsTerminal};
+    use wsl_tools::{
+        config::get_config,
+        exe_command,
+        freedesktop_trash::{Trash, TrashFallback},
+        interop,
+        recycle_backend::{
+            self, FreedesktopTrash, InteropFallback, PathInfo, PermanentDelete, RecycleBackend,
+            RouteOptions, WindowsRecycleBin,
+        },
+        recycle_protect::{self, Protection},
+        recycle_result::Backend,
+        remove_tree::{REMOVE_IGNORE_NOT_FOUND, REMOVE_ONE_FILE_SYSTEM, REMOVE_RECURSIVE},
     };
-    use wsl_tools::{config::get_config, exe_command, exe_exec, wslpath};
 
     let config = get_config();
 
@@ -165,8 +341,15 @@ fn main() {
             },
         )
         .get_matches();
-    let args = Args::from_arg_matches(&args_matches).unwrap(); // Won't panic
+    let mut args = Args::from_arg_matches(&args_matches).unwrap(); // Won't panic
+
+    if let Some(count) = args.undo {
+        undo(count);
+    }
+
+    read_files_from(&mut args);
 
+    let reporter = Reporter::new(&args);
     let mut cmd = exe_command!();
 
     if args.force {
@@ -177,6 +360,10 @@ fn main() {
         cmd.arg("--rm");
     }
 
+    if args.safe {
+        cmd.arg("--safe");
+    }
+
     if args.recursive {
         cmd.arg("--recursive");
     }
@@ -185,134 +372,636 @@ fn main() {
         cmd.arg("--verbose");
     }
 
-    let root_dev_inode: LazyCell<Metadata> = LazyCell::new(|| fs::symlink_metadata("/").unwrap());
-    let mut any_to_recycle = false;
-    let mut linux_paths: Vec<(String, Metadata)> = Vec::new();
+    if args.connected_elements {
+        cmd.arg("--connected-elements");
+    }
+
+    if args.no_explorer_undo {
+        cmd.arg("--no-explorer-undo");
+    }
+
+    if args.require_elevation {
+        cmd.arg("--require-elevation");
+    }
+
+    if args.nuke_warning {
+        cmd.arg("--nuke-warning");
+    }
+
+    let mut options = RouteOptions {
+        force: args.force,
+        rm: args.rm,
+        recursive: args.recursive,
+        use_linux_trash: args.use_linux_trash
+            || (!args.no_use_linux_trash && config.use_linux_trash),
+        interop_unavailable: false,
+    };
 
-    let use_linux_trash =
-        args.use_linux_trash || (!args.no_use_linux_trash && config.use_linux_trash);
+    // Check that recycle.exe can be run before doing anything, rather than failing after the
+    // Linux-side items have been removed. If not, the fallback backend gets the Windows-side items
+    // (and they're checked here instead of by the exe).
+    let interop_available = interop::is_available(&cmd);
+    options.interop_unavailable =
+        !interop_available && config.interop_fallback != InteropFallback::Abort;
 
-    cmd.arg("--");
+    let protect_rules = if args.no_protect {
+        Vec::new()
+    } else {
+        let home = std::env::var("HOME").ok();
+        let (rules, skipped) = recycle_protect::parse_rules(
+            config.protect.iter().map(String::as_str),
+            home.as_deref(),
+        );
+        for rule in skipped {
+            eprintln!("recycle: Ignoring protect rule \"{rule}\" as $HOME isn't set.");
+        }
+        rules
+    };
 
     // Convert WSL paths to Windows paths. The `symlink_to_windows` function runs wslpath on the
     // dirname and then appends the basename, as wslpath resolves symlinks when converting from
     // WSL to Windows paths (this is hardcoded in the source and unfortunately can't be
     // overridden: https://github.com/microsoft/WSL/blob/2.7.0/src/linux/init/wslpath.cpp#L428).
-    for path in args.paths {
-        match wslpath::symlink_to_windows(&path) {
-            Ok(x) if (args.rm || use_linux_trash) && x.starts_with(r"\\wsl.localhost\") => {
-                // For paths in the WSL filesystem, we can unlink them here. If --rm wasn't given
-                // and we're not using the Linux trash, we'll skip this so that the shell can
-                // display a dialog.
-                //
-                // Note that we use symlink_metadata (lstat) here instead of metadata (stat) to be
-                // consistent with `rm` and not follow symlinks (even if --recursive).
-                let stat = match fs::symlink_metadata(&path) {
-                    Ok(m) => m,
-                    Err(err) if err.kind() == ErrorKind::NotFound => {
-                        if args.force {
-                            continue;
-                        }
-                        eprintln!(
-                            "recycle: Failed to delete \"{path}\": No such file or directory."
-                        );
-                        std::process::exit(1);
-                    }
-                    Err(err) => {
-                        eprintln!("recycle: Failed to stat \"{path}\": {err}");
-                        std::process::exit(1);
-                    }
-                };
-
-                // https://github.com/coreutils/coreutils/blob/master/src/rm.c
-                if stat.st_dev() == root_dev_inode.st_dev()
-                    && stat.st_ino() == root_dev_inode.st_ino()
-                {
-                    if path == "/" {
-                        eprintln!("recycle: Refusing to delete \"/\".");
-                    } else {
-                        eprintln!("recycle: Refusing to delete \"{path}\" (same as \"/\").");
-                    }
-                    std::process::exit(1);
+    let paths: Vec<PathInfo> = args.paths.into_iter().map(PathInfo::lookup).collect();
+
+    for info in &paths {
+        let windows_path = info.windows_path.as_deref().ok();
+        if let Some(protection) =
+            recycle_protect::check(&protect_rules, &resolve_path(&info.path), windows_path)
+        {
+            let reason = match protection {
+                Protection::Matches(rule) => format!("Protected by rule \"{rule}\""),
+                Protection::Contains(rule) => {
+                    format!("May contain paths protected by rule \"{rule}\"")
                 }
+            };
+            reporter.report(&RecycleResult::failed(
+                info.path.clone(),
+                windows_path.map(ToOwned::to_owned),
+                if options.use_linux_trash {
+                    options.linux_backend()
+                } else {
+                    Backend::WindowsBin
+                },
+                ErrorCode::Protected,
+                format!(
+                    "Refusing to remove \"{}\": {reason} (use --no-protect to override).",
+                    info.path
+                ),
+            ));
+            reporter.exit(1);
+        }
+    }
 
-                if args.rm && !args.recursive && stat.is_dir() {
-                    eprintln!("recycle: Cannot remove \"{path}\": Is a directory.");
-                    std::process::exit(1);
-                }
+    if (args.git_safe || config.git_safe) && !args.no_git_safe {
+        check_git_safety(
+            &paths,
+            args.force,
+            if options.use_linux_trash {
+                options.linux_backend()
+            } else {
+                Backend::WindowsBin
+            },
+            &reporter,
+        );
+    }
 
-                // Queueing so that directory & exists checks happen before anything gets removed,
-                // matching the Windows-side behavior
-                linux_paths.push((path, stat));
-            }
-            Ok(x) => {
-                cmd.arg(x);
-                any_to_recycle = true;
-            }
-            Err(err) => {
-                eprintln!("recycle: Failed to execute wslpath on \"{path}\": {err}");
-                std::process::exit(1);
+    let routes = recycle_backend::route(paths, &options, &mut |x| reporter.report(&x))
+        .unwrap_or_else(|result| {
+            reporter.report(&result);
+            reporter.exit(1);
+        });
+
+    if !interop_available && !routes.windows.is_empty() {
+        match config.interop_fallback {
+            InteropFallback::Abort => {
+                eprintln!(
+                    "recycle: Windows interop is unavailable; nothing was removed (see \
+                    interop_fallback in wsl-tools.ini)."
+                );
+                reporter.exit(127);
             }
+            InteropFallback::MountTrash | InteropFallback::HomeTrash => eprintln!(
+                "recycle: Windows interop is unavailable; moving items to the Linux trash instead."
+            ),
         }
     }
 
-    for (path, stat) in linux_paths {
-        if use_linux_trash {
-            // This is slightly inefficient compared to delete_all, but unfortunately the trash
-            // crate doesn't give us callbacks or a way to ignore not found errors (if -f)
-            match trash::delete(&path) {
-                Ok(()) => {
-                    if args.verbose {
-                        // Not saying "moved to trash" to be consistent with recycling (see above)
-                        println!("recycle: Removed \"{path}\"");
-                    }
-                }
-                Err(trash::Error::FileSystem { path: _, source })
-                    if source.kind() == ErrorKind::NotFound =>
-                {
-                    if args.force {
-                        continue;
-                    }
-                    eprintln!("recycle: Failed to delete \"{path}\": No such file or directory.");
-                    std::process::exit(1);
-                }
-                Err(err) => {
-                    eprintln!("recycle: Failed to move \"{path}\" to trash: {err:?}");
-                    std::process::exit(1);
-                }
+    let show_progress = !args.json && std::io::stderr().is_terminal();
+
+    // The Recycle Bin's capacity doesn't matter if we're not going to use it
+    if (args.rm || args.safe) && !args.allow_nuke && interop_available {
+        // Drive letter -> (estimated Recycle Bin capacity, total size of the items queued so far)
+        let mut bin_usage: HashMap<char, (u64, u64)> = HashMap::new();
+
+        for item in &routes.windows {
+            if (args.safe || args.recursive || !item.is_dir)
+                && let Err(message) = check_bin_capacity(
+                    &item.path,
+                    &item.windows_path,
+                    config.max_recycle_size,
+                    &mut bin_usage,
+                )
+            {
+                reporter.report(&RecycleResult::failed(
+                    item.path.clone(),
+                    Some(item.windows_path.clone()),
+                    Backend::WindowsBin,
+                    ErrorCode::TooLarge,
+                    message,
+                ));
+                reporter.exit(1);
             }
+        }
+    }
+
+    let linux_backend = || -> std::io::Result<Box<dyn RecycleBackend>> {
+        if options.use_linux_trash {
+            Ok(Box::new(FreedesktopTrash::new(
+                Trash::new(config.linux_trash_fallback)?,
+                args.force,
+                show_progress,
+            )))
         } else {
-            let result = if stat.is_dir() {
-                fs::remove_dir_all(&path)
+            let mut remove_options = REMOVE_RECURSIVE; // Directories were already checked for above
+            if args.force {
+                remove_options |= REMOVE_IGNORE_NOT_FOUND;
+            }
+            if args.one_file_system {
+                remove_options |= REMOVE_ONE_FILE_SYSTEM;
+            }
+            Ok(Box::new(PermanentDelete::new(remove_options)))
+        }
+    };
+
+    let mut windows_backend: Box<dyn RecycleBackend> = if options.interop_unavailable {
+        let trash = Trash::new(match config.interop_fallback {
+            // If the drive doesn't allow creating a trash can, leave the item alone rather than
+            // copying gigabytes into the home trash
+            InteropFallback::MountTrash => TrashFallback::Refuse,
+            _ => TrashFallback::Copy,
+        })
+        .unwrap_or_else(|err| {
+            eprintln!("recycle: Failed to locate the trash: {err}");
+            reporter.exit(1);
+        });
+
+        Box::new(FreedesktopTrash::new(
+            if config.interop_fallback == InteropFallback::HomeTrash {
+                trash.without_topdir()
             } else {
-                fs::remove_file(&path)
-            };
+                trash
+            },
+            args.force,
+            show_progress,
+        ))
+    } else {
+        Box::new(WindowsRecycleBin::new(cmd))
+    };
 
-            match result {
-                Ok(()) => {
-                    if args.verbose {
-                        println!("recycle: Removed \"{path}\"");
-                    }
-                }
-                Err(err) if err.kind() == ErrorKind::NotFound => {
-                    if args.force {
-                        continue;
-                    }
-                    eprintln!("recycle: Failed to delete \"{path}\": No such file or directory.");
-                    std::process::exit(1);
-                }
-                Err(err) => {
-                    eprintln!("recycle: Failed to delete \"{path}\": {err}");
-                    std::process::exit(1);
-                }
+    if interop_available {
+        reporter.snapshot_bins(&routes.windows);
+    }
+
+    let code = routes.run(linux_backend, windows_backend.as_mut(), &mut |x| {
+        reporter.report(&x);
+    });
+    reporter.exit(code);
+}
+
+/// Makes the path absolute and resolves symlinks in the dirname, for matching against the protect
+/// rules. The basename is left alone, same as `symlink_to_windows`.
+#[cfg(unix)]
+fn resolve_path(path: &str) -> String {
+    let Ok(absolute) = std::path::absolute(path) else {
+        return path.to_owned();
+    };
+
+    // Paths ending in `..` don't have a file_name(), so resolve the whole thing
+    let resolved = match (absolute.parent(), absolute.file_name()) {
+        (Some(parent), Some(name)) => fs::canonicalize(parent).map(|x| x.join(name)),
+        _ => fs::canonicalize(&absolute),
+    };
+
+    resolved.unwrap_or(absolute).to_string_lossy().into_owned()
+}
+
+/// With --git-safe, refuses to remove `.git` directories and lists any uncommitted files under the
+/// paths, asking for confirmation if interactive. Exits if the user doesn't confirm (or can't).
+#[cfg(unix)]
+fn check_git_safety(
+    paths: &[wsl_tools::recycle_backend::PathInfo],
+    force: bool,
+    backend: wsl_tools::recycle_result::Backend,
+    reporter: &Reporter,
+) {
+    use std::{
+        io::{IsTerminal, stdin},
+        path::Path,
+    };
+    use wsl_tools::recycle_git::{self, GitSafety};
+
+    // Only show so many, in case someone tries to recycle a repo with a node_modules not ignored
+    const MAX_LISTED: usize = 20;
+
+    let mut dirty = Vec::new();
+
+    for info in paths {
+        let fail = |error: ErrorCode, message: String| -> ! {
+            reporter.report(&RecycleResult::failed(
+                info.path.clone(),
+                info.windows_path.clone().ok(),
+                backend,
+                error,
+                message,
+            ));
+            reporter.exit(1);
+        };
+
+        match recycle_git::check(Path::new(&resolve_path(&info.path))) {
+            Ok(GitSafety::Safe) => {}
+            Ok(GitSafety::Dirty(files)) => dirty.push((info, files)),
+            Ok(GitSafety::GitDirectory) => fail(
+                ErrorCode::GitDirectory,
+                format!(
+                    "Refusing to remove \"{}\": It's part of a git repository's .git directory \
+                    (remove the whole repository instead).",
+                    info.path
+                ),
+            ),
+            Err(err) => fail(
+                ErrorCode::Io,
+                format!(
+                    "Failed to check \"{}\" for uncommitted changes: {err}",
+                    info.path
+                ),
+            ),
+        }
+    }
+
+    if dirty.is_empty() || force {
+        return;
+    }
+
+    let count: usize = dirty.iter().map(|(_, files)| files.len()).sum();
+    eprintln!("recycle: The following files have uncommitted changes:");
+    for file in dirty.iter().flat_map(|(_, files)| files).take(MAX_LISTED) {
+        eprintln!("  {} {}", file.status, file.path.display());
+    }
+    if count > MAX_LISTED {
+        eprintln!("  ...and {} more", count - MAX_LISTED);
+    }
+
+    if stdin().is_terminal() && std::io::stderr().is_terminal() {
+        eprint!("recycle: Remove anyway? [y/N] ");
+        let mut answer = String::new();
+        if stdin().read_line(&mut answer).is_ok()
+            && matches!(answer.trim().to_lowercase().as_str(), "y" | "yes")
+        {
+            return;
+        }
+    }
+
+    let (info, files) = &dirty[0];
+    reporter.report(&RecycleResult::failed(
+        info.path.clone(),
+        info.windows_path.clone().ok(),
+        backend,
+        ErrorCode::Uncommitted,
+        format!(
+            "Refusing to remove \"{}\": It contains {} modified or untracked file{} (use --force \
+            to remove anyway).",
+            info.path,
+            files.len(),
+            if files.len() == 1 { "" } else { "s" }
+        ),
+    ));
+    reporter.exit(1);
+}
+
+/// Walks an item about to be recycled with --rm or --safe and adds it to the total for its drive,
+/// returning an error message if either the item or the batch likely exceeds the Recycle Bin's
+/// capacity, in which case the shell would nuke it without telling us. See `recycle_size` for
+/// details.
+#[cfg(unix)]
+fn check_bin_capacity(
+    path: &str,
+    windows_path: &str,
+    max_recycle_size: wsl_tools::recycle_size::SizeLimit,
+    bin_usage: &mut std::collections::HashMap<char, (u64, u64)>,
+) -> Result<(), String> {
+    use std::path::Path;
+    use wsl_tools::{
+        recycle_size::{self, format_size},
+        wslpath,
+    };
+
+    // Network shares and the like don't have a Recycle Bin at all
+    let mut chars = windows_path.chars();
+    let (Some(drive), Some(':')) = (chars.next(), chars.next()) else {
+        return Ok(());
+    };
+    let drive = drive.to_ascii_uppercase();
+
+    let (limit, total) = bin_usage.entry(drive).or_insert_with(|| {
+        let limit = wslpath::to_wsl(format!("{drive}:\\"))
+            .ok()
+            .and_then(|root| recycle_size::volume_size(Path::new(&root)).ok())
+            .map_or(u64::MAX, |x| max_recycle_size.bytes(x));
+        (limit, 0)
+    });
+
+    if *limit == u64::MAX {
+        return Ok(());
+    }
+
+    let size = recycle_size::tree_size(Path::new(path), *limit - *total);
+    *total += size;
+
+    if size > *limit {
+        return Err(format!(
+            "Refusing to recycle \"{path}\": At {}, it likely won't fit in the Recycle Bin \
+            (estimated {}) and would be deleted permanently (use --allow-nuke to override).",
+            format_size(size),
+            format_size(*limit)
+        ));
+    }
+
+    if *total > *limit {
+        return Err(format!(
+            "Refusing to recycle \"{path}\": The items on {drive}: add up to more than {}, which \
+            likely won't fit in the Recycle Bin (estimated {}), so some would be deleted \
+            permanently (use --allow-nuke to override).",
+            format_size(*total),
+            format_size(*limit)
+        ));
+    }
+
+    Ok(())
+}
+
+#[cfg(unix)]
+fn undo(count: usize) -> ! {
+    use wsl_tools::recycle_journal;
+
+    let _lock = recycle_journal::lock().unwrap_or_else(|err| {
+        eprintln!("recycle: Failed to lock journal: {err}");
+        std::process::exit(1);
+    });
+
+    let mut entries = recycle_journal::load().unwrap_or_else(|err| {
+        eprintln!("recycle: Failed to read journal: {err}");
+        std::process::exit(1);
+    });
+
+    if entries.is_empty() {
+        eprintln!("recycle: Nothing to undo.");
+        std::process::exit(1);
+    }
+
+    let mut errored = false;
+
+    recycle_journal::undo(&mut entries, count, |item, result| match result {
+        Ok(()) => println!("recycle: Restored \"{}\"", item.path),
+        Err(err) => {
+            errored = true;
+            eprintln!("recycle: Failed to restore \"{}\": {err}", item.path);
+        }
+    });
+
+    if let Err(err) = recycle_journal::save(&entries) {
+        eprintln!("recycle: Failed to write journal: {err}");
+        errored = true;
+    }
+
+    std::process::exit(i32::from(errored));
+}
+
+/// Appends the paths from --files-from, if given, to `args.paths`.
+fn read_files_from(args: &mut Args) {
+    let Some(file) = &args.files_from else {
+        return;
+    };
+
+    match wsl_tools::path_list::read(file, args.null) {
+        Ok(paths) => args.paths.extend(paths),
+        Err(err) => {
+            eprintln!("recycle: Failed to read \"{file}\": {err}");
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Prints the result of each item, either as the usual messages or as JSON if --json. Linux-side,
+/// also keeps track of the removed items for the journal.
+struct Reporter {
+    json: bool,
+    verbose: bool,
+    #[cfg(unix)]
+    started: u64,
+    #[cfg(unix)]
+    removed: std::cell::RefCell<Vec<RecycleResult>>,
+    /// Recycle Bin items held back until they can be verified, with --verify.
+    #[cfg(unix)]
+    unverified: Option<std::cell::RefCell<Vec<RecycleResult>>>,
+    /// What was in the Recycle Bin before recycling, with --verify.
+    #[cfg(unix)]
+    bins: std::cell::RefCell<wsl_tools::recycle_bin_index::BinCache>,
+}
+
+impl Reporter {
+    fn new(args: &Args) -> Self {
+        Self {
+            json: args.json,
+            verbose: args.verbose,
+            #[cfg(unix)]
+            started: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+            #[cfg(unix)]
+            removed: std::cell::RefCell::default(),
+            #[cfg(unix)]
+            unverified: args.verify.then(std::cell::RefCell::default),
+            #[cfg(unix)]
+            bins: std::cell::RefCell::default(),
+        }
+    }
+
+    fn report(&self, result: &RecycleResult) {
+        #[cfg(unix)]
+        use wsl_tools::recycle_result::Verification;
+
+        #[cfg(unix)]
+        if result.outcome == Outcome::Removed {
+            if let Some(unverified) = &self.unverified
+                && result.backend == wsl_tools::recycle_result::Backend::WindowsBin
+                && result.verified.is_none()
+            {
+                unverified.borrow_mut().push(result.clone());
+                return;
             }
+
+            self.removed.borrow_mut().push(result.clone());
+        }
+
+        if self.json {
+            println!("{}", result.to_json());
+            return;
+        }
+
+        match result.outcome {
+            #[cfg(unix)]
+            Outcome::Removed if result.verified == Some(Verification::Deleted) => eprintln!(
+                "recycle: \"{}\" was not found in the Recycle Bin; the shell deleted it \
+                permanently.",
+                result.path
+            ),
+            #[cfg(unix)]
+            Outcome::Removed if result.verified == Some(Verification::Unknown) => eprintln!(
+                "recycle: Could not check whether \"{}\" was recycled: Failed to read the \
+                Recycle Bin.",
+                result.path
+            ),
+            Outcome::Removed if self.verbose => println!("recycle: Removed \"{}\"", result.path),
+            Outcome::Failed => eprintln!(
+                "recycle: {}",
+                result.message.as_deref().unwrap_or("Unknown error.")
+            ),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(unix)]
+impl Reporter {
+    /// Notes what's in the Recycle Bin before the items are recycled, with --verify, so that items
+    /// recycled by an earlier run aren't mistaken for these.
+    fn snapshot_bins(&self, items: &[wsl_tools::recycle_backend::RecycleItem]) {
+        use wsl_tools::recycle_bin_index::BinCache;
+
+        if self.unverified.is_none() || items.is_empty() {
+            return;
+        }
+
+        let mut bins = BinCache::new(current_user_sid());
+        for item in items {
+            bins.snapshot(&item.windows_path);
+        }
+
+        self.bins.replace(bins);
+    }
+
+    /// Looks for the held-back items in the Recycle Bin and reports them, with --verify.
+    fn verify(&self) {
+        let Some(unverified) = &self.unverified else {
+            return;
+        };
+
+        let mut bins = self.bins.take();
+        for mut result in unverified.take() {
+            let windows_path = result.windows_path.as_deref().unwrap_or_default();
+            result.verified = Some(bins.verify(windows_path));
+            self.report(&result);
         }
     }
 
-    if any_to_recycle {
-        // Redirect stdin to prevent the interop layer from consuming stdin & breaking read loops
-        cmd.stdin(Stdio::null());
+    /// Appends the removed items to the journal for --undo.
+    fn save_journal(&self) {
+        use std::{collections::HashMap, env};
+        use wsl_tools::recycle_journal::{self, JournalEntry, JournalItem};
 
-        exe_exec!(cmd);
+        let removed = self.removed.take();
+        if removed.is_empty() {
+            return;
+        }
+
+        let mut drive_roots = HashMap::new();
+        let mut items: Vec<JournalItem> = removed
+            .into_iter()
+            .map(|result| JournalItem {
+                path: result
+                    .windows_path
+                    .as_deref()
+                    .and_then(|x| wsl_path_from_windows(x, &mut drive_roots))
+                    .unwrap_or(result.path),
+                windows_path: result.windows_path,
+                backend: result.backend,
+                trash_path: result.trash_path,
+            })
+            .collect();
+
+        recycle_journal::locate_in_linux_trash(&mut items, self.started);
+
+        let entry = JournalEntry {
+            time: self.started,
+            cwd: env::current_dir()
+                .map(|x| x.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            args: env::args_os()
+                .skip(1)
+                .map(|x| x.to_string_lossy().into_owned())
+                .collect(),
+            items,
+        };
+
+        if let Err(err) = recycle_journal::append(&entry) {
+            eprintln!("recycle: Failed to write journal: {err}");
+        }
     }
+
+    /// Verifies any Recycle Bin items, saves the journal, and exits.
+    fn exit(&self, code: i32) -> ! {
+        self.verify();
+        self.save_journal();
+        std::process::exit(code);
+    }
+}
+
+/// Asks recycle.exe for the current user's SID, for --verify.
+#[cfg(unix)]
+fn current_user_sid() -> Option<String> {
+    let output = wsl_tools::exe_command!()
+        .arg("--print-sid")
+        .stdin(std::process::Stdio::null())
+        .output()
+        .ok()?;
+
+    let sid = String::from_utf8_lossy(&output.stdout).trim().to_owned();
+    (output.status.success() && !sid.is_empty()).then_some(sid)
+}
+
+/// Translates an item's Windows path back to an absolute WSL path for the journal. Unlike
+/// `wslpath::to_wsl`, this works after the item's parent directory has been removed, and only runs
+/// wslpath once per drive. (The Windows paths themselves came from `symlink_to_windows`, so they
+/// have the dirname's symlinks resolved already, same as the trash's record of the original path.)
+#[cfg(unix)]
+fn wsl_path_from_windows(
+    windows_path: &str,
+    drive_roots: &mut std::collections::HashMap<char, String>,
+) -> Option<String> {
+    use std::collections::hash_map::Entry;
+    use wsl_tools::wslpath;
+
+    if let Some(unc) = windows_path
+        .strip_prefix(r"\\wsl.localhost\")
+        .or_else(|| windows_path.strip_prefix(r"\\wsl$\"))
+    {
+        let (_distro, rest) = unc.split_once('\\')?;
+        return Some(format!("/{}", rest.replace('\\', "/")));
+    }
+
+    let (drive, rest) = windows_path.split_once(":\\")?;
+    let drive = drive.chars().next().filter(|_| drive.len() == 1)?;
+
+    let root = match drive_roots.entry(drive.to_ascii_lowercase()) {
+        Entry::Occupied(x) => x.into_mut(),
+        Entry::Vacant(x) => x.insert(wslpath::to_wsl(format!("{drive}:\\")).ok()?),
+    };
+
+    Some(format!(
+        "{}/{}",
+        root.trim_end_matches('/'),
+        rest.replace('\\', "/")
+    ))
 }
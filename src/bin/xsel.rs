@@ -3,10 +3,11 @@
 //
 // Based on xsel 1.2.1 by Conrad Parker
 
-use clap::Parser;
-use std::io::IsTerminal;
+use clap::{Parser, ValueEnum};
+use std::{io::IsTerminal, time::Duration};
+use wsl_tools::{clipboard_format, duration, text_encoding::Encoding};
 
-#[derive(Parser)]
+#[derive(Parser, Clone)]
 #[command(
     name = "xsel",
     bin_name = "xsel",
@@ -24,16 +25,80 @@ before being replaced by the contents of standard input.
 
 \x1b[1;4mInput options\x1b[m
   -a, --append            Append standard input to the clipboard
-  -f, --follow            <Not supported>
-  -z, --zeroflush         <Not supported>
+  -f, --follow            Append to the clipboard as standard input grows.
+                          Implies -i.
+  -z, --zeroflush         Overwrite the clipboard when a null byte ('\\0') is
+                          received. Implies -f.
   -i, --input             Read standard input into the clipboard
 
+  --ansi                  <Windows-only addition> Convert ANSI colors in
+                          standard input to rich text (HTML and RTF) so that
+                          they're kept when pasting into Word, Teams, etc.
+                          Programs that only take plain text get it with the
+                          escape sequences removed.
+
+  --sensitive             <Windows-only addition> Mark the input as sensitive
+                          (e.g. a password) so that it isn't saved to
+                          clipboard history or synced to other devices, and
+                          clipboard managers ignore it.
+
+  --clear-after DURATION  <Windows-only addition> Clear the clipboard after
+                          the given time (e.g. 45, 30s, 1m30s) unless
+                          something else has been copied since, like pass -c.
+                          With --follow, the time starts once input ends.
+
+  --encoding ENCODING     <Windows-only addition> Read standard input in the
+                          given encoding: auto (default; UTF-8, or UTF-16 if
+                          there's a BOM), utf-8, utf-16le, utf-16be, ansi or
+                          oem (the system code pages), or a Windows code page
+                          such as 932, cp1252, or shift_jis.
+
+  --lossy                 <Windows-only addition> Replace invalid input, or
+                          output that can't be represented in the output
+                          encoding, rather than failing.
+
 \x1b[1;4mOutput options\x1b[m
   -o, --output            Write the clipboard to standard output
 
   --keep-crlf             <Windows-only addition> By default, CRLF is replaced
                           with LF when pasting. Pass this option to disable.
 
+  --document              <Windows-only addition> With -t text/html, output
+                          the whole HTML document rather than just the part
+                          that was copied.
+
+  --output-encoding ENCODING
+                          <Windows-only addition> Write the clipboard to
+                          standard output in the given encoding (default
+                          utf-8), e.g. for legacy Windows programs. Same
+                          choices as --encoding, except auto.
+
+  --watch                 <Windows-only addition> Output the clipboard each
+                          time it changes, until interrupted. Content marked
+                          as sensitive (e.g. by password managers) is skipped.
+                          The clipboard is checked every 100 ms, so copies
+                          made faster than that may output only the last.
+
+  -0, --null              With --watch, end each copy with a null byte rather
+                          than a newline, for text that contains newlines.
+
+  --timestamps            With --watch, prefix each copy with the time (UTC,
+                          RFC 3339) and a tab.
+
+  --json                  With --watch, output each copy as a JSON object on
+                          its own line, with \"time\" and \"text\" properties.
+
+\x1b[1;4mFormat options\x1b[m
+  -t, --target TYPE       <Windows-only addition> Copy or paste the given
+                          format instead of text, like xclip. Supported:
+                          text/plain (default), text/html, image/png,
+                          text/uri-list (files, as copied in Explorer; takes
+                          WSL/Windows paths or file:// URIs, outputs paths)
+
+  --list-targets          <Windows-only addition> List the formats on the
+                          clipboard by MIME type where there is one, or else
+                          by name. Same as -t TARGETS.
+
 \x1b[1;4mAction options\x1b[m
   -c, --clear             Clear the clipboard
   -d, --delete            <Not supported>
@@ -43,14 +108,20 @@ before being replaced by the contents of standard input.
   -s, --secondary         on Windows, but since some Linux clipboard managers
   -b, --clipboard         sync the selection and clipboard buffers, we pretend
                           that's the case and disregard the chosen selection.
+                          Set separate_selections = yes in wsl-tools.ini to
+                          keep PRIMARY and SECONDARY (text only) separately
+                          in $XDG_RUNTIME_DIR instead.
 
-  -k, --keep              <No-op>
-  -x, --exchange          <No-op>
+  -k, --keep              With separate_selections, do nothing, as the
+                          selections already persist. Otherwise <No-op>.
+  -x, --exchange          With separate_selections, exchange PRIMARY (or with
+                          -s, SECONDARY) with the clipboard. Otherwise
+                          <No-op>.
 
 \x1b[1;4mX options\x1b[m
   --display               <Not supported>
   -m, --name              <Not supported>
-  -t, --selectionTimeout  <Not supported>
+  --selectionTimeout      <Not supported>
 
 \x1b[1;4mMiscellaneous options\x1b[m
   --trim                  Remove newline from end of input / output
@@ -72,23 +143,63 @@ struct Args {
     #[arg(short, long)]
     append: bool,
     #[arg(short, long)]
+    follow: bool,
+    #[arg(short, long)]
+    zeroflush: bool,
+    #[arg(short, long)]
     input: bool,
+    #[arg(long, conflicts_with = "target")]
+    ansi: bool,
+    #[arg(long)]
+    sensitive: bool,
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration)]
+    clear_after: Option<Duration>,
+    #[arg(long, value_name = "ENCODING", value_parser = parse_encoding, default_value = "auto")]
+    encoding: Encoding,
+    #[arg(long)]
+    lossy: bool,
     #[arg(short, long)]
     output: bool,
     #[arg(long)]
     keep_crlf: bool,
+    #[arg(long)]
+    document: bool,
+    #[arg(
+        long,
+        value_name = "ENCODING",
+        value_parser = parse_output_encoding,
+        default_value = "utf-8"
+    )]
+    output_encoding: Encoding,
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "input", "append", "follow", "zeroflush", "clear", "target", "list_targets"
+        ]
+    )]
+    watch: bool,
+    #[arg(short = '0', long, requires = "watch")]
+    null: bool,
+    #[arg(long, requires = "watch")]
+    timestamps: bool,
+    #[arg(long, requires = "watch", conflicts_with = "null")]
+    json: bool,
+    #[arg(short, long, value_enum, default_value_t)]
+    target: Target,
+    #[arg(long)]
+    list_targets: bool,
     #[arg(short, long)]
     clear: bool,
     #[arg(short, long)]
-    primary: bool, // Ignored
+    primary: bool, // Same as the clipboard unless separate_selections
     #[arg(short, long)]
-    secondary: bool, // Ignored
+    secondary: bool, // Same as the clipboard unless separate_selections
     #[arg(short = 'b', long)]
-    clipboard: bool, // Ignored
+    clipboard: bool, // Only matters with separate_selections
     #[arg(short, long)]
-    keep: bool, // No-op
+    keep: bool, // No-op unless separate_selections
     #[arg(short = 'x', long)]
-    exchange: bool, // No-op
+    exchange: bool, // No-op unless separate_selections
     #[arg(long)]
     trim: bool,
     #[arg(short, long)]
@@ -103,15 +214,90 @@ struct Args {
     stdin_is_tty: Option<bool>,
     #[arg(long)]
     stdout_is_tty: Option<bool>,
+
+    // --clear-after starts another xsel.exe in the background with this flag, passing the
+    // clipboard's sequence number after setting it, which then waits and clears the clipboard only
+    // if the number hasn't changed (i.e. nothing else has been copied in the meantime).
+    #[arg(long)]
+    clear_if_unchanged: Option<u32>,
+}
+
+impl Args {
+    /// Parses the args, treating --list-targets as -t TARGETS, and applying what --zeroflush and
+    /// --follow imply.
+    fn parse_args() -> Self {
+        let mut args = Self::parse();
+        if args.list_targets {
+            args.target = Target::Targets;
+        }
+        args.follow |= args.zeroflush;
+        args.input |= args.follow;
+        args
+    }
+
+    /// Whether to set the clipboard from stdin, based on options and pipes. Listing the targets
+    /// and watching only ever output.
+    fn do_input(&self, stdin_is_tty: bool) -> bool {
+        self.target != Target::Targets
+            && !self.watch
+            && (self.append || self.input || (!self.output && !stdin_is_tty))
+    }
+
+    /// Whether to write the clipboard to stdout, based on options and pipes.
+    fn do_output(&self, stdin_is_tty: bool, stdout_is_tty: bool) -> bool {
+        self.target == Target::Targets
+            || self.output
+            || (!self.append && !self.input && !self.clear && (!stdout_is_tty || stdin_is_tty))
+    }
+}
+
+fn parse_duration(value: &str) -> Result<Duration, String> {
+    duration::parse(value).ok_or_else(|| "expected a duration like 45, 30s, or 1m30s".to_string())
+}
+
+fn parse_encoding(value: &str) -> Result<Encoding, String> {
+    Encoding::parse(value)
+        .ok_or_else(|| "expected e.g. utf-8, utf-16le, ansi, or cp1252".to_string())
+}
+
+fn parse_output_encoding(value: &str) -> Result<Encoding, String> {
+    match parse_encoding(value)? {
+        Encoding::Auto => Err("auto only applies to input".to_string()),
+        encoding => Ok(encoding),
+    }
+}
+
+/// Clipboard formats, named after their X11 targets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+enum Target {
+    #[default]
+    #[value(name = clipboard_format::TEXT_PLAIN, aliases = ["UTF8_STRING", "STRING", "TEXT"])]
+    Text,
+    #[value(name = clipboard_format::TEXT_HTML)]
+    Html,
+    #[value(name = clipboard_format::IMAGE_PNG)]
+    Png,
+    #[value(name = clipboard_format::TEXT_URI_LIST)]
+    Files,
+    #[value(name = clipboard_format::TARGETS)]
+    Targets,
 }
 
 #[cfg(windows)]
 fn main() {
-    use anyhow::Result;
+    use anyhow::{Context, Result, bail};
     use std::io::Read;
-    use wsl_tools::clipboard;
+    use wsl_tools::{clipboard, text_encoding};
 
-    let args = Args::parse();
+    let args = Args::parse_args();
+
+    if let Some(sequence_number) = args.clear_if_unchanged {
+        std::thread::sleep(args.clear_after.unwrap_or_default());
+        if clipboard::sequence_number() == sequence_number {
+            let _ = clipboard::clear();
+        }
+        return;
+    }
 
     let stdin_is_tty = args
         .stdin_is_tty
@@ -121,9 +307,8 @@ fn main() {
         .unwrap_or_else(|| std::io::stdout().is_terminal());
 
     // Determine input/output behavior based on options and pipes
-    let do_input = args.append || args.input || (!args.output && !stdin_is_tty);
-    let do_output = args.output
-        || (!args.append && !args.input && !args.clear && (!stdout_is_tty || stdin_is_tty));
+    let do_input = args.do_input(stdin_is_tty);
+    let do_output = args.do_output(stdin_is_tty, stdout_is_tty);
 
     if args.keep || args.exchange {
         // No-op
@@ -131,8 +316,34 @@ fn main() {
     }
 
     let result = (|| -> Result<()> {
+        if args.watch {
+            return watch(&args);
+        }
+
+        match args.target {
+            Target::Png | Target::Files if args.follow => bail!("--follow only works with text."),
+            Target::Png | Target::Files
+                if args.encoding != Encoding::Auto || args.output_encoding != Encoding::Utf8 =>
+            {
+                bail!("--encoding and --output-encoding only apply to text.");
+            }
+            Target::Png => return copy_or_paste_png(&args, do_input, do_output),
+            Target::Files => return copy_or_paste_files(&args, do_input, do_output),
+            Target::Targets => {
+                for target in clipboard_format::list_targets(&clipboard::formats()?) {
+                    println!("{target}");
+                }
+                return Ok(());
+            }
+            Target::Text | Target::Html => {}
+        }
+
         let old_sel = if do_output {
-            get_clipboard(&args)?.inspect(|x| print!("{x}"))
+            let old_sel = get_clipboard(&args)?;
+            if let Some(text) = &old_sel {
+                write_text(&args, text)?;
+            }
+            old_sel
         } else {
             None
         };
@@ -148,15 +359,20 @@ fn main() {
                 get_clipboard(&args)?.unwrap_or_default()
             };
 
-            std::io::stdin().read_to_string(&mut new_sel)?;
-
-            let text = if args.trim {
-                new_sel.trim_end_matches(['\r', '\n'])
+            if args.follow {
+                if !matches!(args.encoding, Encoding::Auto | Encoding::Utf8) {
+                    bail!("--follow only supports UTF-8 input.");
+                }
+                follow_stdin(&args, new_sel)?;
             } else {
-                new_sel.as_ref()
-            };
-
-            clipboard::set_text(text)?;
+                let mut input = Vec::new();
+                std::io::stdin().read_to_end(&mut input)?;
+                new_sel.push_str(
+                    &text_encoding::decode(&input, args.encoding, args.lossy)
+                        .context("Couldn't decode standard input (see --encoding and --lossy)")?,
+                );
+                set_text(&args, &new_sel)?;
+            }
         }
 
         Ok(())
@@ -168,9 +384,269 @@ fn main() {
     }
 }
 
+/// Writes text to stdout in the --output-encoding.
+#[cfg(windows)]
+fn write_text(args: &Args, text: &str) -> anyhow::Result<()> {
+    use anyhow::Context;
+    use std::io::Write;
+    use wsl_tools::text_encoding;
+
+    let bytes = text_encoding::encode(text, args.output_encoding, args.lossy)
+        .context("Couldn't encode the clipboard (see --output-encoding and --lossy)")?;
+
+    let mut stdout = std::io::stdout().lock();
+    stdout.write_all(&bytes)?;
+    stdout.flush()?;
+    Ok(())
+}
+
+/// Copies text (or HTML, with -t text/html), applying --trim and --ansi.
+#[cfg(windows)]
+fn set_text(args: &Args, text: &str) -> anyhow::Result<()> {
+    use wsl_tools::{ansi, clipboard::Content};
+
+    let text = if args.trim {
+        text.trim_end_matches(['\r', '\n'])
+    } else {
+        text
+    };
+
+    if args.ansi {
+        let spans = ansi::parse(text);
+        set_clipboard(
+            args,
+            &[
+                Content::Text(&ansi::to_plain(&spans)),
+                Content::Html(&ansi::to_html(&spans)),
+                Content::Rtf(&ansi::to_rtf(&spans)),
+            ],
+        )
+    } else {
+        match args.target {
+            Target::Text => set_clipboard(args, &[Content::Text(text)]),
+            Target::Html => set_clipboard(args, &[Content::Html(text)]),
+            Target::Png | Target::Files | Target::Targets => unreachable!(),
+        }
+    }
+}
+
+/// How often --follow updates the clipboard at most. Input that arrives in between is batched, so
+/// that something like `yes | xsel -f` doesn't tie up the clipboard for everything else.
+#[cfg(windows)]
+const FOLLOW_INTERVAL: std::time::Duration = std::time::Duration::from_millis(250);
+
+/// Copies stdin as it arrives until it's closed, starting from `initial`. See `FollowBuffer`.
+#[cfg(windows)]
+fn follow_stdin(args: &Args, initial: String) -> anyhow::Result<()> {
+    use std::{
+        io::{ErrorKind, Read},
+        sync::mpsc::{self, RecvTimeoutError},
+        thread,
+        time::Instant,
+    };
+    use wsl_tools::follow_buffer::FollowBuffer;
+
+    // Read on another thread so that waiting for input can time out
+    let (tx, rx) = mpsc::channel::<std::io::Result<Vec<u8>>>();
+    thread::spawn(move || {
+        let mut stdin = std::io::stdin().lock();
+        let mut buf = [0; 8192];
+        loop {
+            let chunk = match stdin.read(&mut buf) {
+                Ok(0) => return,
+                Ok(len) => Ok(buf[..len].to_vec()),
+                Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+                Err(err) => Err(err),
+            };
+            let failed = chunk.is_err();
+            if tx.send(chunk).is_err() || failed {
+                return;
+            }
+        }
+    });
+
+    // Only the final update should be cleared, rather than starting a clearer for every line
+    let clear_after = args.clear_after;
+    let args = &Args {
+        clear_after: None,
+        ..args.clone()
+    };
+
+    let mut buffer = FollowBuffer::new(initial, args.zeroflush);
+    let mut last_update: Option<Instant> = None;
+    let mut open = true;
+
+    while open {
+        // Wait for input, then keep collecting it until the next update is due
+        match rx.recv() {
+            Ok(chunk) => buffer.push(&chunk?),
+            Err(_) => break,
+        }
+
+        if let Some(last_update) = last_update {
+            let due = last_update + FOLLOW_INTERVAL;
+            while let Some(timeout) = due.checked_duration_since(Instant::now()) {
+                match rx.recv_timeout(timeout) {
+                    Ok(chunk) => buffer.push(&chunk?),
+                    Err(RecvTimeoutError::Timeout) => break,
+                    Err(RecvTimeoutError::Disconnected) => {
+                        open = false;
+                        break;
+                    }
+                }
+            }
+        }
+
+        if let Some(text) = buffer.take_update() {
+            set_text(args, &text)?;
+        }
+        last_update = Some(Instant::now());
+    }
+
+    // Anything left over, or the initial text if there was no input
+    if let Some(text) = buffer.take_update() {
+        set_text(args, &text)?;
+    }
+
+    if let Some(delay) = clear_after {
+        schedule_clear(delay)?;
+    }
+
+    Ok(())
+}
+
+/// Sets the clipboard, adding `--sensitive` and scheduling `--clear-after` if given.
+#[cfg(windows)]
+fn set_clipboard(args: &Args, contents: &[wsl_tools::clipboard::Content]) -> anyhow::Result<()> {
+    use wsl_tools::clipboard::{self, Content};
+
+    let mut contents = contents.to_vec();
+    if args.sensitive {
+        contents.push(Content::Sensitive);
+    }
+
+    clipboard::set(&contents)?;
+
+    if let Some(delay) = args.clear_after {
+        schedule_clear(delay)?;
+    }
+
+    Ok(())
+}
+
+/// Starts another xsel.exe in the background to clear the clipboard after `delay`, if nothing else
+/// has been copied by then.
+#[cfg(windows)]
+fn schedule_clear(delay: Duration) -> anyhow::Result<()> {
+    use std::process::Command;
+    use wsl_tools::{clipboard, process};
+
+    let mut cmd = Command::new(std::env::current_exe()?);
+    cmd.arg(format!(
+        "--clear-if-unchanged={}",
+        clipboard::sequence_number()
+    ))
+    .arg(format!("--clear-after={}ms", delay.as_millis()));
+    process::spawn_detached(&mut cmd)?;
+
+    Ok(())
+}
+
+/// How often --watch checks whether the clipboard has changed.
+#[cfg(windows)]
+const WATCH_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Outputs the clipboard text each time something is copied, until stdout is closed. Changes are
+/// detected by polling the clipboard's sequence number, which is cheap, but means that several
+/// copies within one `WATCH_INTERVAL` coalesce into one (only the last is output).
+#[cfg(windows)]
+fn watch(args: &Args) -> anyhow::Result<()> {
+    use std::{
+        io::{ErrorKind, Write},
+        time::SystemTime,
+    };
+    use wsl_tools::{
+        clipboard,
+        clipboard_watch::{Record, RecordFormat},
+        text_encoding,
+    };
+
+    let format = if args.json {
+        RecordFormat::Json
+    } else if args.null {
+        RecordFormat::Null
+    } else {
+        RecordFormat::Lines
+    };
+
+    let mut stdout = std::io::stdout();
+    let mut last_sequence_number = clipboard::sequence_number();
+
+    loop {
+        std::thread::sleep(WATCH_INTERVAL);
+
+        let sequence_number = clipboard::sequence_number();
+        if sequence_number == last_sequence_number {
+            continue;
+        }
+        last_sequence_number = sequence_number;
+
+        // A failure to read one copy (e.g. if another program is holding the clipboard open)
+        // shouldn't end the watch
+        let text = match clipboard::is_sensitive() {
+            Ok(true) => continue,
+            Ok(false) => get_clipboard(args),
+            Err(err) => Err(err),
+        };
+        let text = match text {
+            Ok(Some(text)) => text,
+            Ok(None) => continue,
+            Err(err) => {
+                eprintln!("xsel: {err:#}");
+                continue;
+            }
+        };
+
+        let record = Record {
+            time: SystemTime::now(),
+            text: &text,
+        };
+
+        let output = record.format(format, args.timestamps);
+        let output = match text_encoding::encode(&output, args.output_encoding, args.lossy) {
+            Ok(output) => output,
+            Err(err) => {
+                eprintln!("xsel: {err:#}");
+                continue;
+            }
+        };
+
+        let result = stdout.write_all(&output).and_then(|()| stdout.flush());
+
+        match result {
+            Err(err) if err.kind() == ErrorKind::BrokenPipe => return Ok(()),
+            result => result?,
+        }
+    }
+}
+
 #[cfg(windows)]
 fn get_clipboard(args: &Args) -> anyhow::Result<Option<String>> {
-    Ok(wsl_tools::clipboard::get_text()?.map(|text| {
+    use wsl_tools::clipboard;
+
+    let text = match args.target {
+        Target::Text => clipboard::get_text()?,
+        Target::Html => clipboard::get_html()?.map(|html| {
+            if args.document {
+                html.document
+            } else {
+                html.fragment
+            }
+        }),
+        Target::Png | Target::Files | Target::Targets => unreachable!(),
+    };
+
+    Ok(text.map(|text| {
         if args.keep_crlf {
             if args.trim {
                 text.trim_end_matches(['\r', '\n']).to_string()
@@ -185,23 +661,115 @@ fn get_clipboard(args: &Args) -> anyhow::Result<Option<String>> {
     }))
 }
 
+/// Images are binary, so they bypass the text handling (and --append and --trim don't apply).
+#[cfg(windows)]
+fn copy_or_paste_png(args: &Args, do_input: bool, do_output: bool) -> anyhow::Result<()> {
+    use anyhow::bail;
+    use std::io::{Read, Write};
+    use wsl_tools::clipboard;
+
+    if args.append {
+        bail!("Can't append to an image.");
+    }
+
+    if do_output && let Some(png) = clipboard::get_png()? {
+        let mut stdout = std::io::stdout().lock();
+        stdout.write_all(&png)?;
+        stdout.flush()?;
+    }
+
+    if args.clear {
+        clipboard::clear()?;
+    } else if do_input {
+        let mut png = Vec::new();
+        std::io::stdin().read_to_end(&mut png)?;
+        set_clipboard(args, &[clipboard::Content::Png(&png)])?;
+    }
+
+    Ok(())
+}
+
+/// Paths are one per line, both ways. See `exec_translating_paths` for how they're translated from
+/// and to WSL paths.
+#[cfg(windows)]
+fn copy_or_paste_files(args: &Args, do_input: bool, do_output: bool) -> anyhow::Result<()> {
+    use anyhow::bail;
+    use std::io::Read;
+    use wsl_tools::{clipboard, clipboard_files};
+
+    let old_files = if do_output {
+        clipboard::get_files()?.inspect(|files| {
+            for file in files {
+                println!("{file}");
+            }
+        })
+    } else {
+        None
+    };
+
+    if args.clear {
+        clipboard::clear()?;
+    } else if do_input {
+        let mut files = if !args.append {
+            Vec::new()
+        } else if do_output {
+            old_files.unwrap_or_default()
+        } else {
+            clipboard::get_files()?.unwrap_or_default()
+        };
+
+        let mut list = String::new();
+        std::io::stdin().read_to_string(&mut list)?;
+
+        for path in clipboard_files::parse_uri_list(&list) {
+            files.push(std::path::absolute(path)?.to_string_lossy().into_owned());
+        }
+
+        if files.is_empty() {
+            bail!("No files were given.");
+        }
+
+        set_clipboard(args, &[clipboard::Content::Files(&files)])?;
+    }
+
+    Ok(())
+}
+
 #[cfg(unix)]
 fn main() {
     use std::process::Stdio;
-    use wsl_tools::{exe_command, exe_exec};
+    use wsl_tools::{config::get_config, exe_command, exe_exec, selection::Selection};
 
     let stdin_is_tty = std::io::stdin().is_terminal();
     let stdout_is_tty = std::io::stdout().is_terminal();
 
     let mut cmd = exe_command!();
 
+    let args = Args::parse_args();
+
+    // PRIMARY and SECONDARY are either kept here or treated as the clipboard
+    let selection = if args.clipboard {
+        None
+    } else if args.secondary {
+        Some(Selection::Secondary)
+    } else if args.primary {
+        Some(Selection::Primary)
+    } else {
+        None
+    };
+
+    if get_config().separate_selections && (args.keep || args.exchange || selection.is_some()) {
+        if let Err(err) = use_selection(&args, selection, stdin_is_tty, stdout_is_tty) {
+            eprintln!("xsel: {err:#}");
+            std::process::exit(1);
+        }
+        return;
+    }
+
     // Figure out whether the exe will need stdin and, if not, redirect it to prevent the interop
     // layer from consuming stdin & breaking read loops.
-    let args = Args::parse();
-    let will_need_stdin = !args.keep
-        && !args.exchange
-        && !args.clear
-        && (args.append || args.input || (!args.output && !stdin_is_tty));
+    let will_need_stdin =
+        !args.keep && !args.exchange && !args.clear && args.do_input(stdin_is_tty);
     if !will_need_stdin {
         cmd.stdin(Stdio::null());
     }
@@ -210,5 +778,182 @@ fn main() {
         .arg(format!("--stdin-is-tty={stdin_is_tty}"))
         .arg(format!("--stdout-is-tty={stdout_is_tty}"));
 
+    if args.target == Target::Files && !args.keep && !args.exchange {
+        let do_output = args.do_output(stdin_is_tty, stdout_is_tty);
+        exec_translating_paths(cmd, will_need_stdin, do_output);
+    }
+
     exe_exec!(cmd);
 }
+
+/// With `separate_selections = yes` in wsl-tools.ini, the PRIMARY and SECONDARY selections are
+/// stored on this side (see `wsl_tools::selection`), so the exe isn't involved except to exchange
+/// one with the clipboard. They only hold text.
+#[cfg(unix)]
+fn use_selection(
+    args: &Args,
+    selection: Option<wsl_tools::selection::Selection>,
+    stdin_is_tty: bool,
+    stdout_is_tty: bool,
+) -> anyhow::Result<()> {
+    use anyhow::bail;
+    use wsl_tools::selection::{Selection, SelectionOptions, SelectionStore};
+
+    let store = SelectionStore::new();
+
+    // As in xsel, these ignore all input and output options
+    if args.exchange {
+        return exchange_with_clipboard(&store, selection.unwrap_or(Selection::Primary));
+    }
+    let Some(selection) = selection else {
+        // --keep makes the selections outlive the programs that own them, which they already do
+        return Ok(());
+    };
+
+    if args.target != Target::Text
+        || args.ansi
+        || args.sensitive
+        || args.clear_after.is_some()
+        || args.watch
+    {
+        bail!("PRIMARY and SECONDARY only hold plain text. Use -b for the clipboard.");
+    }
+
+    let options = SelectionOptions {
+        output: args.do_output(stdin_is_tty, stdout_is_tty),
+        input: args.do_input(stdin_is_tty),
+        append: args.append,
+        clear: args.clear,
+        follow: args.follow,
+        zero_flush: args.zeroflush,
+        trim: args.trim,
+    };
+
+    store.apply(
+        selection,
+        &options,
+        std::io::stdin().lock(),
+        std::io::stdout().lock(),
+    )?;
+
+    Ok(())
+}
+
+/// Swaps `selection` with the clipboard's text, for `--exchange`. The clipboard is set first, so
+/// that if the exe fails, neither has changed.
+#[cfg(unix)]
+fn exchange_with_clipboard(
+    store: &wsl_tools::selection::SelectionStore,
+    selection: wsl_tools::selection::Selection,
+) -> anyhow::Result<()> {
+    use anyhow::bail;
+    use std::{io::Write, process::Stdio};
+    use wsl_tools::exe_command;
+
+    let mut cmd = exe_command!();
+    cmd.args(["-b", "-o", "--stdin-is-tty=true", "--stdout-is-tty=false"])
+        .stdin(Stdio::null());
+    let output = cmd.output()?;
+    if !output.status.success() {
+        bail!("Failed to read the clipboard.");
+    }
+
+    let mut cmd = exe_command!();
+    let status = match store.get(selection)? {
+        Some(data) => {
+            let mut child = cmd
+                .args(["-b", "-i", "--stdin-is-tty=false", "--stdout-is-tty=false"])
+                .stdin(Stdio::piped())
+                .spawn()?;
+            let mut stdin = child.stdin.take().unwrap();
+            stdin.write_all(&data)?;
+            drop(stdin);
+            child.wait()?
+        }
+        None => cmd
+            .args(["-b", "-c", "--stdin-is-tty=true", "--stdout-is-tty=false"])
+            .stdin(Stdio::null())
+            .status()?,
+    };
+    if !status.success() {
+        bail!("Failed to set the clipboard.");
+    }
+
+    if output.stdout.is_empty() {
+        store.clear(selection)?;
+    } else {
+        store.set(selection, &output.stdout)?;
+    }
+
+    Ok(())
+}
+
+/// File lists are translated between WSL and Windows paths on this side, since relative paths are
+/// relative to our working directory and calling wslpath from here is much faster than through
+/// wsl.exe. Paths that are already Windows paths are passed through as-is; paths that wslpath can't
+/// translate back (e.g. on a drive that isn't mounted) are output as Windows paths.
+#[cfg(unix)]
+fn exec_translating_paths(mut cmd: std::process::Command, do_input: bool, do_output: bool) -> ! {
+    use std::{
+        io::{BufRead, BufReader, Read, Write},
+        process::Stdio,
+        thread,
+    };
+    use wsl_tools::{clipboard_files, exe_exit, exe_spawn, wslpath};
+
+    let mut list = String::new();
+    if do_input {
+        let mut input = String::new();
+        if let Err(err) = std::io::stdin().read_to_string(&mut input) {
+            eprintln!("xsel: {err}");
+            std::process::exit(1);
+        }
+
+        for path in clipboard_files::parse_uri_list(&input) {
+            // wslpath resolves symlinks, which is what we want here: Windows can't follow them
+            let path = if clipboard_files::is_windows_path(&path) {
+                path
+            } else {
+                match wslpath::to_windows(&path) {
+                    Ok(path) => path,
+                    Err(err) => {
+                        eprintln!("xsel: {path}: {err}");
+                        std::process::exit(1);
+                    }
+                }
+            };
+            list.push_str(&path);
+            list.push('\n');
+        }
+
+        cmd.stdin(Stdio::piped());
+    }
+
+    if do_output {
+        cmd.stdout(Stdio::piped());
+    }
+
+    let mut child = exe_spawn!(cmd);
+
+    // Write on another thread so that a full pipe can't deadlock us
+    let writer = child.stdin.take().map(|mut stdin| {
+        thread::spawn(move || {
+            let _ = stdin.write_all(list.as_bytes());
+        })
+    });
+
+    if let Some(stdout) = child.stdout.take() {
+        for line in BufReader::new(stdout).lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            println!("{}", wslpath::to_wsl(&line).unwrap_or(line));
+        }
+    }
+
+    if let Some(writer) = writer {
+        let _ = writer.join();
+    }
+
+    exe_exit!(child.wait());
+}
@@ -3,31 +3,112 @@
 
 #![cfg(windows)]
 
-use crate::hglobal::{GlobalMemory, Lock};
-use anyhow::{Context, Result};
+use crate::{
+    clipboard_files,
+    clipboard_format::{
+        CAN_INCLUDE_IN_CLIPBOARD_HISTORY, CAN_UPLOAD_TO_CLOUD_CLIPBOARD, CF_DIB, CF_DIBV5,
+        CF_HDROP, CF_UNICODETEXT, EXCLUDE_FROM_MONITOR_PROCESSING, FIRST_REGISTERED, Format,
+        HTML_FORMAT, PNG_FORMAT, PREFERRED_DROP_EFFECT, RTF_FORMAT,
+    },
+    clipboard_html::{self, Html},
+    clipboard_image,
+    hglobal::{GlobalMemory, Lock},
+};
+use anyhow::{Context, Result, bail};
 use std::time::Duration;
 use windows::{
     Win32::{
         Foundation::{HANDLE, HGLOBAL},
         System::{
             DataExchange::{
-                CloseClipboard, EmptyClipboard, GetClipboardData, IsClipboardFormatAvailable,
-                OpenClipboard, SetClipboardData,
+                CloseClipboard, EmptyClipboard, EnumClipboardFormats, GetClipboardData,
+                GetClipboardFormatNameW, GetClipboardSequenceNumber, IsClipboardFormatAvailable,
+                OpenClipboard, RegisterClipboardFormatW, SetClipboardData,
             },
-            Memory::{GMEM_MOVEABLE, GlobalAlloc},
+            Memory::{GMEM_MOVEABLE, GlobalAlloc, GlobalSize},
         },
     },
-    core::PWSTR,
+    core::{HSTRING, PCWSTR, PWSTR},
 };
 
-const CF_UNICODETEXT: u32 = 13;
+const DROPEFFECT_COPY: u32 = 1;
+
+/// A format to place on the clipboard with `set`.
+#[derive(Debug, Clone, Copy)]
+pub enum Content<'a> {
+    /// Unicode text (`CF_UNICODETEXT`).
+    Text(&'a str),
+    /// An HTML fragment or document (`CF_HTML`); see `set_html`.
+    Html(&'a str),
+    /// Rich Text Format.
+    Rtf(&'a str),
+    /// A PNG image, which is also converted to a bitmap (`CF_DIBV5`).
+    Png(&'a [u8]),
+    /// A list of files (`CF_HDROP`), as copied in Explorer. The paths should be absolute Windows
+    /// paths.
+    Files(&'a [String]),
+    /// Marks the rest of the content as sensitive (e.g. a password), so that clipboard managers
+    /// ignore it and it isn't saved to clipboard history or synced to other devices.
+    Sensitive,
+}
 
 /// Copies `text` to the clipboard.
 ///
 /// # Errors
 /// Error result contains the Win32 error if the operation failed.
 pub fn set_text(text: &str) -> Result<()> {
+    set(&[Content::Text(text)])
+}
+
+/// Replaces the clipboard with several formats of the same content at once, letting the program
+/// it's pasted into pick the one it understands best.
+///
+/// # Errors
+/// Error result contains the Win32 error if the operation failed.
+pub fn set(contents: &[Content]) -> Result<()> {
     unsafe {
+        // Convert everything up front so that the clipboard is held open as briefly as possible
+        let mut data: Vec<(u32, Vec<u8>)> = Vec::new();
+        for content in contents {
+            match content {
+                Content::Text(text) => {
+                    // Convert the text to UTF-16 and add a null terminator
+                    let mut bytes: Vec<u8> =
+                        text.encode_utf16().flat_map(u16::to_ne_bytes).collect();
+                    bytes.extend([0, 0]);
+                    data.push((CF_UNICODETEXT, bytes));
+                }
+                Content::Html(html) => {
+                    data.push((html_format()?, null_terminate(clipboard_html::build(html))));
+                }
+                Content::Rtf(rtf) => {
+                    data.push((rtf_format()?, null_terminate((*rtf).to_string())));
+                }
+                Content::Png(png) => {
+                    // Programs that support PNG get it as-is; the rest get a bitmap
+                    data.push((png_format()?, png.to_vec()));
+                    data.push((CF_DIBV5, clipboard_image::png_to_dib(png)?));
+                }
+                Content::Files(paths) => {
+                    // Without this, Explorer may decide to move the files instead
+                    data.push((CF_HDROP, clipboard_files::build(paths)));
+                    data.push((
+                        drop_effect_format()?,
+                        DROPEFFECT_COPY.to_le_bytes().to_vec(),
+                    ));
+                }
+                Content::Sensitive => {
+                    for name in [
+                        EXCLUDE_FROM_MONITOR_PROCESSING,
+                        CAN_INCLUDE_IN_CLIPBOARD_HISTORY,
+                        CAN_UPLOAD_TO_CLOUD_CLIPBOARD,
+                    ] {
+                        data.push((register_format(name)?, 0u32.to_le_bytes().to_vec()));
+                    }
+                }
+            }
+        }
+
         // Open the clipboard
         open_clipboard()?;
 
@@ -36,26 +117,11 @@ pub fn set_text(text: &str) -> Result<()> {
             // Clear the clipboard
             EmptyClipboard().context("EmptyClipboard")?;
 
-            // Convert the text to UTF-16 and add a null terminator
-            let mut utf16: Vec<u16> = text.encode_utf16().collect();
-            utf16.push(0);
-            let length = utf16.len();
-
-            // Allocate global memory to hold the text
-            let hglobal = GlobalAlloc(GMEM_MOVEABLE, length * 2).context("GlobalAlloc")?;
-
-            {
-                // Acquire a pointer to the memory (custom smart pointer, see hglobal.rs)
-                let dest: GlobalMemory<u16> = hglobal.lock()?;
-
-                // Copy the text to the buffer
-                std::ptr::copy_nonoverlapping(utf16.as_ptr(), dest.as_ptr(), length);
+            // Place each format on the clipboard
+            for (format, bytes) in &data {
+                set_data(*format, bytes)?;
             }
 
-            // Place the handle on the clipboard
-            SetClipboardData(CF_UNICODETEXT, Some(HANDLE(hglobal.0)))
-                .context("SetClipboardData")?;
-
             Ok(())
         })();
 
@@ -105,6 +171,186 @@ pub fn get_text() -> Result<Option<String>> {
     }
 }
 
+/// Copies HTML to the clipboard in the "HTML Format" (`CF_HTML`) used by browsers and Office.
+/// `html` may be a fragment or a whole document; see `clipboard_html::build`.
+///
+/// # Errors
+/// Error result contains the Win32 error if the operation failed.
+pub fn set_html(html: &str) -> Result<()> {
+    set(&[Content::Html(html)])
+}
+
+/// Gets the HTML on the clipboard, if any.
+///
+/// # Returns
+/// `Some` if the clipboard contains HTML; `None` if it does not.
+///
+/// # Errors
+/// Error result contains the Win32 error if the operation failed, or if the clipboard's HTML
+/// couldn't be parsed.
+pub fn get_html() -> Result<Option<Html>> {
+    unsafe {
+        let format = html_format()?;
+        if IsClipboardFormatAvailable(format).is_err() {
+            return Ok(None);
+        }
+
+        open_clipboard()?;
+        let result = get_data(format);
+        let _ = CloseClipboard();
+
+        match result? {
+            None => Ok(None),
+            Some(data) => match clipboard_html::parse(&data) {
+                Some(html) => Ok(Some(html)),
+                None => bail!("The HTML on the clipboard is missing its header."),
+            },
+        }
+    }
+}
+
+/// Copies a PNG image to the clipboard.
+///
+/// # Errors
+/// Error result contains the Win32 error if the operation failed, or if `png` couldn't be decoded.
+pub fn set_png(png: &[u8]) -> Result<()> {
+    set(&[Content::Png(png)])
+}
+
+/// Gets the image on the clipboard as a PNG. If the program that copied it provided a PNG, that's
+/// returned as-is; otherwise the bitmap is converted.
+///
+/// # Returns
+/// `Some` if the clipboard contains an image; `None` if it does not.
+///
+/// # Errors
+/// Error result contains the Win32 error if the operation failed, or if the bitmap is in a format
+/// that couldn't be converted.
+pub fn get_png() -> Result<Option<Vec<u8>>> {
+    unsafe {
+        // Windows synthesizes CF_DIBV5 from CF_DIB and vice versa, so if there's a bitmap, both
+        // will be available. The V5 header is preferred since it can specify an alpha channel.
+        let png_format = png_format()?;
+        let Some(format) = [png_format, CF_DIBV5, CF_DIB]
+            .into_iter()
+            .find(|&x| IsClipboardFormatAvailable(x).is_ok())
+        else {
+            return Ok(None);
+        };
+
+        open_clipboard()?;
+        let result = get_data(format);
+        let _ = CloseClipboard();
+
+        match result? {
+            None => Ok(None),
+            Some(data) if format == png_format => match clipboard_image::png_len(&data) {
+                Some(len) => Ok(Some(data[..len].to_vec())),
+                None => bail!("The PNG on the clipboard is invalid."),
+            },
+            Some(data) => clipboard_image::dib_to_png(&data).map(Some),
+        }
+    }
+}
+
+/// Copies a list of files to the clipboard, so that they can be pasted in Explorer.
+///
+/// # Errors
+/// Error result contains the Win32 error if the operation failed.
+pub fn set_files(paths: &[String]) -> Result<()> {
+    set(&[Content::Files(paths)])
+}
+
+/// Gets the list of files on the clipboard, as copied in Explorer.
+///
+/// # Returns
+/// `Some` if the clipboard contains files; `None` if it does not.
+///
+/// # Errors
+/// Error result contains the Win32 error if the operation failed, or if the list couldn't be
+/// parsed.
+pub fn get_files() -> Result<Option<Vec<String>>> {
+    unsafe {
+        if IsClipboardFormatAvailable(CF_HDROP).is_err() {
+            return Ok(None);
+        }
+
+        open_clipboard()?;
+        let result = get_data(CF_HDROP);
+        let _ = CloseClipboard();
+
+        match result? {
+            None => Ok(None),
+            Some(data) => match clipboard_files::parse(&data) {
+                Some(paths) => Ok(Some(paths)),
+                None => bail!("The file list on the clipboard is invalid."),
+            },
+        }
+    }
+}
+
+/// Lists the formats on the clipboard, in the order they were placed on it (usually best first),
+/// including any that Windows would synthesize. See `clipboard_format::list_targets`.
+///
+/// # Errors
+/// Error result contains the Win32 error if the operation failed.
+pub fn formats() -> Result<Vec<Format>> {
+    unsafe {
+        open_clipboard()?;
+
+        let result = (|| -> Result<Vec<Format>> {
+            let mut formats = Vec::new();
+            let mut id = 0;
+
+            loop {
+                id = EnumClipboardFormats(id);
+                if id == 0 {
+                    // Zero means either the end of the list or an error
+                    let err = windows::core::Error::from_win32();
+                    if err.code().is_ok() {
+                        return Ok(formats);
+                    }
+                    return Err(err).context("EnumClipboardFormats");
+                }
+
+                let name = if id >= FIRST_REGISTERED {
+                    let mut buf = [0u16; 256];
+                    let len = GetClipboardFormatNameW(id, &mut buf);
+                    usize::try_from(len)
+                        .ok()
+                        .filter(|&x| x > 0)
+                        .map(|x| String::from_utf16_lossy(&buf[..x]))
+                } else {
+                    None
+                };
+
+                formats.push(Format { id, name });
+            }
+        })();
+
+        let _ = CloseClipboard();
+        result
+    }
+}
+
+/// Checks whether the clipboard contains content marked as sensitive (see `Content::Sensitive`).
+///
+/// # Errors
+/// Error result contains the Win32 error if the operation failed.
+pub fn is_sensitive() -> Result<bool> {
+    unsafe {
+        let format = register_format(EXCLUDE_FROM_MONITOR_PROCESSING)?;
+        Ok(IsClipboardFormatAvailable(format).is_ok())
+    }
+}
+
+/// Gets the clipboard's sequence number, which changes every time its contents do. Comparing it
+/// with an earlier value tells whether something else has been copied since.
+#[must_use]
+pub fn sequence_number() -> u32 {
+    unsafe { GetClipboardSequenceNumber() }
+}
+
 /// Clears the clipboard.
 ///
 /// # Errors
@@ -118,6 +364,75 @@ pub fn clear() -> Result<()> {
     }
 }
 
+/// Gets the ID of the registered "HTML Format" clipboard format.
+unsafe fn html_format() -> Result<u32> {
+    unsafe { register_format(HTML_FORMAT) }
+}
+
+/// Gets the ID of the registered "Rich Text Format" clipboard format.
+unsafe fn rtf_format() -> Result<u32> {
+    unsafe { register_format(RTF_FORMAT) }
+}
+
+/// Gets the ID of the registered "PNG" clipboard format.
+unsafe fn png_format() -> Result<u32> {
+    unsafe { register_format(PNG_FORMAT) }
+}
+
+/// Gets the ID of the registered `"Preferred DropEffect"` clipboard format, which tells Explorer
+/// whether files were cut or copied.
+unsafe fn drop_effect_format() -> Result<u32> {
+    unsafe { register_format(PREFERRED_DROP_EFFECT) }
+}
+
+/// Registers a clipboard format by name, or gets its ID if it's already registered (which for the
+/// standard ones it always will be).
+unsafe fn register_format(name: &str) -> Result<u32> {
+    let name = HSTRING::from(name);
+    match unsafe { RegisterClipboardFormatW(PCWSTR(name.as_ptr())) } {
+        0 => Err(windows::core::Error::from_win32()).context("RegisterClipboardFormatW"),
+        format => Ok(format),
+    }
+}
+
+fn null_terminate(str: String) -> Vec<u8> {
+    let mut bytes = str.into_bytes();
+    bytes.push(0);
+    bytes
+}
+
+/// Copies `data` into global memory and places it on the clipboard, which must be open.
+unsafe fn set_data(format: u32, data: &[u8]) -> Result<()> {
+    unsafe {
+        let hglobal = GlobalAlloc(GMEM_MOVEABLE, data.len()).context("GlobalAlloc")?;
+
+        {
+            let dest: GlobalMemory<u8> = hglobal.lock()?;
+            std::ptr::copy_nonoverlapping(data.as_ptr(), dest.as_ptr(), data.len());
+        }
+
+        SetClipboardData(format, Some(HANDLE(hglobal.0))).context("SetClipboardData")?;
+        Ok(())
+    }
+}
+
+/// Copies the data for `format` out of the clipboard, which must be open. Note that the size of the
+/// memory may be larger than the data that was put in it.
+unsafe fn get_data(format: u32) -> Result<Option<Vec<u8>>> {
+    unsafe {
+        let hglobal = match GetClipboardData(format) {
+            Ok(handle) => HGLOBAL(handle.0),
+            Err(_) => return Ok(None), // Clipboard changed while we were opening it
+        };
+
+        let size = GlobalSize(hglobal);
+        let mem: GlobalMemory<u8> = hglobal.lock()?;
+        Ok(Some(
+            std::slice::from_raw_parts(mem.as_ptr(), size).to_vec(),
+        ))
+    }
+}
+
 /// Tries to open the clipboard.
 ///
 /// `OpenClipboard` can fail with `ERROR_ACCESS_DENIED` if another program is using it at the same
@@ -0,0 +1,206 @@
+// Copyright (c) Max Kagamine
+// Licensed under the Apache License, Version 2.0
+
+// Files copied in Explorer are on the clipboard as `CF_HDROP`: a DROPFILES struct followed by the
+// paths, each NUL-terminated, with an extra NUL at the end of the list. Linux file managers use
+// "text/uri-list" for the same thing, which is what `xsel -t text/uri-list` reads and writes:
+// https://learn.microsoft.com/en-us/windows/win32/shell/clipboard#cf_hdrop
+//
+// Building and parsing it is kept separate from `clipboard` so that it can be tested on Linux.
+
+use percent_encoding::percent_decode_str;
+
+/// `sizeof(DROPFILES)`: pFiles, pt.x, pt.y, fNC, fWide.
+const DROPFILES_SIZE: u32 = 20;
+
+/// Builds a `CF_HDROP` list of wide (UTF-16) paths. The paths should be absolute Windows paths.
+#[must_use]
+pub fn build<T: AsRef<str>>(paths: &[T]) -> Vec<u8> {
+    let mut data = Vec::new();
+
+    for field in [DROPFILES_SIZE, 0, 0, 0, 1] {
+        data.extend(field.to_le_bytes());
+    }
+
+    for path in paths {
+        for unit in path.as_ref().encode_utf16().chain([0]) {
+            data.extend(unit.to_le_bytes());
+        }
+    }
+    data.extend([0, 0]);
+
+    data
+}
+
+/// Parses a `CF_HDROP` list. Returns `None` if the DROPFILES struct is missing or points outside
+/// the data.
+#[must_use]
+pub fn parse(data: &[u8]) -> Option<Vec<String>> {
+    let u32_at = |i: usize| Some(u32::from_le_bytes(data.get(i..i + 4)?.try_into().ok()?));
+    let offset = u32_at(0)? as usize;
+    let wide = u32_at(16)? != 0;
+    let list = data.get(offset..)?;
+
+    let paths = if wide {
+        let units: Vec<u16> = list
+            .chunks_exact(2)
+            .map(|x| u16::from_le_bytes([x[0], x[1]]))
+            .collect();
+        units
+            .split(|&x| x == 0)
+            .take_while(|x| !x.is_empty())
+            .map(String::from_utf16_lossy)
+            .collect()
+    } else {
+        // The system code page, which we can't decode here, but nothing has used this since Windows
+        // 9x and the paths will most likely be ASCII anyway
+        list.split(|&x| x == 0)
+            .take_while(|x| !x.is_empty())
+            .map(|x| String::from_utf8_lossy(x).into_owned())
+            .collect()
+    };
+
+    Some(paths)
+}
+
+/// Parses a "text/uri-list" (RFC 2483), or a plain list of paths, one per line. Blank lines and
+/// comments are ignored. `file://` URIs are decoded into paths: `file:///C:/foo` becomes `C:\foo`,
+/// `file://server/share` becomes `\\server\share`, and `file:///home/foo` becomes `/home/foo`.
+/// Anything else is left as-is.
+#[must_use]
+pub fn parse_uri_list(list: &str) -> Vec<String> {
+    list.lines()
+        .map(|x| x.trim_end_matches('\r'))
+        .filter(|x| !x.trim().is_empty() && !x.starts_with('#'))
+        .map(|x| file_uri_to_path(x).unwrap_or_else(|| x.to_string()))
+        .collect()
+}
+
+fn file_uri_to_path(uri: &str) -> Option<String> {
+    let rest = uri
+        .get(..7)
+        .filter(|x| x.eq_ignore_ascii_case("file://"))
+        .map(|_| &uri[7..])?;
+    let (host, path) = rest.split_at(rest.find('/').unwrap_or(rest.len()));
+    let path = percent_decode_str(path).decode_utf8_lossy();
+
+    if !host.is_empty() && !host.eq_ignore_ascii_case("localhost") {
+        let host = percent_decode_str(host).decode_utf8_lossy();
+        return Some(format!("\\\\{host}{}", path.replace('/', "\\")));
+    }
+
+    match path.strip_prefix('/') {
+        Some(windows_path) if is_windows_path(windows_path) => {
+            Some(windows_path.replace('/', "\\"))
+        }
+        _ => Some(path.into_owned()),
+    }
+}
+
+/// Checks if `path` is an absolute Windows path (`C:\` or `C:/`) or a UNC path (`\\server`).
+#[must_use]
+pub fn is_windows_path(path: &str) -> bool {
+    match path.as_bytes() {
+        [drive, b':', b'\\' | b'/', ..] | [drive, b':'] => drive.is_ascii_alphabetic(),
+        [b'\\', b'\\', ..] => true,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_wide_dropfiles() {
+        let data = build(&["C:\\a", "D:\\鏡"]);
+
+        #[rustfmt::skip]
+        let expected: Vec<u8> = [
+            &[20, 0, 0, 0][..], // pFiles
+            &[0; 8],            // pt
+            &[0; 4],            // fNC
+            &[1, 0, 0, 0],      // fWide
+            &[b'C', 0, b':', 0, b'\\', 0, b'a', 0, 0, 0],
+            &[b'D', 0, b':', 0, b'\\', 0, 0xe1, 0x93, 0, 0],
+            &[0, 0],
+        ]
+        .concat();
+
+        assert_eq!(data, expected);
+    }
+
+    #[test]
+    fn round_trips() {
+        let paths = vec![
+            "C:\\Users\\Rin\\Pictures\\鏡音リン.png".to_string(),
+            "\\\\wsl.localhost\\Ubuntu\\home\\rin\\foo bar".to_string(),
+        ];
+
+        assert_eq!(parse(&build(&paths)), Some(paths));
+    }
+
+    #[test]
+    fn parses_ansi_dropfiles_with_gap_before_list() {
+        let mut data = Vec::new();
+        for field in [24u32, 0, 0, 0, 0, 0xdead_beef] {
+            data.extend(field.to_le_bytes());
+        }
+        data.extend(b"C:\\a.txt\0C:\\b.txt\0\0");
+
+        assert_eq!(
+            parse(&data),
+            Some(vec!["C:\\a.txt".to_string(), "C:\\b.txt".to_string()])
+        );
+    }
+
+    #[test]
+    fn parses_empty_list() {
+        assert_eq!(parse(&build::<&str>(&[])), Some(vec![]));
+    }
+
+    #[test]
+    fn rejects_invalid_dropfiles() {
+        assert_eq!(parse(&[20, 0, 0, 0]), None);
+
+        let mut data = build(&["C:\\a"]);
+        data[0] = 200;
+        assert_eq!(parse(&data), None);
+    }
+
+    #[test]
+    fn parses_uri_list() {
+        let list = "# Copied from Nautilus\r\n\
+            file:///home/rin/Music/%E3%83%AA%E3%83%B3%20-%20Remote%20Controller.flac\r\n\
+            FILE://localhost/etc/hosts\r\n\
+            \r\n\
+            file:///C:/Program%20Files/\r\n\
+            file://server/share/a%23b.txt\n\
+            relative/path\n\
+            D:\\Windows path\n";
+
+        assert_eq!(
+            parse_uri_list(list),
+            [
+                "/home/rin/Music/リン - Remote Controller.flac",
+                "/etc/hosts",
+                "C:\\Program Files\\",
+                "\\\\server\\share\\a#b.txt",
+                "relative/path",
+                "D:\\Windows path",
+            ]
+        );
+    }
+
+    #[test]
+    fn detects_windows_paths() {
+        assert!(is_windows_path("C:\\"));
+        assert!(is_windows_path("c:/Users"));
+        assert!(is_windows_path("D:"));
+        assert!(is_windows_path("\\\\wsl$\\Ubuntu"));
+        assert!(!is_windows_path("/mnt/c"));
+        assert!(!is_windows_path("C:relative"));
+        assert!(!is_windows_path("foo"));
+        assert!(!is_windows_path("1:\\"));
+    }
+}
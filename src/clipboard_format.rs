@@ -0,0 +1,236 @@
+// Copyright (c) Max Kagamine
+// Licensed under the Apache License, Version 2.0
+
+// Windows clipboard formats are identified by number: the standard ones are fixed, while others
+// are registered by name at runtime. X11 clipboards use MIME types instead, so this maps between
+// the two for `xsel -t`, the same way that xclip would list them with `-t TARGETS`.
+// https://learn.microsoft.com/en-us/windows/win32/dataxchg/standard-clipboard-formats
+
+pub const CF_TEXT: u32 = 1;
+pub const CF_BITMAP: u32 = 2;
+pub const CF_METAFILEPICT: u32 = 3;
+pub const CF_SYLK: u32 = 4;
+pub const CF_DIF: u32 = 5;
+pub const CF_TIFF: u32 = 6;
+pub const CF_OEMTEXT: u32 = 7;
+pub const CF_DIB: u32 = 8;
+pub const CF_PALETTE: u32 = 9;
+pub const CF_PENDATA: u32 = 10;
+pub const CF_RIFF: u32 = 11;
+pub const CF_WAVE: u32 = 12;
+pub const CF_UNICODETEXT: u32 = 13;
+pub const CF_ENHMETAFILE: u32 = 14;
+pub const CF_HDROP: u32 = 15;
+pub const CF_LOCALE: u32 = 16;
+pub const CF_DIBV5: u32 = 17;
+
+/// Registered formats start here. Below are standard formats, private formats, and GDI objects.
+pub const FIRST_REGISTERED: u32 = 0xc000;
+
+// Names of registered formats
+pub const HTML_FORMAT: &str = "HTML Format";
+pub const RTF_FORMAT: &str = "Rich Text Format";
+pub const PNG_FORMAT: &str = "PNG";
+pub const PREFERRED_DROP_EFFECT: &str = "Preferred DropEffect";
+
+// Registered formats that keep passwords out of clipboard managers, clipboard history (Win+V), and
+// cloud sync. Their presence (or a DWORD 0, for the latter two) is the signal.
+// https://learn.microsoft.com/en-us/windows/win32/dataxchg/clipboard-formats#cloud-clipboard-and-clipboard-history-formats
+pub const EXCLUDE_FROM_MONITOR_PROCESSING: &str = "ExcludeClipboardContentFromMonitorProcessing";
+pub const CAN_INCLUDE_IN_CLIPBOARD_HISTORY: &str = "CanIncludeInClipboardHistory";
+pub const CAN_UPLOAD_TO_CLOUD_CLIPBOARD: &str = "CanUploadToCloudClipboard";
+
+// MIME types, as used by X11 clipboards and `xsel -t`
+pub const TEXT_PLAIN: &str = "text/plain";
+pub const TEXT_HTML: &str = "text/html";
+pub const TEXT_RTF: &str = "text/rtf";
+pub const TEXT_URI_LIST: &str = "text/uri-list";
+pub const IMAGE_PNG: &str = "image/png";
+pub const TARGETS: &str = "TARGETS";
+
+/// Standard formats: ID, name, and the MIME type they can be pasted as, if any. All the bitmap
+/// formats are listed as `image/png` since `clipboard::get_png` converts them.
+const STANDARD: &[(u32, &str, Option<&str>)] = &[
+    (CF_TEXT, "CF_TEXT", Some(TEXT_PLAIN)),
+    (CF_BITMAP, "CF_BITMAP", Some(IMAGE_PNG)),
+    (CF_METAFILEPICT, "CF_METAFILEPICT", None),
+    (CF_SYLK, "CF_SYLK", None),
+    (CF_DIF, "CF_DIF", None),
+    (CF_TIFF, "CF_TIFF", Some("image/tiff")),
+    (CF_OEMTEXT, "CF_OEMTEXT", Some(TEXT_PLAIN)),
+    (CF_DIB, "CF_DIB", Some(IMAGE_PNG)),
+    (CF_PALETTE, "CF_PALETTE", None),
+    (CF_PENDATA, "CF_PENDATA", None),
+    (CF_RIFF, "CF_RIFF", None),
+    (CF_WAVE, "CF_WAVE", Some("audio/wav")),
+    (CF_UNICODETEXT, "CF_UNICODETEXT", Some(TEXT_PLAIN)),
+    (CF_ENHMETAFILE, "CF_ENHMETAFILE", None),
+    (CF_HDROP, "CF_HDROP", Some(TEXT_URI_LIST)),
+    (CF_LOCALE, "CF_LOCALE", None),
+    (CF_DIBV5, "CF_DIBV5", Some(IMAGE_PNG)),
+    (0x0080, "CF_OWNERDISPLAY", None),
+    (0x0081, "CF_DSPTEXT", None),
+    (0x0082, "CF_DSPBITMAP", None),
+    (0x0083, "CF_DSPMETAFILEPICT", None),
+    (0x008e, "CF_DSPENHMETAFILE", None),
+];
+
+/// Registered formats that have a MIME type. Names are matched case-insensitively; anything that
+/// already looks like a MIME type (as some cross-platform programs register) is used as-is.
+const REGISTERED: &[(&str, &str)] = &[
+    (HTML_FORMAT, TEXT_HTML),
+    (RTF_FORMAT, TEXT_RTF),
+    (PNG_FORMAT, IMAGE_PNG),
+    ("JFIF", "image/jpeg"),
+    ("GIF", "image/gif"),
+];
+
+/// A format on the clipboard: its ID, and its name if it's a registered format.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Format {
+    pub id: u32,
+    pub name: Option<String>,
+}
+
+impl Format {
+    /// Gets the MIME type that this format can be pasted as, if there is one.
+    #[must_use]
+    pub fn mime_type(&self) -> Option<&str> {
+        match &self.name {
+            None => STANDARD.iter().find(|x| x.0 == self.id).and_then(|x| x.2),
+            Some(name) => REGISTERED
+                .iter()
+                .find(|x| x.0.eq_ignore_ascii_case(name))
+                .map(|x| x.1)
+                .or_else(|| is_mime_type(name).then_some(name.as_str())),
+        }
+    }
+
+    /// Gets the name of the format: the registered name, the name of the `CF_` constant, or if
+    /// it's a private format or otherwise unknown, `CF_` followed by the ID.
+    #[must_use]
+    pub fn name(&self) -> String {
+        match &self.name {
+            Some(name) => name.clone(),
+            None => STANDARD
+                .iter()
+                .find(|x| x.0 == self.id)
+                .map_or_else(|| format!("CF_{:#06x}", self.id), |x| x.1.to_string()),
+        }
+    }
+}
+
+fn is_mime_type(name: &str) -> bool {
+    name.split_once('/').is_some_and(|(kind, subtype)| {
+        !kind.is_empty()
+            && !subtype.is_empty()
+            && kind.chars().all(|c| c.is_ascii_lowercase())
+            && !subtype.contains(char::is_whitespace)
+    })
+}
+
+/// Lists the formats on the clipboard as xclip would: `TARGETS` first, then the MIME type of each
+/// format that has one (without duplicates, since text and bitmaps have several), then the names
+/// of the rest, all in the clipboard's order.
+#[must_use]
+pub fn list_targets(formats: &[Format]) -> Vec<String> {
+    let mut targets = vec![TARGETS.to_string()];
+    let mut others = Vec::new();
+
+    for format in formats {
+        if let Some(mime_type) = format.mime_type() {
+            if !targets.iter().any(|x| x == mime_type) {
+                targets.push(mime_type.to_string());
+            }
+        } else {
+            let name = format.name();
+            if !others.contains(&name) {
+                others.push(name);
+            }
+        }
+    }
+
+    targets.extend(others);
+    targets
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn standard(id: u32) -> Format {
+        Format { id, name: None }
+    }
+
+    fn registered(id: u32, name: &str) -> Format {
+        Format {
+            id,
+            name: Some(name.to_string()),
+        }
+    }
+
+    #[test]
+    fn maps_formats_to_mime_types() {
+        assert_eq!(standard(CF_UNICODETEXT).mime_type(), Some(TEXT_PLAIN));
+        assert_eq!(standard(CF_DIBV5).mime_type(), Some(IMAGE_PNG));
+        assert_eq!(standard(CF_HDROP).mime_type(), Some(TEXT_URI_LIST));
+        assert_eq!(standard(CF_LOCALE).mime_type(), None);
+        assert_eq!(
+            registered(0xc0a1, "HTML Format").mime_type(),
+            Some(TEXT_HTML)
+        );
+        assert_eq!(registered(0xc0a2, "png").mime_type(), Some(IMAGE_PNG));
+        assert_eq!(
+            registered(0xc0a3, "image/svg+xml").mime_type(),
+            Some("image/svg+xml")
+        );
+        assert_eq!(registered(0xc0a4, "Shell IDList Array").mime_type(), None);
+        assert_eq!(registered(0xc0a5, "FileNameW").mime_type(), None);
+    }
+
+    #[test]
+    fn names_formats() {
+        assert_eq!(standard(CF_LOCALE).name(), "CF_LOCALE");
+        assert_eq!(standard(0x0200).name(), "CF_0x0200");
+        assert_eq!(
+            registered(0xc0a4, "Shell IDList Array").name(),
+            "Shell IDList Array"
+        );
+    }
+
+    #[test]
+    fn lists_targets_like_xclip() {
+        // As copied from Chrome (abbreviated)
+        let formats = [
+            registered(0xc00d, "DataObject"),
+            registered(0xc0a1, "HTML Format"),
+            standard(CF_UNICODETEXT),
+            standard(CF_LOCALE),
+            standard(CF_TEXT),
+            standard(CF_OEMTEXT),
+            registered(0xc2b0, "Chromium internal source URL"),
+            registered(0xc0a2, "PNG"),
+            standard(CF_DIB),
+            standard(CF_BITMAP),
+            standard(CF_DIBV5),
+        ];
+
+        assert_eq!(
+            list_targets(&formats),
+            [
+                "TARGETS",
+                "text/html",
+                "text/plain",
+                "image/png",
+                "DataObject",
+                "CF_LOCALE",
+                "Chromium internal source URL",
+            ]
+        );
+    }
+
+    #[test]
+    fn lists_only_targets_when_empty() {
+        assert_eq!(list_targets(&[]), ["TARGETS"]);
+    }
+}
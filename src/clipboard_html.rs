@@ -0,0 +1,247 @@
+// Copyright (c) Max Kagamine
+// Licensed under the Apache License, Version 2.0
+
+// The clipboard's HTML format ("HTML Format", a.k.a. CF_HTML) is UTF-8 with a plain-text header in
+// front giving the byte offsets of the document and of the fragment that was actually copied:
+// https://learn.microsoft.com/en-us/windows/win32/dataxchg/html-clipboard-format
+//
+// Building and parsing it is kept separate from `clipboard` so that it can be tested on Linux.
+
+use std::collections::HashMap;
+
+const START_FRAGMENT: &str = "<!--StartFragment-->";
+const END_FRAGMENT: &str = "<!--EndFragment-->";
+
+/// HTML read from the clipboard.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Html {
+    /// The whole document, including the fragment comments if present. Same as `fragment` if the
+    /// source didn't provide any context.
+    pub document: String,
+    /// The part that was selected when copying.
+    pub fragment: String,
+    pub source_url: Option<String>,
+}
+
+/// Wraps `html` in a `CF_HTML` header. If it's a whole document (starting with a doctype or
+/// `<html>`), the contents of its `<body>` become the fragment; otherwise it's treated as the
+/// fragment and put in a minimal document.
+#[must_use]
+pub fn build(html: &str) -> String {
+    let (before, fragment, after) = split_document(html);
+    let document = format!("{before}{START_FRAGMENT}{fragment}{END_FRAGMENT}{after}");
+
+    // The offsets are zero-padded so that the header is the same length whatever they are
+    let header_len = header(0, 0, 0, 0).len();
+    let start_fragment = header_len + before.len() + START_FRAGMENT.len();
+    let end_fragment = start_fragment + fragment.len();
+
+    header(
+        header_len,
+        header_len + document.len(),
+        start_fragment,
+        end_fragment,
+    ) + &document
+}
+
+fn header(
+    start_html: usize,
+    end_html: usize,
+    start_fragment: usize,
+    end_fragment: usize,
+) -> String {
+    format!(
+        "Version:0.9\r\nStartHTML:{start_html:010}\r\nEndHTML:{end_html:010}\r\nStartFragment:{start_fragment:010}\r\nEndFragment:{end_fragment:010}\r\n"
+    )
+}
+
+/// Splits a document into the part before the fragment, the fragment, and the part after.
+fn split_document(html: &str) -> (&str, &str, &str) {
+    const WRAP_BEFORE: &str = "<html>\r\n<body>\r\n";
+    const WRAP_AFTER: &str = "\r\n</body>\r\n</html>";
+
+    let lower = html.trim_start().to_ascii_lowercase();
+    if !lower.starts_with("<!doctype") && !lower.starts_with("<html") {
+        return (WRAP_BEFORE, html, WRAP_AFTER);
+    }
+
+    // Lowercasing ASCII doesn't change byte offsets
+    let lower = html.to_ascii_lowercase();
+    let body_start = lower
+        .find("<body")
+        .and_then(|i| lower[i..].find('>').map(|j| i + j + 1));
+    let body_end = lower.rfind("</body");
+
+    match (body_start, body_end) {
+        (Some(start), Some(end)) if start <= end => {
+            (&html[..start], &html[start..end], &html[end..])
+        }
+        _ => ("", html, ""),
+    }
+}
+
+/// Parses the clipboard's HTML format. Returns `None` if the header is missing or its offsets are
+/// out of range.
+#[must_use]
+pub fn parse(data: &[u8]) -> Option<Html> {
+    let data = data.strip_suffix(b"\0").unwrap_or(data);
+    let data = &data[..data.iter().position(|&b| b == 0).unwrap_or(data.len())];
+
+    let fields = parse_header(data);
+    let offset = |key: &str| -> Option<usize> { fields.get(key)?.parse().ok() };
+
+    // StartHTML and EndHTML may be -1 if there's no context, in which case we only have a fragment
+    let start_fragment = offset("StartFragment");
+    let end_fragment = offset("EndFragment");
+    let (start_html, end_html) = match (offset("StartHTML"), offset("EndHTML")) {
+        (Some(start), Some(end)) => (start, end),
+        _ => (start_fragment?, end_fragment?),
+    };
+
+    let document = data.get(start_html..end_html)?;
+
+    // Some programs have gotten the fragment offsets wrong, so fall back to the comments
+    let fragment = match (start_fragment, end_fragment) {
+        (Some(start), Some(end)) if start_html <= start && start <= end && end <= end_html => {
+            &data[start..end]
+        }
+        _ => find_fragment(document).unwrap_or(document),
+    };
+
+    Some(Html {
+        document: String::from_utf8_lossy(document).into_owned(),
+        fragment: String::from_utf8_lossy(fragment).into_owned(),
+        source_url: fields.get("SourceURL").map(ToString::to_string),
+    })
+}
+
+/// Reads the `Key:Value` lines at the start of the data, up to the first tag.
+fn parse_header(data: &[u8]) -> HashMap<&str, &str> {
+    let mut fields = HashMap::new();
+
+    for line in data.split(|&b| b == b'\n') {
+        let Ok(line) = str::from_utf8(line) else {
+            break;
+        };
+        let line = line.trim_end_matches('\r');
+        if line.starts_with('<') {
+            break;
+        }
+        let Some((key, value)) = line.split_once(':') else {
+            break;
+        };
+        fields.insert(key.trim(), value.trim());
+    }
+
+    if fields.contains_key("Version") {
+        fields
+    } else {
+        HashMap::new()
+    }
+}
+
+fn find_fragment(document: &[u8]) -> Option<&[u8]> {
+    let start = find(document, START_FRAGMENT.as_bytes())? + START_FRAGMENT.len();
+    let end = start + find(&document[start..], END_FRAGMENT.as_bytes())?;
+    Some(&document[start..end])
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|x| x == needle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn field(built: &str, key: &str) -> usize {
+        built
+            .lines()
+            .find_map(|x| x.strip_prefix(&format!("{key}:")))
+            .unwrap()
+            .parse()
+            .unwrap()
+    }
+
+    #[test]
+    fn builds_header_with_byte_offsets() {
+        let fragment = "<b>鏡音リン</b> &amp; レン";
+        let built = build(fragment);
+
+        assert!(built.starts_with("Version:0.9\r\nStartHTML:0000000105\r\n"));
+        assert_eq!(
+            &built[field(&built, "StartFragment")..field(&built, "EndFragment")],
+            fragment
+        );
+        assert_eq!(
+            &built[field(&built, "StartHTML")..field(&built, "EndHTML")],
+            format!(
+                "<html>\r\n<body>\r\n<!--StartFragment-->{fragment}<!--EndFragment-->\r\n</body>\r\n</html>"
+            )
+        );
+        assert_eq!(field(&built, "EndHTML"), built.len());
+    }
+
+    #[test]
+    fn uses_body_of_whole_documents_as_fragment() {
+        let document = "<!DOCTYPE html>\n<html><head><style>td { color: red; }</style></head>\n<BODY class=\"report\"><table><tr><td>42</td></tr></table></BODY>\n</html>\n";
+        let built = build(document);
+        let html = parse(built.as_bytes()).unwrap();
+
+        assert_eq!(html.fragment, "<table><tr><td>42</td></tr></table>");
+        assert_eq!(
+            html.document
+                .replace(START_FRAGMENT, "")
+                .replace(END_FRAGMENT, ""),
+            document
+        );
+    }
+
+    #[test]
+    fn round_trips() {
+        let fragment = "<p>Line 1\r\nLine 2</p>";
+        let html = parse(build(fragment).as_bytes()).unwrap();
+
+        assert_eq!(html.fragment, fragment);
+        assert!(html.document.contains(fragment));
+        assert_eq!(html.source_url, None);
+    }
+
+    #[test]
+    fn parses_html_copied_from_a_browser() {
+        // As copied from Chrome (null-terminated)
+        let data = b"Version:0.9\r\nStartHTML:0000000137\r\nEndHTML:0000000234\r\nStartFragment:0000000173\r\nEndFragment:0000000198\r\nSourceURL:https://example.com/\r\n<html>\r\n<body>\r\n<!--StartFragment--><span>Hello, world</span><!--EndFragment-->\r\n</body>\r\n</html>\0";
+        let html = parse(data).unwrap();
+
+        assert_eq!(html.fragment, "<span>Hello, world</span>");
+        assert!(html.document.starts_with("<html>"));
+        assert!(html.document.ends_with("</html>"));
+        assert_eq!(html.source_url.as_deref(), Some("https://example.com/"));
+    }
+
+    #[test]
+    fn parses_fragment_without_context() {
+        let data = b"Version:1.0\r\nStartHTML:-1\r\nEndHTML:-1\r\nStartFragment:0000000089\r\nEndFragment:0000000098\r\n<b>hi</b>";
+        let html = parse(data).unwrap();
+
+        assert_eq!(html.fragment, "<b>hi</b>");
+        assert_eq!(html.document, "<b>hi</b>");
+    }
+
+    #[test]
+    fn falls_back_to_fragment_comments() {
+        let data = b"Version:0.9\r\nStartHTML:0000000105\r\nEndHTML:0000000177\r\nStartFragment:0000009999\r\nEndFragment:0000000000\r\n<html><body><!--StartFragment--><i>x</i><!--EndFragment--></body></html>";
+        let html = parse(data).unwrap();
+
+        assert_eq!(html.fragment, "<i>x</i>");
+    }
+
+    #[test]
+    fn rejects_invalid_data() {
+        assert_eq!(parse(b"<b>no header</b>"), None);
+        assert_eq!(
+            parse(b"Version:0.9\r\nStartHTML:0000000010\r\nEndHTML:0000099999\r\n<b></b>"),
+            None
+        );
+    }
+}
@@ -0,0 +1,491 @@
+// Copyright (c) Max Kagamine
+// Licensed under the Apache License, Version 2.0
+
+// Images on the Windows clipboard are device-independent bitmaps (`CF_DIB` and `CF_DIBV5`): a
+// BITMAPINFOHEADER or BITMAPV5HEADER followed by the pixels, without the BITMAPFILEHEADER that a
+// .bmp file would have. Some programs (browsers, Office, Snipping Tool) also provide a registered
+// "PNG" format, which keeps transparency intact.
+//
+// Converting between DIBs and PNGs is kept separate from `clipboard` so that it can be tested on
+// Linux. See https://learn.microsoft.com/en-us/windows/win32/gdi/bitmap-header-types
+
+use anyhow::{Context, Result, bail};
+use png::{BitDepth, ColorType, Transformations};
+use std::io::Cursor;
+
+const BI_RGB: u32 = 0;
+const BI_BITFIELDS: u32 = 3;
+const BI_PNG: u32 = 5;
+const BI_ALPHABITFIELDS: u32 = 6;
+
+const BITMAPINFOHEADER_SIZE: usize = 40;
+const BITMAPV5HEADER_SIZE: usize = 124;
+const LCS_SRGB: u32 = 0x7352_4742;
+const LCS_GM_IMAGES: u32 = 4;
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+/// The fields of a BITMAPINFOHEADER (and the later versions that extend it) that we care about.
+#[derive(Debug)]
+struct Header {
+    size: usize,
+    width: usize,
+    height: usize,
+    top_down: bool,
+    bit_count: u16,
+    compression: u32,
+    /// Red, green, blue, and alpha masks, if given.
+    masks: Option<[u32; 4]>,
+    palette: Vec<[u8; 4]>,
+    /// Where the pixels start, relative to the start of the header.
+    offset: usize,
+}
+
+impl Header {
+    fn parse(dib: &[u8]) -> Result<Header> {
+        let u16_at = |i: usize| dib.get(i..i + 2).map(|x| u16::from_le_bytes([x[0], x[1]]));
+        let u32_at = |i: usize| {
+            dib.get(i..i + 4)
+                .map(|x| u32::from_le_bytes([x[0], x[1], x[2], x[3]]))
+        };
+        let truncated = || anyhow::anyhow!("The bitmap's header is truncated.");
+
+        let size = u32_at(0).ok_or_else(truncated)? as usize;
+        if size < BITMAPINFOHEADER_SIZE {
+            bail!("Unsupported bitmap header (size {size}).");
+        }
+        if dib.len() < size {
+            return Err(truncated());
+        }
+
+        // The header's all there, so these won't fail
+        #[allow(clippy::cast_possible_wrap)]
+        let (width, height) = (u32_at(4).unwrap() as i32, u32_at(8).unwrap() as i32);
+        let bit_count = u16_at(14).unwrap();
+        let compression = u32_at(16).unwrap();
+        let colors_used = u32_at(32).unwrap() as usize;
+
+        if width <= 0 || height == 0 {
+            bail!("The bitmap has no pixels ({width}×{height}).");
+        }
+
+        // A plain BITMAPINFOHEADER has the masks after it; the later versions have them inside
+        let mut offset = size;
+        let masks = match compression {
+            BI_BITFIELDS | BI_ALPHABITFIELDS => {
+                let mask_at = |i: usize| u32_at(i).ok_or_else(truncated);
+                if size > BITMAPINFOHEADER_SIZE {
+                    let alpha = if size >= 56 { mask_at(52)? } else { 0 };
+                    Some([mask_at(40)?, mask_at(44)?, mask_at(48)?, alpha])
+                } else {
+                    let count = if compression == BI_ALPHABITFIELDS {
+                        4
+                    } else {
+                        3
+                    };
+                    offset += count * 4;
+                    let alpha = if count == 4 { mask_at(size + 12)? } else { 0 };
+                    Some([
+                        mask_at(size)?,
+                        mask_at(size + 4)?,
+                        mask_at(size + 8)?,
+                        alpha,
+                    ])
+                }
+            }
+            BI_RGB | BI_PNG => None,
+            _ => bail!("Compressed bitmaps are not supported."),
+        };
+
+        // Indexed bitmaps always have a color table; others may have one for optimizing display
+        let palette_len = match (bit_count, colors_used) {
+            (1 | 4 | 8, 0) => 1 << bit_count,
+            (_, n) => n,
+        };
+        let palette = dib
+            .get(offset..offset + palette_len * 4)
+            .ok_or_else(truncated)?
+            .chunks_exact(4)
+            .map(|x| [x[2], x[1], x[0], 255])
+            .collect();
+        offset += palette_len * 4;
+
+        Ok(Header {
+            size,
+            width: width.unsigned_abs() as usize,
+            height: height.unsigned_abs() as usize,
+            top_down: height < 0,
+            bit_count,
+            compression,
+            masks,
+            palette,
+            offset,
+        })
+    }
+}
+
+/// Gets the RGBA of pixel `x` in a row.
+type ReadPixel<'a> = Box<dyn Fn(&[u8], usize) -> [u8; 4] + 'a>;
+
+/// Converts a DIB, as found on the clipboard as `CF_DIB` or `CF_DIBV5`, to a PNG.
+///
+/// # Errors
+/// The bitmap is truncated or in a format that isn't supported (RLE or JPEG compression, or a
+/// pre-Windows 3.0 BITMAPCOREHEADER).
+pub fn dib_to_png(dib: &[u8]) -> Result<Vec<u8>> {
+    let header = Header::parse(dib)?;
+
+    // A DIB can technically just be a PNG with a header in front of it
+    if header.compression == BI_PNG {
+        return Ok(dib[header.size..].to_vec());
+    }
+
+    let bits = header.bit_count as usize;
+    let stride = (header.width * bits).div_ceil(32) * 4; // Rows are padded to four bytes
+    let Some(pixels) = header
+        .height
+        .checked_mul(stride)
+        .and_then(|len| dib.get(header.offset..header.offset.checked_add(len)?))
+    else {
+        bail!("The bitmap's pixel data is truncated.");
+    };
+
+    let read_pixel: ReadPixel = match (bits, header.masks) {
+        (1 | 2 | 4 | 8, None) => {
+            let palette = &header.palette;
+            Box::new(move |row, x| {
+                let byte = row[x * bits / 8];
+                let shift = 8 - bits - (x * bits % 8);
+                let index = (byte >> shift) as usize & ((1 << bits) - 1);
+                palette.get(index).copied().unwrap_or([0, 0, 0, 255])
+            })
+        }
+        (16, masks) => {
+            let masks = masks.unwrap_or([0x7c00, 0x03e0, 0x001f, 0]);
+            Box::new(move |row, x| {
+                let value = u16::from_le_bytes([row[x * 2], row[x * 2 + 1]]);
+                apply_masks(u32::from(value), masks)
+            })
+        }
+        (24, None) => Box::new(|row, x| [row[x * 3 + 2], row[x * 3 + 1], row[x * 3], 255]),
+        (32, Some(masks)) => Box::new(move |row, x| {
+            let value =
+                u32::from_le_bytes([row[x * 4], row[x * 4 + 1], row[x * 4 + 2], row[x * 4 + 3]]);
+            apply_masks(value, masks)
+        }),
+        // The fourth byte is officially reserved, but browsers and others put alpha in it
+        (32, None) => {
+            Box::new(|row, x| [row[x * 4 + 2], row[x * 4 + 1], row[x * 4], row[x * 4 + 3]])
+        }
+        _ => bail!(
+            "Unsupported bitmap format ({bits}-bit, compression {}).",
+            header.compression
+        ),
+    };
+
+    let mut rgba = Vec::with_capacity(header.width * header.height * 4);
+    for y in 0..header.height {
+        let row = if header.top_down {
+            y
+        } else {
+            header.height - 1 - y
+        };
+        let row = &pixels[row * stride..(row + 1) * stride];
+        for x in 0..header.width {
+            rgba.extend(read_pixel(row, x));
+        }
+    }
+
+    // An all-zero reserved byte just means it wasn't used
+    if bits == 32 && header.masks.is_none() && rgba.chunks_exact(4).all(|x| x[3] == 0) {
+        rgba.chunks_exact_mut(4).for_each(|x| x[3] = 255);
+    }
+
+    encode_png(header.width, header.height, &rgba)
+}
+
+/// Extracts and scales each channel to 8 bits. Alpha is opaque if there's no mask for it.
+fn apply_masks(value: u32, masks: [u32; 4]) -> [u8; 4] {
+    masks.map(|mask| {
+        if mask == 0 {
+            return 255;
+        }
+        let max = u64::from(mask >> mask.trailing_zeros());
+        let channel = u64::from((value & mask) >> mask.trailing_zeros());
+        #[allow(clippy::cast_possible_truncation)] // channel <= max
+        let scaled = ((channel * 255 + max / 2) / max) as u8;
+        scaled
+    })
+}
+
+fn encode_png(width: usize, height: usize, rgba: &[u8]) -> Result<Vec<u8>> {
+    let mut png = Vec::new();
+
+    // Leave out the alpha channel if the image is opaque, to save space
+    let opaque = rgba.chunks_exact(4).all(|x| x[3] == 255);
+    let rgb: Vec<u8>;
+    let (color_type, data) = if opaque {
+        rgb = rgba
+            .chunks_exact(4)
+            .flat_map(|x| &x[..3])
+            .copied()
+            .collect();
+        (ColorType::Rgb, rgb.as_slice())
+    } else {
+        (ColorType::Rgba, rgba)
+    };
+
+    let mut encoder = png::Encoder::new(
+        &mut png,
+        u32::try_from(width).context("Image is too large")?,
+        u32::try_from(height).context("Image is too large")?,
+    );
+    encoder.set_color(color_type);
+    encoder.set_depth(BitDepth::Eight);
+
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(data)?;
+    writer.finish()?;
+
+    Ok(png)
+}
+
+/// Converts a PNG to a 32-bit bottom-up DIB with a BITMAPV5HEADER and (non-premultiplied) alpha,
+/// for `CF_DIBV5`. Windows will synthesize `CF_DIB` and `CF_BITMAP` from it for older programs.
+///
+/// # Errors
+/// The PNG couldn't be decoded.
+pub fn png_to_dib(png: &[u8]) -> Result<Vec<u8>> {
+    let mut decoder = png::Decoder::new(Cursor::new(png));
+    decoder.set_transformations(Transformations::EXPAND | Transformations::STRIP_16);
+
+    let mut reader = decoder.read_info().context("Not a valid PNG")?;
+    let mut buf = vec![0; reader.output_buffer_size().context("Image is too large")?];
+    let info = reader.next_frame(&mut buf).context("Not a valid PNG")?;
+
+    let to_bgra: fn(&[u8]) -> [u8; 4] = match info.color_type {
+        ColorType::Grayscale => |x| [x[0], x[0], x[0], 255],
+        ColorType::GrayscaleAlpha => |x| [x[0], x[0], x[0], x[1]],
+        ColorType::Rgb => |x| [x[2], x[1], x[0], 255],
+        ColorType::Rgba => |x| [x[2], x[1], x[0], x[3]],
+        ColorType::Indexed => bail!("Indexed PNG wasn't expanded"), // Shouldn't happen
+    };
+    let channels = info.color_type.samples();
+
+    let (width, height) = (info.width as usize, info.height as usize);
+    let image_size = width * height * 4;
+    let mut dib = Vec::with_capacity(BITMAPV5HEADER_SIZE + image_size);
+
+    #[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+    let fields: [u32; 15] = [
+        BITMAPV5HEADER_SIZE as u32, // bV5Size
+        info.width,                 // bV5Width
+        info.height,                // bV5Height (positive = bottom-up)
+        1 | (32 << 16),             // bV5Planes, bV5BitCount
+        BI_BITFIELDS,               // bV5Compression
+        image_size as u32,          // bV5SizeImage
+        0,                          // bV5XPelsPerMeter
+        0,                          // bV5YPelsPerMeter
+        0,                          // bV5ClrUsed
+        0,                          // bV5ClrImportant
+        0x00ff_0000,                // bV5RedMask
+        0x0000_ff00,                // bV5GreenMask
+        0x0000_00ff,                // bV5BlueMask
+        0xff00_0000,                // bV5AlphaMask
+        LCS_SRGB,                   // bV5CSType
+    ];
+    for field in fields {
+        dib.extend(field.to_le_bytes());
+    }
+    dib.extend([0; 36 + 12]); // bV5Endpoints, bV5GammaRed/Green/Blue (unused for sRGB)
+    for field in [LCS_GM_IMAGES, 0, 0, 0] {
+        // bV5Intent, bV5ProfileData, bV5ProfileSize, bV5Reserved
+        dib.extend(field.to_le_bytes());
+    }
+    debug_assert_eq!(dib.len(), BITMAPV5HEADER_SIZE);
+
+    for row in buf[..info.buffer_size()].chunks_exact(info.line_size).rev() {
+        for pixel in row.chunks_exact(channels).take(width) {
+            dib.extend(to_bgra(pixel));
+        }
+    }
+
+    Ok(dib)
+}
+
+/// Returns the length of the PNG at the start of `data`, up to the end of its IEND chunk. The
+/// memory behind clipboard data can be larger than what was put in it, so this is used to drop the
+/// excess.
+#[must_use]
+pub fn png_len(data: &[u8]) -> Option<usize> {
+    if !data.starts_with(PNG_SIGNATURE) {
+        return None;
+    }
+
+    let mut i = PNG_SIGNATURE.len();
+    loop {
+        let len = u32::from_be_bytes(data.get(i..i + 4)?.try_into().ok()?) as usize;
+        let chunk_type = data.get(i + 4..i + 8)?;
+        i = i.checked_add(12 + len)?; // Length, type, data, CRC
+        if chunk_type == b"IEND" {
+            return (i <= data.len()).then_some(i);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // All of the fixtures are the same 3×2 image (chosen so that the rows need padding) in
+    // different formats, and all but v5_alpha.dib are BITMAPINFOHEADERs.
+    const RED: [u8; 4] = [255, 0, 0, 255];
+    const GREEN: [u8; 4] = [0, 255, 0, 255];
+    const BLUE: [u8; 4] = [0, 0, 255, 255];
+    const WHITE: [u8; 4] = [255, 255, 255, 255];
+    const BLACK: [u8; 4] = [0, 0, 0, 255];
+    const YELLOW: [u8; 4] = [255, 255, 0, 255];
+
+    const OPAQUE: [[u8; 4]; 6] = [RED, GREEN, BLUE, WHITE, BLACK, YELLOW];
+    const TRANSLUCENT: [[u8; 4]; 6] = [
+        RED,
+        [0, 255, 0, 128],
+        [0, 0, 255, 0],
+        WHITE,
+        BLACK,
+        [255, 255, 0, 64],
+    ];
+
+    fn fixture(name: &str) -> Vec<u8> {
+        let path = format!("{}/tests/fixtures/dib/{name}", env!("CARGO_MANIFEST_DIR"));
+        std::fs::read(&path).unwrap_or_else(|err| panic!("{path}: {err}"))
+    }
+
+    /// Decodes a PNG to its dimensions and RGBA pixels.
+    fn decode(png: &[u8]) -> (u32, u32, Vec<[u8; 4]>) {
+        let mut decoder = png::Decoder::new(Cursor::new(png));
+        decoder.set_transformations(Transformations::ALPHA);
+        let mut reader = decoder.read_info().unwrap();
+        let mut buf = vec![0; reader.output_buffer_size().unwrap()];
+        let info = reader.next_frame(&mut buf).unwrap();
+        assert_eq!(info.color_type, ColorType::Rgba);
+
+        let pixels = buf[..info.buffer_size()]
+            .chunks_exact(4)
+            .map(|x| x.try_into().unwrap())
+            .collect();
+        (info.width, info.height, pixels)
+    }
+
+    fn assert_converts(name: &str, expected: [[u8; 4]; 6]) {
+        let png = dib_to_png(&fixture(name)).unwrap_or_else(|err| panic!("{name}: {err}"));
+        assert_eq!(decode(&png), (3, 2, expected.to_vec()), "{name}");
+    }
+
+    #[test]
+    fn converts_bottom_up_24_bit() {
+        assert_converts("bottom_up_24.dib", OPAQUE);
+    }
+
+    #[test]
+    fn converts_top_down_bitfields() {
+        assert_converts("top_down_32_bitfields.dib", OPAQUE);
+    }
+
+    #[test]
+    fn converts_16_bit_bitfields() {
+        assert_converts("rgb565.dib", OPAQUE);
+    }
+
+    #[test]
+    fn converts_indexed() {
+        assert_converts("palette_4.dib", OPAQUE);
+    }
+
+    #[test]
+    fn converts_v5_with_alpha_mask() {
+        assert_converts("v5_alpha.dib", TRANSLUCENT);
+    }
+
+    #[test]
+    fn uses_reserved_byte_as_alpha_unless_all_zero() {
+        assert_converts("argb_32.dib", TRANSLUCENT);
+        assert_converts("xrgb_32.dib", OPAQUE);
+    }
+
+    #[test]
+    fn leaves_out_alpha_channel_when_opaque() {
+        let png = dib_to_png(&fixture("bottom_up_24.dib")).unwrap();
+        let reader = png::Decoder::new(Cursor::new(&png)).read_info().unwrap();
+        assert_eq!(reader.info().color_type, ColorType::Rgb);
+    }
+
+    #[test]
+    fn round_trips_png_through_dib() {
+        let png = dib_to_png(&fixture("v5_alpha.dib")).unwrap();
+        let dib = png_to_dib(&png).unwrap();
+
+        let header = Header::parse(&dib).unwrap();
+        assert_eq!(header.size, BITMAPV5HEADER_SIZE);
+        assert_eq!((header.width, header.height), (3, 2));
+        assert!(!header.top_down);
+        assert_eq!(header.bit_count, 32);
+        assert_eq!(
+            header.masks,
+            Some([0x00ff_0000, 0x0000_ff00, 0x0000_00ff, 0xff00_0000])
+        );
+        assert_eq!(dib.len(), BITMAPV5HEADER_SIZE + 3 * 2 * 4);
+
+        assert_eq!(decode(&dib_to_png(&dib).unwrap()), decode(&png));
+    }
+
+    #[test]
+    fn converts_grayscale_png() {
+        let mut png = Vec::new();
+        let mut encoder = png::Encoder::new(&mut png, 2, 1);
+        encoder.set_color(ColorType::GrayscaleAlpha);
+        let mut writer = encoder.write_header().unwrap();
+        writer.write_image_data(&[0x40, 255, 0xc0, 0x80]).unwrap();
+        writer.finish().unwrap();
+
+        let dib = png_to_dib(&png).unwrap();
+        assert_eq!(
+            dib[BITMAPV5HEADER_SIZE..],
+            [0x40, 0x40, 0x40, 255, 0xc0, 0xc0, 0xc0, 0x80]
+        );
+    }
+
+    #[test]
+    fn passes_through_embedded_png() {
+        let png = dib_to_png(&fixture("bottom_up_24.dib")).unwrap();
+        let mut dib = fixture("bottom_up_24.dib")[..BITMAPINFOHEADER_SIZE].to_vec();
+        dib[16..20].copy_from_slice(&BI_PNG.to_le_bytes());
+        dib.extend(&png);
+
+        assert_eq!(dib_to_png(&dib).unwrap(), png);
+    }
+
+    #[test]
+    fn rejects_truncated_and_compressed_bitmaps() {
+        let dib = fixture("bottom_up_24.dib");
+        assert!(dib_to_png(&dib[..20]).is_err());
+        assert!(dib_to_png(&dib[..dib.len() - 1]).is_err());
+
+        let mut rle = dib.clone();
+        rle[16..20].copy_from_slice(&1u32.to_le_bytes()); // BI_RLE8
+        assert!(dib_to_png(&rle).is_err());
+
+        assert!(png_to_dib(b"not a png").is_err());
+    }
+
+    #[test]
+    fn finds_end_of_png() {
+        let png = dib_to_png(&fixture("bottom_up_24.dib")).unwrap();
+        let mut padded = png.clone();
+        padded.extend([0; 13]);
+
+        assert_eq!(png_len(&padded), Some(png.len()));
+        assert_eq!(png_len(&png[..png.len() - 1]), None);
+        assert_eq!(png_len(b"GIF89a"), None);
+    }
+}
@@ -0,0 +1,144 @@
+// Copyright (c) Max Kagamine
+// Licensed under the Apache License, Version 2.0
+
+// `xsel --watch` outputs the clipboard each time it changes, so that Windows copies can be piped
+// into scripts. This formats those records; the watching itself is in the xsel binary.
+
+use serde::Serialize;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// How records are delimited.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordFormat {
+    /// Each record ends with a newline.
+    Lines,
+    /// Each record ends with a NUL, for text that may contain newlines.
+    Null,
+    /// Each record is a JSON object on its own line, with the time and text.
+    Json,
+}
+
+/// Something that was copied.
+#[derive(Debug, Clone, Copy)]
+pub struct Record<'a> {
+    pub time: SystemTime,
+    pub text: &'a str,
+}
+
+#[derive(Serialize)]
+struct JsonRecord<'a> {
+    time: String,
+    text: &'a str,
+}
+
+impl Record<'_> {
+    /// Formats the record for output, including its delimiter. With `timestamps`, the text is
+    /// prefixed with the time and a tab (JSON always includes the time).
+    ///
+    /// # Panics
+    /// Never; a string and a timestamp are infallibly serializable.
+    #[must_use]
+    pub fn format(&self, format: RecordFormat, timestamps: bool) -> String {
+        if format == RecordFormat::Json {
+            let record = JsonRecord {
+                time: timestamp(self.time),
+                text: self.text,
+            };
+            return serde_json::to_string(&record).unwrap() + "\n";
+        }
+
+        let mut output = String::new();
+        if timestamps {
+            output.push_str(&timestamp(self.time));
+            output.push('\t');
+        }
+        output.push_str(self.text);
+        output.push(if format == RecordFormat::Null {
+            '\0'
+        } else {
+            '\n'
+        });
+        output
+    }
+}
+
+/// Formats `time` as an RFC 3339 timestamp in UTC, with milliseconds.
+#[must_use]
+pub fn timestamp(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let seconds = since_epoch.as_secs();
+    let (year, month, day) = civil_from_days(seconds / 86400);
+
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}.{:03}Z",
+        seconds / 3600 % 24,
+        seconds / 60 % 60,
+        seconds % 60,
+        since_epoch.subsec_millis()
+    )
+}
+
+/// Converts days since 1970-01-01 to a date.
+/// <https://howardhinnant.github.io/date_algorithms.html#civil_from_days>
+fn civil_from_days(days: u64) -> (u64, u64, u64) {
+    let days = days + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_from_march = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_from_march + 2) / 5 + 1;
+    let month = if month_from_march < 10 {
+        month_from_march + 3
+    } else {
+        month_from_march - 9
+    };
+    let year = era * 400 + year_of_era + u64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn time(millis: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn formats_timestamps() {
+        assert_eq!(timestamp(UNIX_EPOCH), "1970-01-01T00:00:00.000Z");
+        assert_eq!(
+            timestamp(time(1_792_326_896_789)),
+            "2026-10-18T12:34:56.789Z"
+        );
+        assert_eq!(
+            timestamp(time(1_709_164_800_000)),
+            "2024-02-29T00:00:00.000Z"
+        );
+        assert_eq!(timestamp(time(951_782_400_500)), "2000-02-29T00:00:00.500Z");
+    }
+
+    #[test]
+    fn formats_records() {
+        let record = Record {
+            time: time(1_792_326_896_789),
+            text: "鏡音リン\n\"Remote Controller\"",
+        };
+
+        assert_eq!(
+            record.format(RecordFormat::Lines, false),
+            "鏡音リン\n\"Remote Controller\"\n"
+        );
+        assert_eq!(
+            record.format(RecordFormat::Null, true),
+            "2026-10-18T12:34:56.789Z\t鏡音リン\n\"Remote Controller\"\0"
+        );
+        assert_eq!(
+            record.format(RecordFormat::Json, false),
+            "{\"time\":\"2026-10-18T12:34:56.789Z\",\"text\":\"鏡音リン\\n\\\"Remote Controller\\\"\"}\n"
+        );
+    }
+}
@@ -3,14 +3,30 @@
 
 #![cfg(unix)]
 
+use crate::{
+    freedesktop_trash::TrashFallback, recycle_backend::InteropFallback, recycle_size::SizeLimit,
+};
 use ini::Ini;
 use std::{env, sync::OnceLock};
 
 const CONFIG_FILENAME: &str = "wsl-tools.ini";
 
+#[allow(clippy::struct_excessive_bools)]
 pub struct Config {
     pub ini_exists: bool,
     pub use_linux_trash: bool,
+    /// What to do with items on mounts that can't have their own trash can.
+    pub linux_trash_fallback: TrashFallback,
+    /// Raw `protect` patterns, in the order given. See `recycle_protect`.
+    pub protect: Vec<String>,
+    /// Estimated Recycle Bin capacity, for refusing items that would be nuked with `--rm`.
+    pub max_recycle_size: SizeLimit,
+    /// What to do with items on Windows drives if `recycle.exe` can't be run.
+    pub interop_fallback: InteropFallback,
+    /// Check for uncommitted changes before recycling, as with `--git-safe`.
+    pub git_safe: bool,
+    /// Keep xsel's PRIMARY and SECONDARY selections separate from the clipboard. See `selection`.
+    pub separate_selections: bool,
 }
 
 static CONFIG: OnceLock<Config> = OnceLock::new();
@@ -28,6 +44,24 @@ fn load_config() -> Config {
     Config {
         ini_exists: ini.is_some(),
         use_linux_trash: get_bool(ini.as_ref(), "use_linux_trash", false),
+        linux_trash_fallback: ini
+            .as_ref()
+            .and_then(|x| x.get_from(Some("config"), "linux_trash_fallback"))
+            .and_then(TrashFallback::parse)
+            .unwrap_or_default(),
+        protect: get_all(ini.as_ref(), "protect"),
+        max_recycle_size: ini
+            .as_ref()
+            .and_then(|x| x.get_from(Some("config"), "max_recycle_size"))
+            .and_then(SizeLimit::parse)
+            .unwrap_or_default(),
+        interop_fallback: ini
+            .as_ref()
+            .and_then(|x| x.get_from(Some("config"), "interop_fallback"))
+            .and_then(InteropFallback::parse)
+            .unwrap_or_default(),
+        git_safe: get_bool(ini.as_ref(), "git_safe", false),
+        separate_selections: get_bool(ini.as_ref(), "separate_selections", false),
     }
 }
 
@@ -39,3 +73,9 @@ fn get_bool(ini: Option<&Ini>, key: &str, default: bool) -> bool {
         _ => default,
     }
 }
+
+fn get_all(ini: Option<&Ini>, key: &str) -> Vec<String> {
+    ini.and_then(|x| x.section(Some("config")))
+        .map(|x| x.get_all(key).map(ToOwned::to_owned).collect())
+        .unwrap_or_default()
+}
@@ -0,0 +1,72 @@
+// Copyright (c) Max Kagamine
+// Licensed under the Apache License, Version 2.0
+
+use std::time::Duration;
+
+/// Parses a duration like `45`, `45s`, `500ms`, `1.5m`, or `1h30m`. A number without a unit is in
+/// seconds.
+#[must_use]
+pub fn parse(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    if value.is_empty() {
+        return None;
+    }
+
+    if let Ok(seconds) = value.parse::<f64>() {
+        return Duration::try_from_secs_f64(seconds).ok();
+    }
+
+    let mut total = Duration::ZERO;
+    let mut rest = value;
+
+    while !rest.is_empty() {
+        let number_len = rest
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .unwrap_or(rest.len());
+        let unit_len = rest[number_len..]
+            .find(|c: char| c.is_ascii_digit() || c == '.')
+            .unwrap_or(rest.len() - number_len);
+
+        let number: f64 = rest[..number_len].parse().ok()?;
+        let seconds = match &rest[number_len..number_len + unit_len] {
+            "ms" => number / 1000.0,
+            "s" | "sec" => number,
+            "m" | "min" => number * 60.0,
+            "h" => number * 3600.0,
+            _ => return None,
+        };
+
+        total = total.checked_add(Duration::try_from_secs_f64(seconds).ok()?)?;
+        rest = &rest[number_len + unit_len..];
+    }
+
+    Some(total)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(parse("45"), Some(Duration::from_secs(45)));
+        assert_eq!(parse("0.5"), Some(Duration::from_millis(500)));
+        assert_eq!(parse("45s"), Some(Duration::from_secs(45)));
+        assert_eq!(parse("500ms"), Some(Duration::from_millis(500)));
+        assert_eq!(parse("1.5m"), Some(Duration::from_secs(90)));
+        assert_eq!(parse("2min"), Some(Duration::from_mins(2)));
+        assert_eq!(parse(" 1h30m "), Some(Duration::from_mins(90)));
+        assert_eq!(parse("1m30s250ms"), Some(Duration::from_millis(90_250)));
+    }
+
+    #[test]
+    fn rejects_invalid_durations() {
+        assert_eq!(parse(""), None);
+        assert_eq!(parse("-5"), None);
+        assert_eq!(parse("5d"), None);
+        assert_eq!(parse("m"), None);
+        assert_eq!(parse("1.2.3s"), None);
+        assert_eq!(parse("5 s"), None);
+        assert_eq!(parse("soon"), None);
+    }
+}
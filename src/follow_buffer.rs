@@ -0,0 +1,152 @@
+// Copyright (c) Max Kagamine
+// Licensed under the Apache License, Version 2.0
+
+// `xsel --follow` copies standard input as it arrives rather than once it's been read to the end,
+// e.g. `tail -f log | xsel -f`. Input comes in arbitrary chunks, so this collects it and decides
+// what text the clipboard should hold after each one.
+
+use std::borrow::Cow;
+
+/// Accumulates input for `xsel --follow` and `--zeroflush`.
+#[derive(Debug)]
+pub struct FollowBuffer {
+    bytes: Vec<u8>,
+    zero_flush: bool,
+    flush_pending: bool,
+    changed: bool,
+}
+
+impl FollowBuffer {
+    /// Creates a buffer starting with `initial` (the existing clipboard, for `--append`). If
+    /// `zero_flush` is set, a NUL in the input starts the buffer over.
+    #[must_use]
+    pub fn new(initial: String, zero_flush: bool) -> Self {
+        Self {
+            bytes: initial.into_bytes(),
+            zero_flush,
+            flush_pending: false,
+            changed: true,
+        }
+    }
+
+    /// Appends a chunk of input. With `zero_flush`, the text after a NUL replaces the buffer, but
+    /// not until some actually arrives, so that the clipboard isn't left empty in between.
+    pub fn push(&mut self, input: &[u8]) {
+        if !self.zero_flush {
+            self.bytes.extend_from_slice(input);
+            self.changed |= !input.is_empty();
+            return;
+        }
+
+        for (i, segment) in input.split(|&x| x == 0).enumerate() {
+            if i > 0 {
+                self.flush_pending = true;
+            }
+
+            if !segment.is_empty() {
+                if self.flush_pending {
+                    self.bytes.clear();
+                    self.flush_pending = false;
+                }
+                self.bytes.extend_from_slice(segment);
+                self.changed = true;
+            }
+        }
+    }
+
+    /// Returns the text to copy if it's changed since the last call. A character that's only been
+    /// partially received is held back until the rest of it arrives; invalid UTF-8 is replaced.
+    pub fn take_update(&mut self) -> Option<Cow<'_, str>> {
+        if !std::mem::take(&mut self.changed) {
+            return None;
+        }
+
+        Some(String::from_utf8_lossy(
+            &self.bytes[..complete_len(&self.bytes)],
+        ))
+    }
+}
+
+/// Gets the length of `bytes` without an incomplete UTF-8 sequence at the end, if there is one.
+fn complete_len(bytes: &[u8]) -> usize {
+    // An incomplete sequence is at most three bytes, starting with a byte that isn't 10xxxxxx
+    let start = bytes.len().saturating_sub(3);
+    match bytes[start..].iter().rposition(|&x| x & 0xc0 != 0x80) {
+        Some(i)
+            if std::str::from_utf8(&bytes[start + i..])
+                .is_err_and(|err| err.error_len().is_none()) =>
+        {
+            start + i
+        }
+        _ => bytes.len(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn update(buffer: &mut FollowBuffer) -> Option<String> {
+        buffer.take_update().map(Cow::into_owned)
+    }
+
+    #[test]
+    fn appends_input() {
+        let mut buffer = FollowBuffer::new("existing ".to_string(), false);
+        assert_eq!(update(&mut buffer).as_deref(), Some("existing "));
+        assert_eq!(update(&mut buffer), None);
+
+        buffer.push(b"line 1\n");
+        buffer.push(b"line 2\n");
+        assert_eq!(
+            update(&mut buffer).as_deref(),
+            Some("existing line 1\nline 2\n")
+        );
+
+        buffer.push(b"");
+        assert_eq!(update(&mut buffer), None);
+    }
+
+    #[test]
+    fn holds_back_partial_characters() {
+        let mut buffer = FollowBuffer::new(String::new(), false);
+        let bytes = "鏡音リン".as_bytes();
+
+        buffer.push(&bytes[..4]);
+        assert_eq!(update(&mut buffer).as_deref(), Some("鏡"));
+
+        buffer.push(&bytes[4..]);
+        assert_eq!(update(&mut buffer).as_deref(), Some("鏡音リン"));
+    }
+
+    #[test]
+    fn replaces_invalid_utf8() {
+        let mut buffer = FollowBuffer::new(String::new(), false);
+        buffer.push(b"a\xffb\xe3\x83");
+        assert_eq!(update(&mut buffer).as_deref(), Some("a\u{fffd}b"));
+    }
+
+    #[test]
+    fn zero_flush_starts_over_after_nul() {
+        let mut buffer = FollowBuffer::new("existing".to_string(), true);
+
+        buffer.push(b"one");
+        assert_eq!(update(&mut buffer).as_deref(), Some("existingone"));
+
+        buffer.push(b"\0");
+        assert_eq!(update(&mut buffer), None, "should keep the old text");
+
+        buffer.push(b"two\0\0three");
+        assert_eq!(update(&mut buffer).as_deref(), Some("three"));
+
+        buffer.push(b"\0four\0");
+        assert_eq!(update(&mut buffer).as_deref(), Some("four"));
+    }
+
+    #[test]
+    fn nul_is_kept_without_zero_flush() {
+        let mut buffer = FollowBuffer::new(String::new(), false);
+        buffer.push(b"a\0b");
+        assert_eq!(update(&mut buffer).as_deref(), Some("a\0b"));
+    }
+}
@@ -0,0 +1,640 @@
+// Copyright (c) Max Kagamine
+// Licensed under the Apache License, Version 2.0
+
+#![cfg(unix)]
+
+// Implements the "trashing" half of the Freedesktop.org trash spec:
+// https://specifications.freedesktop.org/trash/1.0/
+//
+// Files on the same file system as the home trash ($XDG_DATA_HOME/Trash) go there. Files on any
+// other mount (a second VHD, /mnt/wsl, a bind mount, tmpfs...) go in a trash can at the top of that
+// mount instead, as moving them to the home trash would mean copying: either $topdir/.Trash/$uid,
+// if the admin created a $topdir/.Trash with the sticky bit set, or else $topdir/.Trash-$uid. If
+// neither can be used (e.g. the mount is read-only, or we don't have permission to create the
+// directory), the `TrashFallback` decides what happens.
+//
+// `Trash` is shared between the worker threads in `recycle_backend`, so the home trash and the
+// trash can for each mount are only looked up (and created) once per run, not once per item.
+
+use crate::remove_tree::{self, REMOVE_RECURSIVE};
+use std::{
+    collections::HashMap,
+    env,
+    error::Error,
+    fmt::Display,
+    fs::{self, DirBuilder, File, OpenOptions},
+    io::{self, ErrorKind, Read, Write},
+    os::unix::{
+        ffi::OsStrExt,
+        fs::{DirBuilderExt, MetadataExt, PermissionsExt, symlink},
+    },
+    path::{Path, PathBuf},
+    sync::{Mutex, PoisonError},
+};
+
+/// What to do with items on a mount that doesn't have (and can't have) its own trash can. Set by
+/// `linux_trash_fallback` in the config.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TrashFallback {
+    /// Copy the item into the home trash, then delete the original. Never done for tmpfs.
+    #[default]
+    Copy,
+    /// Delete the item permanently.
+    Delete,
+    /// Leave the item alone and return `TrashError::NoTrash`.
+    Refuse,
+}
+
+impl TrashFallback {
+    #[must_use]
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "copy" => Some(Self::Copy),
+            "delete" => Some(Self::Delete),
+            "refuse" => Some(Self::Refuse),
+            _ => None,
+        }
+    }
+}
+
+/// Where an item ended up.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Trashed {
+    /// Moved (or copied) into a trash can; the path is its `.trashinfo` file.
+    InTrash(PathBuf),
+    /// Deleted permanently due to `TrashFallback::Delete`.
+    Deleted,
+}
+
+#[derive(Debug)]
+pub enum TrashError {
+    /// The item's mount has no usable trash can, and the fallback is `Refuse`. Contains the mount
+    /// point.
+    NoTrash(PathBuf),
+    /// The item is on a tmpfs without a usable trash can, which we won't copy into the home trash.
+    /// Contains the mount point.
+    Tmpfs(PathBuf),
+    /// The item was copied into the home trash, but deleting the original failed.
+    CopiedButNotDeleted(remove_tree::RemoveError),
+    Io(io::Error),
+}
+
+impl From<io::Error> for TrashError {
+    fn from(value: io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+
+impl Display for TrashError {
+    #[rustfmt::skip]
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NoTrash(mount) => write!(f, "There's no trash can on its file system ({}), and linux_trash_fallback is set to refuse.", mount.display()),
+            Self::Tmpfs(mount) => write!(f, "It's on a tmpfs ({}) with no trash can, which won't be copied to the home trash.", mount.display()),
+            Self::CopiedButNotDeleted(err) => write!(f, "Copied it to the trash but failed to delete the original: {err}"),
+            Self::Io(err) => Display::fmt(err, f),
+        }
+    }
+}
+
+impl Error for TrashError {}
+
+/// A line from /proc/self/mountinfo.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mount {
+    pub mount_point: PathBuf,
+    pub fs_type: String,
+}
+
+/// Parses the contents of /proc/self/mountinfo.
+#[must_use]
+pub fn parse_mountinfo(text: &str) -> Vec<Mount> {
+    text.lines()
+        .filter_map(|line| {
+            let (fields, rest) = line.split_once(" - ")?;
+            Some(Mount {
+                mount_point: PathBuf::from(unescape_mountinfo(fields.split(' ').nth(4)?)),
+                fs_type: rest.split(' ').next()?.to_owned(),
+            })
+        })
+        .collect()
+}
+
+/// Mount points have spaces, tabs, newlines, and backslashes escaped as octal (e.g. `\040`).
+fn unescape_mountinfo(value: &str) -> String {
+    let mut bytes = Vec::with_capacity(value.len());
+    let mut rest = value.as_bytes();
+
+    while let Some((&b, after)) = rest.split_first() {
+        if b == b'\\'
+            && let Some(octal) = after.get(..3)
+            && let Some(decoded) = std::str::from_utf8(octal)
+                .ok()
+                .and_then(|x| u8::from_str_radix(x, 8).ok())
+        {
+            bytes.push(decoded);
+            rest = &after[3..];
+        } else {
+            bytes.push(b);
+            rest = after;
+        }
+    }
+
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+/// Returns the mount containing `path` (which should be absolute with symlinks resolved), i.e.
+/// the one with the longest mount point that's a prefix of it. Later mounts shadow earlier ones.
+#[must_use]
+pub fn find_mount<'a>(mounts: &'a [Mount], path: &Path) -> Option<&'a Mount> {
+    // max_by_key returns the last of equal elements
+    mounts
+        .iter()
+        .filter(|x| path.starts_with(&x.mount_point))
+        .max_by_key(|x| x.mount_point.as_os_str().len())
+}
+
+/// Percent-encodes a path for the `Path=` key, as in a URL (but without the `file://`).
+#[must_use]
+pub fn encode_path(path: &Path) -> String {
+    use std::fmt::Write;
+
+    let mut encoded = String::new();
+    for &b in path.as_os_str().as_bytes() {
+        if b.is_ascii_alphanumeric() || b"-._~/".contains(&b) {
+            encoded.push(b as char);
+        } else {
+            let _ = write!(encoded, "%{b:02X}");
+        }
+    }
+    encoded
+}
+
+/// Sends items to the trash, resolving (and caching) the trash can for each mount.
+pub struct Trash {
+    /// The home trash, `$XDG_DATA_HOME/Trash`.
+    home: PathBuf,
+    mounts: Vec<Mount>,
+    uid: u32,
+    fallback: TrashFallback,
+    /// Whether to use trash cans at the top of other mounts, or always fall back.
+    use_topdir: bool,
+    /// The home trash's mount point, once it's been created.
+    home_mount: Mutex<Option<PathBuf>>,
+    /// Mount point -> that mount's trash can, or `None` if it can't have one. Keyed by mount rather
+    /// than device, as bind mounts of the same device can't be renamed across.
+    topdir_cans: Mutex<HashMap<PathBuf, Option<PathBuf>>>,
+}
+
+impl Trash {
+    /// # Errors
+    ///
+    /// Neither `$XDG_DATA_HOME` nor `$HOME` is set, or /proc/self/mountinfo couldn't be read.
+    pub fn new(fallback: TrashFallback) -> io::Result<Self> {
+        let home_trash = env::var_os("XDG_DATA_HOME")
+            .map(PathBuf::from)
+            .filter(|x| x.is_absolute())
+            .or_else(|| env::var_os("HOME").map(|x| Path::new(&x).join(".local/share")))
+            .ok_or(ErrorKind::NotFound)?
+            .join("Trash");
+
+        Ok(Self {
+            home: home_trash,
+            mounts: parse_mountinfo(&fs::read_to_string("/proc/self/mountinfo")?),
+            // SAFETY: Always succeeds
+            uid: unsafe { libc::geteuid() },
+            fallback,
+            use_topdir: true,
+            home_mount: Mutex::new(None),
+            topdir_cans: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Skips the trash cans at the top of other mounts, so that items not on the home trash's
+    /// mount always go to the fallback.
+    #[must_use]
+    pub fn without_topdir(mut self) -> Self {
+        self.use_topdir = false;
+        self
+    }
+
+    /// Moves the file or directory at `path` into the trash. Symlinks are not followed (the link
+    /// itself is trashed). If the item has to be copied, `progress` is called periodically with
+    /// the number of bytes copied so far and the total. Safe to call from multiple threads.
+    ///
+    /// # Errors
+    ///
+    /// See `TrashError`. Returns `Io` with `ErrorKind::NotFound` if the item doesn't exist.
+    pub fn trash(
+        &self,
+        path: &Path,
+        progress: impl FnMut(u64, u64),
+    ) -> Result<Trashed, TrashError> {
+        fs::symlink_metadata(path)?;
+
+        let original = resolve_dirname(path)?;
+        let parent = original.parent().unwrap_or(&original);
+
+        let home_trash_mount = self.home_trash_mount()?;
+        let mount = find_mount(&self.mounts, parent)
+            .cloned()
+            .ok_or(io::Error::from(ErrorKind::NotFound))?;
+
+        if mount.mount_point == home_trash_mount {
+            let info_path = move_to_trash(&self.home, &original, &original)?;
+            return Ok(Trashed::InTrash(info_path));
+        }
+
+        if self.use_topdir
+            && let Some(trash_can) = self.trash_can_for(&mount.mount_point)
+        {
+            // The spec says paths in a $topdir trash should be relative to $topdir
+            let relative = original
+                .strip_prefix(&mount.mount_point)
+                .unwrap_or(&original);
+            let info_path = move_to_trash(&trash_can, &original, relative)?;
+            return Ok(Trashed::InTrash(info_path));
+        }
+
+        match self.fallback {
+            TrashFallback::Refuse => Err(TrashError::NoTrash(mount.mount_point)),
+            TrashFallback::Copy if mount.fs_type == "tmpfs" => {
+                Err(TrashError::Tmpfs(mount.mount_point))
+            }
+            TrashFallback::Copy => {
+                let info_path = copy_to_trash(&self.home, &original, progress)?;
+                delete(&original).map_err(TrashError::CopiedButNotDeleted)?;
+                Ok(Trashed::InTrash(info_path))
+            }
+            TrashFallback::Delete => {
+                delete(&original).map_err(|err| match err {
+                    remove_tree::RemoveError::Io(err) => TrashError::Io(err),
+                    err => TrashError::Io(io::Error::other(err.to_string())),
+                })?;
+                Ok(Trashed::Deleted)
+            }
+        }
+    }
+
+    /// Creates the home trash if necessary and returns its mount point.
+    fn home_trash_mount(&self) -> io::Result<PathBuf> {
+        let mut cached = self
+            .home_mount
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        if let Some(mount) = &*cached {
+            return Ok(mount.clone());
+        }
+
+        create_trash_dirs(&self.home)?;
+        let home_trash = fs::canonicalize(&self.home)?;
+        let mount = find_mount(&self.mounts, &home_trash)
+            .map(|x| x.mount_point.clone())
+            .ok_or(ErrorKind::NotFound)?;
+        *cached = Some(mount.clone());
+        Ok(mount)
+    }
+
+    fn trash_can_for(&self, topdir: &Path) -> Option<PathBuf> {
+        // Held while creating the trash can, so that other threads wait for it rather than racing
+        let mut cans = self
+            .topdir_cans
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        if let Some(cached) = cans.get(topdir) {
+            return cached.clone();
+        }
+
+        let uid = self.uid;
+        let trash_can = admin_trash_can(topdir, uid).or_else(|| user_trash_can(topdir, uid));
+        cans.insert(topdir.to_path_buf(), trash_can.clone());
+        trash_can
+    }
+}
+
+/// Makes `path` absolute and resolves symlinks in its dirname only, since the basename is what's
+/// being trashed (or deleted).
+///
+/// # Errors
+///
+/// The path has no basename, or the parent directory doesn't exist.
+pub fn resolve_dirname(path: &Path) -> io::Result<PathBuf> {
+    let absolute = std::path::absolute(path)?;
+    let (Some(parent), Some(name)) = (absolute.parent(), absolute.file_name()) else {
+        return Err(ErrorKind::InvalidInput.into());
+    };
+    Ok(fs::canonicalize(parent)?.join(name))
+}
+
+/// Method (1): `$topdir/.Trash/$uid`, if `$topdir/.Trash` is a real directory with the sticky bit
+/// set. Otherwise the spec says not to use it, as anyone could have created it.
+fn admin_trash_can(topdir: &Path, uid: u32) -> Option<PathBuf> {
+    let shared = topdir.join(".Trash");
+    let stat = fs::symlink_metadata(&shared).ok()?;
+    if !stat.is_dir() || stat.permissions().mode() & libc::S_ISVTX == 0 {
+        return None;
+    }
+
+    let trash_can = shared.join(uid.to_string());
+    create_trash_can(&trash_can, uid).ok()?;
+    Some(trash_can)
+}
+
+/// Method (2): `$topdir/.Trash-$uid`, created if it doesn't exist.
+fn user_trash_can(topdir: &Path, uid: u32) -> Option<PathBuf> {
+    let trash_can = topdir.join(format!(".Trash-{uid}"));
+    create_trash_can(&trash_can, uid).ok()?;
+    Some(trash_can)
+}
+
+fn is_own_dir(path: &Path, uid: u32) -> bool {
+    fs::symlink_metadata(path).is_ok_and(|x| x.is_dir() && x.uid() == uid)
+}
+
+/// Creates a trash can in a directory others can write to. Each level is created non-recursively
+/// and checked to be a real directory owned by `uid` before going into it, so that a symlink
+/// planted in place of the trash can isn't followed.
+fn create_trash_can(trash_can: &Path, uid: u32) -> io::Result<()> {
+    let mut builder = DirBuilder::new();
+    builder.mode(0o700);
+
+    for dir in [
+        trash_can.to_path_buf(),
+        trash_can.join("files"),
+        trash_can.join("info"),
+    ] {
+        match builder.create(&dir) {
+            Err(err) if err.kind() != ErrorKind::AlreadyExists => return Err(err),
+            _ => {}
+        }
+
+        if !is_own_dir(&dir, uid) {
+            return Err(ErrorKind::PermissionDenied.into());
+        }
+    }
+
+    Ok(())
+}
+
+fn create_trash_dirs(trash_can: &Path) -> io::Result<()> {
+    let mut builder = DirBuilder::new();
+    builder.recursive(true).mode(0o700);
+    builder.create(trash_can.join("files"))?;
+    builder.create(trash_can.join("info"))
+}
+
+/// Creates a `.trashinfo` file for `original` with a unique name, returning the name and the
+/// info file's path. Creating the info file first with `O_EXCL` is how the spec avoids races
+/// between applications trashing files with the same name.
+fn create_info_file(
+    trash_can: &Path,
+    original: &Path,
+    info_path_key: &Path,
+) -> io::Result<(PathBuf, PathBuf)> {
+    let name = original.file_name().ok_or(ErrorKind::InvalidInput)?;
+
+    for n in 1.. {
+        let mut candidate = name.to_owned();
+        if n > 1 {
+            candidate.push(format!(".{n}"));
+        }
+
+        let info_path = trash_can.join("info").join({
+            let mut x = candidate.clone();
+            x.push(".trashinfo");
+            x
+        });
+        let data_path = trash_can.join("files").join(&candidate);
+
+        if fs::symlink_metadata(&data_path).is_ok() {
+            continue;
+        }
+
+        let mut file = match OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&info_path)
+        {
+            Ok(file) => file,
+            Err(err) if err.kind() == ErrorKind::AlreadyExists => continue,
+            Err(err) => return Err(err),
+        };
+
+        write!(
+            file,
+            "[Trash Info]\nPath={}\nDeletionDate={}\n",
+            encode_path(info_path_key),
+            local_timestamp()
+        )?;
+
+        return Ok((data_path, info_path));
+    }
+
+    unreachable!()
+}
+
+/// The trash can must already exist (it's created when it's first looked up).
+fn move_to_trash(trash_can: &Path, original: &Path, info_path_key: &Path) -> io::Result<PathBuf> {
+    let (data_path, info_path) = create_info_file(trash_can, original, info_path_key)?;
+
+    if let Err(err) = fs::rename(original, &data_path) {
+        let _ = fs::remove_file(&info_path);
+        return Err(err);
+    }
+
+    Ok(info_path)
+}
+
+fn copy_to_trash(
+    trash_can: &Path,
+    original: &Path,
+    mut progress: impl FnMut(u64, u64),
+) -> io::Result<PathBuf> {
+    create_trash_dirs(trash_can)?;
+    let (data_path, info_path) = create_info_file(trash_can, original, original)?;
+
+    let total = crate::recycle_size::tree_size(original, u64::MAX);
+    let mut copied = 0;
+
+    if let Err(err) = copy_tree(original, &data_path, &mut |n| {
+        copied += n;
+        progress(copied, total);
+    }) {
+        // Clean up the partial copy
+        let _ = delete(&data_path);
+        let _ = fs::remove_file(&info_path);
+        return Err(err);
+    }
+
+    Ok(info_path)
+}
+
+/// Copies a file or directory tree without following symlinks, preserving permissions.
+pub(crate) fn copy_tree(from: &Path, to: &Path, progress: &mut impl FnMut(u64)) -> io::Result<()> {
+    let stat = fs::symlink_metadata(from)?;
+
+    if stat.is_symlink() {
+        symlink(fs::read_link(from)?, to)?;
+    } else if stat.is_dir() {
+        fs::create_dir(to)?;
+        for entry in fs::read_dir(from)? {
+            let entry = entry?;
+            copy_tree(&entry.path(), &to.join(entry.file_name()), progress)?;
+        }
+        fs::set_permissions(to, stat.permissions())?;
+    } else if stat.is_file() {
+        let mut reader = File::open(from)?;
+        let mut writer = File::create(to)?;
+        let mut buffer = vec![0; 1024 * 1024];
+        loop {
+            let n = reader.read(&mut buffer)?;
+            if n == 0 {
+                break;
+            }
+            writer.write_all(&buffer[..n])?;
+            progress(n as u64);
+        }
+        fs::set_permissions(to, stat.permissions())?;
+    } else {
+        return Err(io::Error::new(
+            ErrorKind::Unsupported,
+            format!("Can't copy special file \"{}\".", from.display()),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Deletes a file or directory tree, returning the first error.
+pub(crate) fn delete(path: &Path) -> Result<(), remove_tree::RemoveError> {
+    let mut first_error = None;
+    let result =
+        remove_tree::remove_with_callback([path.to_string_lossy()], REMOVE_RECURSIVE, |_, err| {
+            if first_error.is_none() {
+                first_error = err;
+            }
+        });
+    match (result, first_error) {
+        (Ok(()), _) => Ok(()),
+        (Err(_), Some(err)) | (Err(err), None) => Err(err),
+    }
+}
+
+/// The current local time as `YYYY-MM-DDThh:mm:ss`, as required for `DeletionDate`.
+fn local_timestamp() -> String {
+    // SAFETY: localtime_r is given valid pointers, and `tm` is only read if it succeeds
+    unsafe {
+        let now = libc::time(std::ptr::null_mut());
+        let mut tm = std::mem::MaybeUninit::<libc::tm>::uninit();
+        if libc::localtime_r(&raw const now, tm.as_mut_ptr()).is_null() {
+            return String::new();
+        }
+        let tm = tm.assume_init();
+        format!(
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+            tm.tm_year + 1900,
+            tm.tm_mon + 1,
+            tm.tm_mday,
+            tm.tm_hour,
+            tm.tm_min,
+            tm.tm_sec
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_mountinfo() {
+        let mounts = parse_mountinfo(
+            "\
+65 1 8:48 / / rw,relatime - ext4 /dev/sdd rw,discard,errors=remount-ro,data=ordered
+72 65 0:51 / /mnt/c rw,noatime - 9p C:\\134 rw,aname=drvfs;path=C:\\
+91 65 0:60 / /mnt/my\\040disk rw,relatime shared:1 - ext4 /dev/sde rw
+92 65 0:61 / /tmp rw,nosuid,nodev - tmpfs tmpfs rw
+",
+        );
+
+        assert_eq!(
+            mounts,
+            vec![
+                Mount {
+                    mount_point: "/".into(),
+                    fs_type: "ext4".into()
+                },
+                Mount {
+                    mount_point: "/mnt/c".into(),
+                    fs_type: "9p".into()
+                },
+                Mount {
+                    mount_point: "/mnt/my disk".into(),
+                    fs_type: "ext4".into()
+                },
+                Mount {
+                    mount_point: "/tmp".into(),
+                    fs_type: "tmpfs".into()
+                },
+            ]
+        );
+
+        let find = |path: &str| find_mount(&mounts, Path::new(path)).map(|x| &x.mount_point);
+        assert_eq!(find("/home/rin/foo"), Some(&"/".into()));
+        assert_eq!(find("/mnt/my disk/foo"), Some(&"/mnt/my disk".into()));
+        assert_eq!(find("/tmpfoo"), Some(&"/".into()));
+        assert_eq!(find("/tmp"), Some(&"/tmp".into()));
+    }
+
+    #[test]
+    fn later_mounts_shadow_earlier_ones() {
+        let mounts = parse_mountinfo(
+            "\
+1 0 0:1 / / rw - ext4 /dev/sda rw
+2 1 0:2 / /mnt rw - ext4 /dev/sdb rw
+3 1 0:3 / /mnt rw - tmpfs tmpfs rw
+",
+        );
+        assert_eq!(
+            find_mount(&mounts, Path::new("/mnt/x")).map(|x| x.fs_type.as_str()),
+            Some("tmpfs")
+        );
+    }
+
+    #[test]
+    fn refuses_symlinked_trash_cans() {
+        let topdir = env::temp_dir().join("wsl-tools-freedesktop-trash-symlink");
+        let _ = fs::remove_dir_all(&topdir);
+        let target = topdir.join("target");
+        fs::create_dir_all(&target).unwrap();
+        let uid = unsafe { libc::getuid() };
+
+        symlink(&target, topdir.join(format!(".Trash-{uid}"))).unwrap();
+        assert_eq!(user_trash_can(&topdir, uid), None);
+
+        fs::create_dir(topdir.join(".Trash")).unwrap();
+        fs::set_permissions(topdir.join(".Trash"), fs::Permissions::from_mode(0o1777)).unwrap();
+        symlink(&target, topdir.join(".Trash").join(uid.to_string())).unwrap();
+        assert_eq!(admin_trash_can(&topdir, uid), None);
+
+        assert!(
+            fs::read_dir(&target).unwrap().next().is_none(),
+            "should not have created anything through the symlinks"
+        );
+
+        fs::remove_file(topdir.join(format!(".Trash-{uid}"))).unwrap();
+        let trash_can = user_trash_can(&topdir, uid).unwrap();
+        assert!(trash_can.join("files").is_dir() && trash_can.join("info").is_dir());
+
+        fs::remove_dir_all(&topdir).unwrap();
+    }
+
+    #[test]
+    fn encodes_paths() {
+        assert_eq!(
+            encode_path(Path::new("/home/rin/foo bar/ミク.txt")),
+            "/home/rin/foo%20bar/%E3%83%9F%E3%82%AF.txt"
+        );
+        assert_eq!(encode_path(Path::new("a%b")), "a%25b");
+    }
+}
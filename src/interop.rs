@@ -0,0 +1,46 @@
+// Copyright (c) Max Kagamine
+// Licensed under the Apache License, Version 2.0
+
+#![cfg(unix)]
+
+use std::{env, fs, os::unix::fs::PermissionsExt, path::Path, process::Command};
+
+// WSL runs Windows executables through a binfmt_misc handler, which is missing or disabled if
+// interop is turned off in wsl.conf (or with `echo 0 > /proc/sys/fs/binfmt_misc/WSLInterop`).
+// Newer versions of WSL register it as WSLInterop-late instead.
+const BINFMT_HANDLERS: [&str; 2] = [
+    "/proc/sys/fs/binfmt_misc/WSLInterop",
+    "/proc/sys/fs/binfmt_misc/WSLInterop-late",
+];
+
+/// Checks whether Windows executables can be run.
+#[must_use]
+pub fn is_enabled() -> bool {
+    BINFMT_HANDLERS.iter().any(|handler| {
+        fs::read_to_string(handler).is_ok_and(|x| x.lines().next() == Some("enabled"))
+    })
+}
+
+/// Checks whether the command's program exists: as a path if it contains a slash, otherwise in
+/// `$PATH`, same as `Command::spawn` would look for it.
+#[must_use]
+pub fn command_exists(cmd: &Command) -> bool {
+    let program = Path::new(cmd.get_program());
+    if program.components().count() > 1 {
+        return is_executable(program);
+    }
+
+    env::var_os("PATH")
+        .is_some_and(|path| env::split_paths(&path).any(|dir| is_executable(&dir.join(program))))
+}
+
+/// Checks that interop is enabled and the command (i.e. one from `exe_command!`) exists, so that
+/// the caller can bail out before doing anything instead of failing halfway through.
+#[must_use]
+pub fn is_available(cmd: &Command) -> bool {
+    is_enabled() && command_exists(cmd)
+}
+
+fn is_executable(path: &Path) -> bool {
+    fs::metadata(path).is_ok_and(|x| x.is_file() && x.permissions().mode() & 0o111 != 0)
+}
@@ -1,13 +1,37 @@
 // Copyright (c) Max Kagamine
 // Licensed under the Apache License, Version 2.0
 
+pub mod ansi;
 pub mod clipboard;
+pub mod clipboard_files;
+pub mod clipboard_format;
+pub mod clipboard_html;
+pub mod clipboard_image;
+pub mod clipboard_watch;
 pub mod config;
+pub mod duration;
+pub mod follow_buffer;
+pub mod freedesktop_trash;
+pub mod interop;
 pub mod macros;
 pub mod message_box;
+pub mod path_list;
 pub mod process;
+pub mod recycle_backend;
 pub mod recycle_bin;
+pub mod recycle_bin_index;
+pub mod recycle_git;
+pub mod recycle_journal;
+pub mod recycle_protect;
+pub mod recycle_report;
+pub mod recycle_result;
+pub mod recycle_safe;
+pub mod recycle_size;
+pub mod remove_tree;
+pub mod selection;
+pub mod text_encoding;
 pub mod vscode;
+pub mod worker_pool;
 pub mod wslpath;
 
 mod hglobal;
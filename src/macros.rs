@@ -36,19 +36,48 @@ macro_rules! exe_command {
 #[cfg(unix)]
 macro_rules! exe_exec {
     ($cmd:ident) => {
-        use std::{io::ErrorKind, os::unix::process::ExitStatusExt};
-
-        let name = env!("CARGO_BIN_NAME");
+        let mut child = $crate::exe_spawn!($cmd);
+        $crate::exe_exit!(child.wait());
+    };
+}
 
-        std::process::exit(match $cmd.status() {
+/// Spawns the command, evaluating to the `Child`. If the command fails to execute, echoes an
+/// appropriate error message to stderr and exits with a code the shell would use.
+#[macro_export]
+#[cfg(unix)]
+macro_rules! exe_spawn {
+    ($cmd:ident) => {
+        match $cmd.spawn() {
+            Ok(child) => child,
             Err(err) => {
-                if err.kind() == ErrorKind::NotFound {
+                let name = env!("CARGO_BIN_NAME");
+                std::process::exit(if err.kind() == std::io::ErrorKind::NotFound {
                     eprintln!("{name}: could not find '{name}.exe'");
                     127
                 } else {
                     eprintln!("{name}: failed to start '{name}.exe': {err}");
                     126
-                }
+                });
+            }
+        }
+    };
+}
+
+/// Exits with the exit code from the given `io::Result<ExitStatus>` (i.e. the result of waiting on
+/// a child spawned with `exe_spawn!`). If the child was killed, echoes an appropriate error message
+/// to stderr and exits with a code the shell would use.
+#[macro_export]
+#[cfg(unix)]
+macro_rules! exe_exit {
+    ($status:expr) => {
+        use std::os::unix::process::ExitStatusExt;
+
+        let name = env!("CARGO_BIN_NAME");
+
+        std::process::exit(match $status {
+            Err(err) => {
+                eprintln!("{name}: failed to wait for '{name}.exe': {err}");
+                126
             }
             Ok(status) => {
                 if let Some(code) = status.code() {
@@ -0,0 +1,93 @@
+// Copyright (c) Max Kagamine
+// Licensed under the Apache License, Version 2.0
+
+// `recycle --files-from` reads its paths from a file or stdin instead of argv, so that large lists
+// (e.g. from `find`) don't run into ARG_MAX or get split into batches by xargs. The Linux side
+// uses the same format to hand the list to `recycle.exe` over a pipe.
+
+use std::{
+    fs::File,
+    io::{self, ErrorKind, Read},
+};
+
+/// Reads a list of paths from `file`, or stdin if `-`. See `parse`.
+///
+/// # Errors
+///
+/// The file couldn't be read or isn't valid UTF-8.
+pub fn read(file: &str, null: bool) -> io::Result<Vec<String>> {
+    let mut bytes = Vec::new();
+    if file == "-" {
+        io::stdin().lock().read_to_end(&mut bytes)?;
+    } else {
+        File::open(file)?.read_to_end(&mut bytes)?;
+    }
+
+    parse(&bytes, null)
+}
+
+/// Parses a list of paths separated by newlines (LF or CRLF), or NULs if `null`, as output by
+/// `find -print0`. Empty entries are ignored.
+///
+/// # Errors
+///
+/// The list isn't valid UTF-8.
+pub fn parse(bytes: &[u8], null: bool) -> io::Result<Vec<String>> {
+    let text = str::from_utf8(bytes).map_err(|err| io::Error::new(ErrorKind::InvalidData, err))?;
+
+    let paths: Box<dyn Iterator<Item = &str>> = if null {
+        Box::new(text.split('\0'))
+    } else {
+        Box::new(text.lines())
+    };
+
+    Ok(paths
+        .filter(|x| !x.is_empty())
+        .map(ToOwned::to_owned)
+        .collect())
+}
+
+/// Joins paths into a NUL-separated list for `parse`.
+#[must_use]
+pub fn join_null<T: AsRef<str>>(paths: &[T]) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    for path in paths {
+        bytes.extend_from_slice(path.as_ref().as_bytes());
+        bytes.push(0);
+    }
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_lines() {
+        assert_eq!(
+            parse(
+                b"foo\r\nbar baz\n\n/mnt/c/\xE3\x83\xAA\xE3\x83\xB3.txt\n",
+                false
+            )
+            .unwrap(),
+            vec!["foo", "bar baz", "/mnt/c/リン.txt"]
+        );
+    }
+
+    #[test]
+    fn parses_nul_separated() {
+        assert_eq!(
+            parse(b"new\nline\0\0 space \0last", true).unwrap(),
+            vec!["new\nline", " space ", "last"]
+        );
+
+        let paths = [r"C:\foo", "bar\nbaz"];
+        assert_eq!(parse(&join_null(&paths), true).unwrap(), paths);
+    }
+
+    #[test]
+    fn rejects_invalid_utf8() {
+        let err = parse(b"foo\n\xFF\n", false).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+    }
+}
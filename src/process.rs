@@ -3,10 +3,20 @@
 
 #![cfg(windows)]
 
-use anyhow::Result;
-use std::{ffi::OsStr, os::windows::ffi::OsStrExt};
+use anyhow::{Context, Result};
+use std::{
+    ffi::OsStr,
+    os::windows::{ffi::OsStrExt, io::AsRawHandle, process::CommandExt},
+    process::{Command, Stdio},
+};
 use windows::Win32::{
-    System::Com::{COINIT_APARTMENTTHREADED, COINIT_DISABLE_OLE1DDE, CoInitializeEx},
+    Foundation::{
+        ERROR_ACCESS_DENIED, HANDLE, HANDLE_FLAG_INHERIT, HANDLE_FLAGS, SetHandleInformation,
+    },
+    System::{
+        Com::{COINIT_APARTMENTTHREADED, COINIT_DISABLE_OLE1DDE, CoInitializeEx},
+        Threading::{CREATE_BREAKAWAY_FROM_JOB, CREATE_NEW_PROCESS_GROUP, DETACHED_PROCESS},
+    },
     UI::{
         Shell::{SEE_MASK_FLAG_NO_UI, SEE_MASK_NOASYNC, SHELLEXECUTEINFOW, ShellExecuteExW},
         WindowsAndMessaging::SW_SHOWNORMAL,
@@ -61,3 +71,46 @@ pub fn shell_execute(path: &str, verb: Option<&str>) -> Result<()> {
         Ok(())
     }
 }
+
+/// Starts a process in the background that keeps running after we (and the shell that started us)
+/// have exited, with no console and its stdio redirected to NUL.
+///
+/// When run from WSL, we're in a job object that's torn down along with the interop session, so the
+/// process is started outside of it if the job allows that. Our own stdin/stdout/stderr are also
+/// made non-inheritable first: `CreateProcess` would otherwise hand them to the child regardless of
+/// its stdio, and the Linux side would then wait for the pipes to close until the child exits.
+///
+/// # Errors
+/// Error result contains the OS error if the process couldn't be started.
+pub fn spawn_detached(cmd: &mut Command) -> Result<()> {
+    for handle in [
+        std::io::stdin().as_raw_handle(),
+        std::io::stdout().as_raw_handle(),
+        std::io::stderr().as_raw_handle(),
+    ] {
+        // Fails harmlessly if the handle is null, e.g. when there's no stderr
+        let _ =
+            unsafe { SetHandleInformation(HANDLE(handle), HANDLE_FLAG_INHERIT.0, HANDLE_FLAGS(0)) };
+    }
+
+    cmd.stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+
+    let flags = DETACHED_PROCESS | CREATE_NEW_PROCESS_GROUP;
+    let result = cmd
+        .creation_flags((flags | CREATE_BREAKAWAY_FROM_JOB).0)
+        .spawn();
+
+    // Breaking away is denied if the job doesn't allow it, in which case we'll have to stay in it
+    let result = match result {
+        Err(err) if err.raw_os_error() == Some(ERROR_ACCESS_DENIED.0.cast_signed()) => {
+            cmd.creation_flags(flags.0).spawn()
+        }
+        result => result,
+    };
+
+    result
+        .map(|_| ())
+        .context("Failed to start background process")
+}
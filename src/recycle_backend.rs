@@ -0,0 +1,866 @@
+// Copyright (c) Max Kagamine
+// Licensed under the Apache License, Version 2.0
+
+#![cfg(unix)]
+
+// The Linux side of `recycle` decides where each path should go (`route`) and then hands the items
+// to the backend for each destination: paths on Windows drives go to `recycle.exe`, while paths in
+// the WSL filesystem are trashed or deleted Linux-side. Keeping the routing separate from the
+// backends means it can be tested without WSL or Windows, using `MockBackend`. The Linux-side
+// backends spread the items over a few threads (see `worker_pool`).
+
+use crate::{
+    freedesktop_trash::{self, Trash, TrashError, Trashed},
+    path_list,
+    recycle_result::{Backend, ErrorCode, RecycleResult},
+    remove_tree::{self, RemoveError, RemoveOptions},
+    worker_pool,
+};
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, BufRead, BufReader, ErrorKind, Write},
+    os::{linux::fs::MetadataExt, unix::process::ExitStatusExt},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    sync::Mutex,
+    thread,
+};
+
+/// A path that passed the checks in `route`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecycleItem {
+    /// The path as given on the command line.
+    pub path: String,
+    pub windows_path: String,
+    /// Whether the path is a directory (not following symlinks).
+    pub is_dir: bool,
+}
+
+pub trait RecycleBackend {
+    /// Removes the items, passing the result of each to `report`, and returns the exit code.
+    /// Backends may stop at the first failure.
+    fn recycle(&mut self, items: Vec<RecycleItem>, report: &mut dyn FnMut(RecycleResult)) -> i32;
+}
+
+impl<T: RecycleBackend + ?Sized> RecycleBackend for &mut T {
+    fn recycle(&mut self, items: Vec<RecycleItem>, report: &mut dyn FnMut(RecycleResult)) -> i32 {
+        (**self).recycle(items, report)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct RouteOptions {
+    pub force: bool,
+    pub rm: bool,
+    pub recursive: bool,
+    pub use_linux_trash: bool,
+    /// Windows interop is unavailable, so paths that would go to `recycle.exe` are checked the same
+    /// as Linux-side paths instead, for a fallback backend (see `InteropFallback`).
+    pub interop_unavailable: bool,
+}
+
+impl RouteOptions {
+    /// The backend that paths in the WSL filesystem are routed to.
+    #[must_use]
+    pub fn linux_backend(&self) -> Backend {
+        if self.use_linux_trash {
+            Backend::LinuxTrash
+        } else {
+            Backend::Permanent
+        }
+    }
+}
+
+/// What to do with paths that would go to `recycle.exe` if interop is unavailable. Set by
+/// `interop_fallback` in the config.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum InteropFallback {
+    /// Move items to the trash can at the top of their mount, e.g. `/mnt/c/.Trash-1000`.
+    MountTrash,
+    /// Move items to the home trash, copying them if necessary.
+    HomeTrash,
+    /// Exit without removing anything.
+    #[default]
+    Abort,
+}
+
+impl InteropFallback {
+    #[must_use]
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "mount-trash" => Some(Self::MountTrash),
+            "home-trash" => Some(Self::HomeTrash),
+            "abort" => Some(Self::Abort),
+            _ => None,
+        }
+    }
+}
+
+/// What `route` needs to know about a path, as gathered by `PathInfo::lookup` (or faked in tests).
+#[derive(Debug)]
+pub struct PathInfo {
+    pub path: String,
+    /// The path's Windows translation, or the error message from wslpath.
+    pub windows_path: Result<String, String>,
+    pub stat: io::Result<ItemStat>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ItemStat {
+    pub is_dir: bool,
+    /// The path refers to `/` (same device and inode).
+    pub is_root: bool,
+}
+
+impl PathInfo {
+    /// Translates the path with `symlink_to_windows` and lstats it.
+    #[must_use]
+    pub fn lookup(path: String) -> Self {
+        let windows_path = crate::wslpath::symlink_to_windows(&path).map_err(|err| err.to_string());
+
+        // Note that we use symlink_metadata (lstat) here instead of metadata (stat) to be
+        // consistent with `rm` and not follow symlinks (even if --recursive).
+        let stat = fs::symlink_metadata(&path).map(|stat| ItemStat {
+            is_dir: stat.is_dir(),
+            // https://github.com/coreutils/coreutils/blob/master/src/rm.c
+            is_root: fs::symlink_metadata("/")
+                .is_ok_and(|root| stat.st_dev() == root.st_dev() && stat.st_ino() == root.st_ino()),
+        });
+
+        Self {
+            path,
+            windows_path,
+            stat,
+        }
+    }
+}
+
+/// The checked paths, split by destination.
+#[derive(Debug, Default)]
+pub struct Routes {
+    /// Paths to hand to `recycle.exe` (or the interop fallback).
+    pub windows: Vec<RecycleItem>,
+    /// Paths in the WSL filesystem to trash or delete Linux-side.
+    pub linux: Vec<RecycleItem>,
+}
+
+/// Decides where each path should go, checking everything before anything is removed. Paths
+/// skipped due to `force` are passed to `report`.
+///
+/// With `rm` or `use_linux_trash`, paths in the WSL filesystem are removed Linux-side. Otherwise,
+/// they're sent to `recycle.exe` as well, so that the shell can display a dialog. Paths for
+/// `recycle.exe` are left for it to check, unless `interop_unavailable`.
+///
+/// # Errors
+///
+/// Returns the result to report for the first path that can't be removed (wslpath failed, doesn't
+/// exist, a directory without `recursive`, or `/`).
+pub fn route(
+    paths: impl IntoIterator<Item = PathInfo>,
+    options: &RouteOptions,
+    report: &mut dyn FnMut(RecycleResult),
+) -> Result<Routes, Box<RecycleResult>> {
+    let linux_backend = options.linux_backend();
+    let mut routes = Routes::default();
+
+    for PathInfo {
+        path,
+        windows_path,
+        stat,
+    } in paths
+    {
+        let windows_path = match windows_path {
+            Ok(x) => x,
+            Err(err) => {
+                return Err(Box::new(RecycleResult::failed(
+                    path.clone(),
+                    None,
+                    Backend::WindowsBin,
+                    ErrorCode::InvalidPath,
+                    format!("Failed to execute wslpath on \"{path}\": {err}"),
+                )));
+            }
+        };
+
+        let is_linux_side = (options.rm || options.use_linux_trash)
+            && windows_path.starts_with(r"\\wsl.localhost\");
+
+        if !is_linux_side && !options.interop_unavailable {
+            // recycle.exe does its own checks
+            routes.windows.push(RecycleItem {
+                is_dir: stat.is_ok_and(|x| x.is_dir),
+                path,
+                windows_path,
+            });
+            continue;
+        }
+
+        let backend = if is_linux_side {
+            linux_backend
+        } else {
+            Backend::LinuxTrash
+        };
+
+        let fail = |error: ErrorCode, message: String| {
+            Err(Box::new(RecycleResult::failed(
+                path.clone(),
+                Some(windows_path.clone()),
+                backend,
+                error,
+                message,
+            )))
+        };
+
+        let stat = match stat {
+            Ok(x) => x,
+            Err(err) if err.kind() == ErrorKind::NotFound => {
+                if options.force {
+                    report(RecycleResult::skipped(path, Some(windows_path), backend));
+                    continue;
+                }
+                return fail(
+                    ErrorCode::NotFound,
+                    format!("Failed to delete \"{path}\": No such file or directory."),
+                );
+            }
+            Err(err) => return fail(ErrorCode::Io, format!("Failed to stat \"{path}\": {err}")),
+        };
+
+        if stat.is_root {
+            return if path == "/" {
+                fail(
+                    ErrorCode::RefusedRoot,
+                    "Refusing to delete \"/\".".to_owned(),
+                )
+            } else {
+                fail(
+                    ErrorCode::RefusedRoot,
+                    format!("Refusing to delete \"{path}\" (same as \"/\")."),
+                )
+            };
+        }
+
+        if options.rm && !options.recursive && stat.is_dir {
+            return fail(
+                ErrorCode::IsDirectory,
+                format!("Cannot remove \"{path}\": Is a directory."),
+            );
+        }
+
+        // Queueing so that directory & exists checks happen before anything gets removed,
+        // matching the Windows-side behavior
+        let item = RecycleItem {
+            path,
+            windows_path,
+            is_dir: stat.is_dir,
+        };
+        if is_linux_side {
+            routes.linux.push(item);
+        } else {
+            routes.windows.push(item);
+        }
+    }
+
+    Ok(routes)
+}
+
+impl Routes {
+    /// Removes the Linux-side items and then, if that succeeded, the Windows-side items, returning
+    /// the exit code. `linux` is only called if there are Linux-side items, so that e.g. the trash
+    /// isn't created unnecessarily.
+    pub fn run<'a>(
+        self,
+        linux: impl FnOnce() -> io::Result<Box<dyn RecycleBackend + 'a>>,
+        windows: &mut dyn RecycleBackend,
+        report: &mut dyn FnMut(RecycleResult),
+    ) -> i32 {
+        if !self.linux.is_empty() {
+            let code = match linux() {
+                Ok(mut backend) => backend.recycle(self.linux, report),
+                Err(err) => {
+                    eprintln!("recycle: Failed to locate the trash: {err}");
+                    1
+                }
+            };
+            if code != 0 {
+                return code;
+            }
+        }
+
+        if self.windows.is_empty() {
+            0
+        } else {
+            windows.recycle(self.windows, report)
+        }
+    }
+}
+
+/// Sends items to the Recycle Bin by running `recycle.exe` with `--json`. The paths are passed over
+/// stdin, so that there's no limit on how many can be recycled in one operation.
+pub struct WindowsRecycleBin {
+    cmd: Command,
+}
+
+impl WindowsRecycleBin {
+    /// `cmd` should run `recycle.exe` with any options; the paths and `--json` are added here.
+    #[must_use]
+    pub fn new(mut cmd: Command) -> Self {
+        // We always want the results from the exe as JSON, so that they can be journaled. The
+        // caller will print them in the requested format.
+        cmd.arg("--json");
+        Self { cmd }
+    }
+}
+
+impl RecycleBackend for WindowsRecycleBin {
+    fn recycle(&mut self, items: Vec<RecycleItem>, report: &mut dyn FnMut(RecycleResult)) -> i32 {
+        let list = path_list::join_null(
+            &items
+                .iter()
+                .map(|x| x.windows_path.as_str())
+                .collect::<Vec<_>>(),
+        );
+
+        // Maps the (lowercased) Windows paths passed to the exe back to the original paths
+        let original_paths: HashMap<String, String> = items
+            .into_iter()
+            .map(|x| (x.windows_path.to_lowercase(), x.path))
+            .collect();
+
+        // Passing the paths over stdin rather than as args also prevents the interop layer from
+        // consuming our stdin & breaking read loops
+        self.cmd.args(["--files-from", "-", "--null"]);
+        self.cmd.stdin(Stdio::piped());
+        self.cmd.stdout(Stdio::piped());
+
+        let mut child = match self.cmd.spawn() {
+            Ok(child) => child,
+            Err(err) if err.kind() == ErrorKind::NotFound => {
+                eprintln!("recycle: could not find 'recycle.exe'");
+                return 127;
+            }
+            Err(err) => {
+                eprintln!("recycle: failed to start 'recycle.exe': {err}");
+                return 126;
+            }
+        };
+
+        // Write on another thread so that a full pipe can't deadlock us. Errors (e.g. if the exe
+        // exits early) will show up in the exit code.
+        let writer = child.stdin.take().map(|mut stdin| {
+            thread::spawn(move || {
+                let _ = stdin.write_all(&list);
+            })
+        });
+
+        if let Some(stdout) = child.stdout.take() {
+            for line in BufReader::new(stdout).lines() {
+                let Ok(line) = line else {
+                    break;
+                };
+
+                match serde_json::from_str::<RecycleResult>(&line) {
+                    Ok(mut result) => {
+                        if let Some(original) = result
+                            .windows_path
+                            .as_ref()
+                            .and_then(|x| original_paths.get(&x.to_lowercase()))
+                        {
+                            result.path.clone_from(original);
+                        }
+                        report(result);
+                    }
+                    Err(_) => println!("{line}"),
+                }
+            }
+        }
+
+        if let Some(writer) = writer {
+            let _ = writer.join();
+        }
+
+        match child.wait() {
+            Err(err) => {
+                eprintln!("recycle: failed to wait for 'recycle.exe': {err}");
+                126
+            }
+            Ok(status) => status.code().unwrap_or_else(|| {
+                eprintln!("recycle: 'recycle.exe' exited with {status}");
+                status.signal().unwrap_or_default().saturating_add(128)
+            }),
+        }
+    }
+}
+
+/// Moves items to the Freedesktop.org trash. See `freedesktop_trash`.
+pub struct FreedesktopTrash {
+    trash: Trash,
+    force: bool,
+    show_progress: bool,
+    workers: usize,
+}
+
+impl FreedesktopTrash {
+    #[must_use]
+    pub fn new(trash: Trash, force: bool, show_progress: bool) -> Self {
+        Self {
+            trash,
+            force,
+            show_progress,
+            workers: worker_pool::default_workers(),
+        }
+    }
+
+    /// Sets the number of threads (default `worker_pool::default_workers`).
+    #[must_use]
+    pub fn workers(mut self, workers: usize) -> Self {
+        self.workers = workers;
+        self
+    }
+}
+
+impl RecycleBackend for FreedesktopTrash {
+    fn recycle(&mut self, items: Vec<RecycleItem>, report: &mut dyn FnMut(RecycleResult)) -> i32 {
+        let resolved: Vec<PathBuf> = items
+            .iter()
+            .map(|x| {
+                freedesktop_trash::resolve_dirname(Path::new(&x.path))
+                    .unwrap_or_else(|_| PathBuf::from(&x.path))
+            })
+            .collect();
+        let chains = worker_pool::chain_nested(&resolved);
+
+        let (trash, show_progress, force) = (&self.trash, self.show_progress, self.force);
+
+        // Only one item's copy progress can be shown at a time
+        let progress_line = Mutex::new(());
+
+        let mut code = 0;
+
+        worker_pool::run_ordered(
+            &items,
+            &chains,
+            self.workers,
+            |RecycleItem { path, .. }| {
+                // Show progress if the item has to be copied to the home trash
+                let mut line = None;
+                let progress = |copied: u64, total: u64| {
+                    if show_progress {
+                        if line.is_none() {
+                            line = progress_line.try_lock().ok();
+                        }
+                        if line.is_some() {
+                            eprint!(
+                                "\rrecycle: Copying \"{path}\" to the trash... {}%",
+                                copied * 100 / total.max(1)
+                            );
+                        }
+                    }
+                };
+
+                let result = trash.trash(Path::new(path), progress);
+                if line.is_some() {
+                    eprint!("\r\x1b[K");
+                }
+                result
+            },
+            |i, result| {
+                let RecycleItem {
+                    path, windows_path, ..
+                } = items[i].clone();
+
+                let (error, message) = match result {
+                    Ok(Trashed::InTrash(info_path)) => {
+                        // Not saying "moved to trash" to be consistent with recycling
+                        report(RecycleResult {
+                            trash_path: Some(info_path.to_string_lossy().into_owned()),
+                            ..RecycleResult::removed(path, Some(windows_path), Backend::LinuxTrash)
+                        });
+                        return true;
+                    }
+                    Ok(Trashed::Deleted) => {
+                        report(RecycleResult::removed(
+                            path,
+                            Some(windows_path),
+                            Backend::Permanent,
+                        ));
+                        return true;
+                    }
+                    Err(TrashError::Io(err)) if err.kind() == ErrorKind::NotFound => {
+                        if force {
+                            report(RecycleResult::skipped(
+                                path,
+                                Some(windows_path),
+                                Backend::LinuxTrash,
+                            ));
+                            return true;
+                        }
+                        (
+                            ErrorCode::NotFound,
+                            format!("Failed to delete \"{path}\": No such file or directory."),
+                        )
+                    }
+                    Err(err @ (TrashError::NoTrash(_) | TrashError::Tmpfs(_))) => (
+                        ErrorCode::NoTrash,
+                        format!("Refusing to move \"{path}\" to trash: {err}"),
+                    ),
+                    Err(err) => (
+                        ErrorCode::Io,
+                        format!("Failed to move \"{path}\" to trash: {err}"),
+                    ),
+                };
+
+                report(RecycleResult::failed(
+                    path,
+                    Some(windows_path),
+                    Backend::LinuxTrash,
+                    error,
+                    message,
+                ));
+
+                // Stop at the first failure, as before; anything already underway is still
+                // reported
+                code = 1;
+                false
+            },
+        );
+
+        code
+    }
+}
+
+/// Deletes items permanently Linux-side. See `remove_tree`.
+pub struct PermanentDelete {
+    options: RemoveOptions,
+    workers: usize,
+}
+
+impl PermanentDelete {
+    /// `options` should include `REMOVE_RECURSIVE` if directories have already been checked for.
+    #[must_use]
+    pub fn new(options: RemoveOptions) -> Self {
+        Self {
+            options,
+            workers: worker_pool::default_workers(),
+        }
+    }
+
+    /// Sets the number of threads (default `worker_pool::default_workers`).
+    #[must_use]
+    pub fn workers(mut self, workers: usize) -> Self {
+        self.workers = workers;
+        self
+    }
+}
+
+impl RecycleBackend for PermanentDelete {
+    fn recycle(&mut self, items: Vec<RecycleItem>, report: &mut dyn FnMut(RecycleResult)) -> i32 {
+        let windows_paths: HashMap<&str, &str> = items
+            .iter()
+            .map(|x| (x.path.as_str(), x.windows_path.as_str()))
+            .collect();
+
+        let result = remove_tree::remove_parallel(
+            items.iter().map(|x| &x.path),
+            self.options,
+            self.workers,
+            |item, err| {
+                // Errors may be for entries inside one of the directories, which won't have a
+                // Windows path
+                let windows_path = windows_paths.get(item.as_str()).map(ToString::to_string);
+                match err {
+                    None => report(RecycleResult::removed(
+                        item,
+                        windows_path,
+                        Backend::Permanent,
+                    )),
+                    Some(err) => report(RecycleResult::failed(
+                        item.clone(),
+                        windows_path,
+                        Backend::Permanent,
+                        (&err).into(),
+                        match err {
+                            RemoveError::NotFound(_) => err.to_string(),
+                            _ => format!("Failed to delete \"{item}\": {err}"),
+                        },
+                    )),
+                }
+            },
+        );
+
+        match result {
+            Ok(()) => 0,
+            Err(RemoveError::Incomplete) => 1,
+            Err(
+                ref err @ (RemoveError::NotFound(ref path)
+                | RemoveError::IsDirectory(ref path)
+                | RemoveError::InvalidPath(ref path)),
+            ) => {
+                report(RecycleResult::failed(
+                    path.clone(),
+                    windows_paths.get(path.as_str()).map(ToString::to_string),
+                    Backend::Permanent,
+                    err.into(),
+                    err.to_string(),
+                ));
+                1
+            }
+            Err(err) => {
+                eprintln!("recycle: {err}");
+                1
+            }
+        }
+    }
+}
+
+/// Records the items it's given instead of removing them, for testing. Items whose path is in
+/// `fail` are reported as failed (and stop the batch, like `FreedesktopTrash`).
+#[derive(Debug)]
+pub struct MockBackend {
+    pub backend: Backend,
+    pub fail: Vec<String>,
+    pub recycled: Vec<RecycleItem>,
+}
+
+impl MockBackend {
+    #[must_use]
+    pub fn new(backend: Backend) -> Self {
+        Self {
+            backend,
+            fail: Vec::new(),
+            recycled: Vec::new(),
+        }
+    }
+}
+
+impl RecycleBackend for MockBackend {
+    fn recycle(&mut self, items: Vec<RecycleItem>, report: &mut dyn FnMut(RecycleResult)) -> i32 {
+        for item in items {
+            if self.fail.contains(&item.path) {
+                report(RecycleResult::failed(
+                    item.path.clone(),
+                    Some(item.windows_path),
+                    self.backend,
+                    ErrorCode::Unknown,
+                    format!("Failed to recycle \"{}\".", item.path),
+                ));
+                return 1;
+            }
+
+            report(RecycleResult::removed(
+                item.path.clone(),
+                Some(item.windows_path.clone()),
+                self.backend,
+            ));
+            self.recycled.push(item);
+        }
+
+        0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::recycle_result::Outcome;
+
+    fn wsl(path: &str, stat: io::Result<ItemStat>) -> PathInfo {
+        PathInfo {
+            path: path.to_owned(),
+            windows_path: Ok(format!(
+                r"\\wsl.localhost\Ubuntu{}",
+                path.replace('/', "\\")
+            )),
+            stat,
+        }
+    }
+
+    fn drive(path: &str, stat: io::Result<ItemStat>) -> PathInfo {
+        PathInfo {
+            path: path.to_owned(),
+            windows_path: Ok(format!(
+                "C:{}",
+                path.trim_start_matches("/mnt/c").replace('/', "\\")
+            )),
+            stat,
+        }
+    }
+
+    const FILE: ItemStat = ItemStat {
+        is_dir: false,
+        is_root: false,
+    };
+
+    const DIR: ItemStat = ItemStat {
+        is_dir: true,
+        is_root: false,
+    };
+
+    fn not_found() -> io::Result<ItemStat> {
+        Err(ErrorKind::NotFound.into())
+    }
+
+    fn paths(items: &[RecycleItem]) -> Vec<&str> {
+        items.iter().map(|x| x.path.as_str()).collect()
+    }
+
+    fn rm() -> RouteOptions {
+        RouteOptions {
+            rm: true,
+            ..RouteOptions::default()
+        }
+    }
+
+    #[test]
+    fn routes_wsl_paths_linux_side_only_with_rm_or_trash() {
+        let infos = || vec![wsl("/home/rin/a", Ok(FILE)), drive("/mnt/c/b", Ok(FILE))];
+
+        let routes = route(infos(), &RouteOptions::default(), &mut |_| {}).unwrap();
+        assert_eq!(paths(&routes.windows), ["/home/rin/a", "/mnt/c/b"]);
+        assert!(routes.linux.is_empty());
+
+        let routes = route(infos(), &rm(), &mut |_| {}).unwrap();
+        assert_eq!(paths(&routes.linux), ["/home/rin/a"]);
+        assert_eq!(paths(&routes.windows), ["/mnt/c/b"]);
+
+        let trash = RouteOptions {
+            use_linux_trash: true,
+            ..RouteOptions::default()
+        };
+        let routes = route(infos(), &trash, &mut |_| {}).unwrap();
+        assert_eq!(paths(&routes.linux), ["/home/rin/a"]);
+    }
+
+    #[test]
+    fn skips_not_found_only_with_force() {
+        let infos = || {
+            vec![
+                wsl("/home/rin/gone", not_found()),
+                wsl("/home/rin/a", Ok(FILE)),
+            ]
+        };
+
+        let err = route(infos(), &rm(), &mut |_| {}).unwrap_err();
+        assert_eq!(err.error, Some(ErrorCode::NotFound));
+        assert_eq!(err.path, "/home/rin/gone");
+
+        let mut reported = Vec::new();
+        let force = RouteOptions {
+            force: true,
+            ..rm()
+        };
+        let routes = route(infos(), &force, &mut |x| reported.push(x)).unwrap();
+        assert_eq!(paths(&routes.linux), ["/home/rin/a"]);
+        assert_eq!(reported.len(), 1);
+        assert_eq!(reported[0].outcome, Outcome::Skipped);
+        assert_eq!(reported[0].backend, Backend::Permanent);
+    }
+
+    #[test]
+    fn refuses_directories_without_recursive() {
+        let infos = || vec![wsl("/home/rin/dir", Ok(DIR))];
+
+        let err = route(infos(), &rm(), &mut |_| {}).unwrap_err();
+        assert_eq!(err.error, Some(ErrorCode::IsDirectory));
+
+        let recursive = RouteOptions {
+            recursive: true,
+            ..rm()
+        };
+        let routes = route(infos(), &recursive, &mut |_| {}).unwrap();
+        assert_eq!(paths(&routes.linux), ["/home/rin/dir"]);
+        assert!(routes.linux[0].is_dir);
+    }
+
+    #[test]
+    fn refuses_root() {
+        let root = ItemStat {
+            is_dir: true,
+            is_root: true,
+        };
+        let options = RouteOptions {
+            recursive: true,
+            ..rm()
+        };
+
+        let err = route(vec![wsl("/", Ok(root))], &options, &mut |_| {}).unwrap_err();
+        assert_eq!(err.error, Some(ErrorCode::RefusedRoot));
+        assert_eq!(err.message.as_deref(), Some("Refusing to delete \"/\"."));
+
+        let err = route(vec![wsl("/home/..", Ok(root))], &options, &mut |_| {}).unwrap_err();
+        assert_eq!(
+            err.message.as_deref(),
+            Some("Refusing to delete \"/home/..\" (same as \"/\").")
+        );
+    }
+
+    #[test]
+    fn checks_windows_side_paths_if_interop_unavailable() {
+        let options = RouteOptions {
+            interop_unavailable: true,
+            ..rm()
+        };
+
+        let err = route(
+            vec![drive("/mnt/c/gone", not_found())],
+            &options,
+            &mut |_| {},
+        )
+        .unwrap_err();
+        assert_eq!(err.error, Some(ErrorCode::NotFound));
+        assert_eq!(err.backend, Backend::LinuxTrash);
+
+        let err = route(vec![drive("/mnt/c/dir", Ok(DIR))], &options, &mut |_| {}).unwrap_err();
+        assert_eq!(err.error, Some(ErrorCode::IsDirectory));
+
+        let infos = vec![drive("/mnt/c/b", Ok(FILE)), wsl("/home/rin/a", Ok(FILE))];
+        let routes = route(infos, &options, &mut |_| {}).unwrap();
+        assert_eq!(paths(&routes.windows), ["/mnt/c/b"]);
+        assert_eq!(paths(&routes.linux), ["/home/rin/a"]);
+    }
+
+    #[test]
+    fn fails_if_wslpath_fails() {
+        let info = PathInfo {
+            path: "foo".to_owned(),
+            windows_path: Err("nope".to_owned()),
+            stat: Ok(FILE),
+        };
+        let err = route(vec![info], &rm(), &mut |_| {}).unwrap_err();
+        assert_eq!(err.error, Some(ErrorCode::InvalidPath));
+    }
+
+    #[test]
+    fn runs_linux_side_first_and_stops_on_failure() {
+        let infos = || vec![drive("/mnt/c/b", Ok(FILE)), wsl("/home/rin/a", Ok(FILE))];
+        let mut reported = Vec::new();
+
+        let mut linux = MockBackend::new(Backend::Permanent);
+        let mut windows = MockBackend::new(Backend::WindowsBin);
+        let code = route(infos(), &rm(), &mut |_| {}).unwrap().run(
+            || Ok(Box::new(&mut linux)),
+            &mut windows,
+            &mut |x| reported.push(x.path),
+        );
+        assert_eq!(code, 0);
+        assert_eq!(reported, ["/home/rin/a", "/mnt/c/b"]);
+        assert_eq!(paths(&linux.recycled), ["/home/rin/a"]);
+        assert_eq!(paths(&windows.recycled), ["/mnt/c/b"]);
+
+        let mut linux = MockBackend::new(Backend::Permanent);
+        linux.fail.push("/home/rin/a".to_owned());
+        let mut windows = MockBackend::new(Backend::WindowsBin);
+        let code = route(infos(), &rm(), &mut |_| {}).unwrap().run(
+            || Ok(Box::new(&mut linux)),
+            &mut windows,
+            &mut |_| {},
+        );
+        assert_eq!(code, 1);
+        assert!(
+            windows.recycled.is_empty(),
+            "should not continue after failure"
+        );
+    }
+}
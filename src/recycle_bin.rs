@@ -4,48 +4,169 @@
 #![cfg(windows)]
 
 pub use crate::recycle_error::RecycleError;
-use crate::recycle_progress_sink::RecycleProgressSink;
-use std::{fs, os::windows::ffi::OsStrExt};
+use crate::{
+    recycle_progress_sink::RecycleProgressSink,
+    recycle_report::{
+        self, ItemOutcome, ItemReport, PendingItem, PendingState, RecycleReport, SinkEvent,
+    },
+    recycle_safe::{self, Attempt, RecycleOperation},
+};
+use std::{cell::RefCell, fs, os::windows::ffi::OsStrExt, rc::Rc};
 use windows::{
     Win32::{
-        Foundation::{ERROR_CANCELLED, ERROR_FILE_NOT_FOUND},
+        Foundation::{
+            CloseHandle, E_ABORT, ERROR_CANCELLED, ERROR_FILE_NOT_FOUND, HANDLE, HLOCAL, LocalFree,
+        },
+        Security::{
+            Authorization::ConvertSidToStringSidW, GetTokenInformation, TOKEN_QUERY, TOKEN_USER,
+            TokenUser,
+        },
         System::Com::{
             CLSCTX_ALL, COINIT_APARTMENTTHREADED, COINIT_DISABLE_OLE1DDE, CoCreateInstance,
             CoInitializeEx,
         },
+        System::Threading::{GetCurrentProcess, OpenProcessToken},
         UI::Shell::{
-            COPYENGINE_E_USER_CANCELLED, FOF_NOCONFIRMATION, FOF_NOERRORUI, FOF_SILENT,
-            FOFX_ADDUNDORECORD, FOFX_RECYCLEONDELETE, FileOperation, IFileOperation,
+            COPYENGINE_E_USER_CANCELLED, FILEOPERATION_FLAGS, FOF_NO_CONNECTED_ELEMENTS,
+            FOF_NOCONFIRMATION, FOF_NOERRORUI, FOF_SILENT, FOF_WANTNUKEWARNING, FOFX_ADDUNDORECORD,
+            FOFX_RECYCLEONDELETE, FOFX_REQUIREELEVATION, FileOperation, IFileOperation,
             IFileOperationProgressSink, IShellItem, SHCreateItemFromParsingName,
         },
     },
-    core::{HRESULT, PCWSTR},
+    core::{HRESULT, PCWSTR, PWSTR},
 };
 
-pub type RecycleOptions = u8;
+/// Controls how `recycle` behaves. The default is to show the progress dialog etc. and add to the
+/// undo stack, the same as if deleting from Explorer, except that connected files aren't deleted.
+///
+/// # Examples
+///
+/// ```ignore
+/// let options = RecycleOptions::new()
+///     .dangerously_in_background(true)
+///     .ignore_not_found(true);
+/// recycle_bin::recycle(["foo.txt"], options)?;
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct RecycleOptions {
+    background: bool,
+    safe: bool,
+    ignore_not_found: bool,
+    connected_elements: bool,
+    undo: Option<bool>,
+    require_elevation: bool,
+    nuke_warning: bool,
+}
+
+impl RecycleOptions {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Hide all dialogs and let the shell permanently delete anything it can't recycle. This may
+    /// result in files that could have been recycled being nuked instead; see `recycle()`'s
+    /// remarks. Also turns off `undo` unless set explicitly.
+    #[must_use]
+    pub fn dangerously_in_background(mut self, value: bool) -> Self {
+        self.background = value;
+        self
+    }
+
+    /// Recycle in the background like `dangerously_in_background`, but abort instead of letting
+    /// the shell delete anything permanently, then retry one-by-one so that only the items that
+    /// can't be recycled fail (with `RecycleError::CannotRecycle`). This is slower if any items
+    /// can't be recycled. The shell claims it can recycle items too big for the Recycle Bin, so
+    /// this also turns on `nuke_warning` to at least show a dialog for those.
+    #[must_use]
+    pub fn safe_background(mut self, value: bool) -> Self {
+        self.safe = value;
+        self
+    }
+
+    /// Skip files/directories that don't exist instead of returning `RecycleError::NotFound`.
+    #[must_use]
+    pub fn ignore_not_found(mut self, value: bool) -> Self {
+        self.ignore_not_found = value;
+        self
+    }
+
+    /// Let the shell also delete "connected" files, e.g. the `foo_files` directory when deleting
+    /// `foo.html` (`FOF_NO_CONNECTED_ELEMENTS` is set unless this is true). See the remarks.
+    #[must_use]
+    pub fn connected_elements(mut self, value: bool) -> Self {
+        self.connected_elements = value;
+        self
+    }
+
+    /// Add the operation to Explorer's undo history (`FOFX_ADDUNDORECORD`). Defaults to true,
+    /// unless `dangerously_in_background`.
+    #[must_use]
+    pub fn undo(mut self, value: bool) -> Self {
+        self.undo = Some(value);
+        self
+    }
+
+    /// If an item requires admin to delete, immediately show a UAC prompt rather than a dialog
+    /// asking whether to continue as admin (`FOFX_REQUIREELEVATION`).
+    #[must_use]
+    pub fn require_elevation(mut self, value: bool) -> Self {
+        self.require_elevation = value;
+        self
+    }
+
+    /// Show a dialog if an item is too big for the Recycle Bin, even when
+    /// `dangerously_in_background` (`FOF_WANTNUKEWARNING`). This does not cover items on drives
+    /// with no Recycle Bin at all; see the remarks.
+    #[must_use]
+    pub fn nuke_warning(mut self, value: bool) -> Self {
+        self.nuke_warning = value;
+        self
+    }
+
+    /// The flags to pass to `IFileOperation::SetOperationFlags`.
+    #[must_use]
+    pub fn operation_flags(&self) -> FILEOPERATION_FLAGS {
+        let mut flags = FOFX_RECYCLEONDELETE;
+        let background = self.background || self.safe;
+
+        if background {
+            flags |= FOF_SILENT | FOF_NOERRORUI | FOF_NOCONFIRMATION;
+        }
+
+        if self.undo.unwrap_or(!background) {
+            flags |= FOFX_ADDUNDORECORD;
+        }
 
-/// Show progress dialog etc. and add to undo stack, the same as if deleting from Explorer.
-pub const RECYCLE_NORMAL: RecycleOptions = 0x0;
+        if !self.connected_elements {
+            flags |= FOF_NO_CONNECTED_ELEMENTS;
+        }
 
-/// Hide all dialogs and let the shell permanently delete anything it can't recycle. This may result
-/// in files that could have been recycled being nuked instead; see `recycle()`'s remarks.
-pub const RECYCLE_DANGEROUSLY_IN_BACKGROUND: RecycleOptions = 0x1;
+        if self.require_elevation {
+            flags |= FOFX_REQUIREELEVATION;
+        }
 
-/// Skip files/directories that don't exist instead of returning `RecycleError::NotFound`.
-pub const RECYCLE_IGNORE_NOT_FOUND: RecycleOptions = 0x2;
+        if self.nuke_warning || self.safe {
+            flags |= FOF_WANTNUKEWARNING;
+        }
+
+        flags
+    }
+}
 
 const FILE_NOT_FOUND: HRESULT = HRESULT::from_win32(ERROR_FILE_NOT_FOUND.0);
 const CANCELLED: HRESULT = HRESULT::from_win32(ERROR_CANCELLED.0);
 
 /// Sends the given files/directories to the Recycle Bin. Paths may be relative to the current
-/// directory. Use the `RecycleOptions` flags to control the function's behavior.
+/// directory. Use `RecycleOptions` to control the function's behavior.
 ///
-/// The default behavior (`RECYCLE_NORMAL`) is to let the shell display the normal progress and
-/// confirmation dialogs and add to Explorer's undo history, the same as if the user had deleted the
-/// files in Explorer. **This should only be done in response to a user action, not automatically
-/// behind the scenes.**
+/// The default behavior (`RecycleOptions::new()`) is to let the shell display the normal progress
+/// and confirmation dialogs and add to Explorer's undo history, the same as if the user had deleted
+/// the files in Explorer. **This should only be done in response to a user action, not
+/// automatically behind the scenes.**
 ///
-/// To recycle in the background, use `RECYCLE_DANGEROUSLY_IN_BACKGROUND`. As the name implies, this
+/// To recycle in the background, use `dangerously_in_background`. As the name implies, this
 /// is dangerous: if _any_ files cannot be recycled, _all_ of them will be permanently deleted. Due
 /// to Windows API limitations, it is not possible to fully prevent this while preventing dialogs
 /// from appearing: Even when using `IFileOperationProgressSink` to attempt to abort if a file is
@@ -53,18 +174,23 @@ const CANCELLED: HRESULT = HRESULT::from_win32(ERROR_CANCELLED.0);
 /// permanently deleted anyway with no indication to the code of what happened. `IFileOperation` is
 /// _full_ of gotchas like this. _See the Remarks below for details._
 ///
+/// Alternatively, `safe_background` implements the approach described in the remarks: the shell is
+/// prevented from deleting anything permanently, at the cost of speed when it can't recycle, and
+/// with the exception of items too big for the Recycle Bin (for which it shows a dialog).
+///
 /// By default, if any files/directories do not exist, `RecycleError::NotFound` is returned without
-/// recycling anything. Set the `RECYCLE_IGNORE_NOT_FOUND` flag to skip them instead.
+/// recycling anything. Set `ignore_not_found` to skip them instead.
 ///
 /// # Errors
 ///
-/// If any paths do not exist (and `RECYCLE_IGNORE_NOT_FOUND` is not set) or are otherwise invalid
+/// If any paths do not exist (and `ignore_not_found` is not set) or are otherwise invalid
 /// (i.e. empty string or either `GetFullPathNameW` or `SHCreateItemFromParsingName` threw an
 /// error), returns `NotFound` or `InvalidPath` with the original path and (if invalid) the error
 /// without recycling.
 ///
 /// If the operation was canceled by the user, or completed but not all files were recycled (e.g.
-/// user responded "Skip" or "No" to a dialog prompt), returns `Canceled`.
+/// user responded "Skip" or "No" to a dialog prompt, or `safe_background` refused some), returns
+/// `Canceled`.
 ///
 /// If any other Win32 error occurred, returns `Win32`, which includes the HRESULT.
 ///
@@ -84,8 +210,8 @@ const CANCELLED: HRESULT = HRESULT::from_win32(ERROR_CANCELLED.0);
 /// >
 /// > If the recycling isn't initiated by the user, messing with Explorer's undo could be surprising
 /// > (the user might for example try to undo a rename only to inadvertently restore a file to some
-/// > unknown location, with no visual indication of what happened). If this is reused as part of a
-/// > library crate, it would be a good idea to add a parameter to override the default flags.
+/// > unknown location, with no visual indication of what happened). `RecycleOptions::undo` can
+/// > turn it off (it's off by default with `dangerously_in_background` and `safe_background`).
 /// >
 /// > Note that even with FOF_SILENT | FOF_NOERRORUI, a dialog will still be shown if file(s)
 /// > can't be recycled (e.g. due to being on a network drive w/o a recycle bin), prompting whether
@@ -177,62 +303,39 @@ where
     TIter: IntoIterator<Item = TItem>,
     TItem: AsRef<str>,
 {
-    recycle_internal(
-        paths,
-        options,
-        None::<Box<dyn FnMut(String, Option<RecycleError>)>>,
-    )
-}
-
-/// See `recycle`.
-#[allow(clippy::missing_errors_doc)]
-pub fn recycle_with_callback<'a, TIter, TItem, TCallback>(
-    paths: TIter,
-    options: RecycleOptions,
-    callback: TCallback,
-) -> Result<(), RecycleError>
-where
-    TIter: IntoIterator<Item = TItem>,
-    TItem: AsRef<str>,
-    TCallback: FnMut(String, Option<RecycleError>) + 'a,
-{
-    recycle_internal(paths, options, Some(callback))
+    recycle_with_report(paths, options)?.into_result()
 }
 
-fn recycle_internal<'a, TIter, TItem, TCallback>(
+/// Same as `recycle`, but returns a `RecycleReport` with the outcome of each path (in the same
+/// order) rather than a single result.
+///
+/// # Errors
+///
+/// Same as `recycle`, except that failed or canceled items are recorded in the report (along with
+/// whether `GetAnyOperationsAborted` fired) instead of returning `Canceled` or `Unknown`.
+pub fn recycle_with_report<TIter, TItem>(
     paths: TIter,
     options: RecycleOptions,
-    callback: Option<TCallback>,
-) -> Result<(), RecycleError>
+) -> Result<RecycleReport, RecycleError>
 where
     TIter: IntoIterator<Item = TItem>,
     TItem: AsRef<str>,
-    TCallback: FnMut(String, Option<RecycleError>) + 'a,
 {
     unsafe {
         // Initialize COM. This is normally done in main(), but it's safe to call multiple times
         // with the same threading model. IFileOperation requires an STA thread.
         CoInitializeEx(None, COINIT_APARTMENTTHREADED | COINIT_DISABLE_OLE1DDE).ok()?;
 
-        // Instantiate an IFileOperation and set flags for recycling.
-        // https://learn.microsoft.com/en-us/windows/win32/api/shobjidl_core/nn-shobjidl_core-ifileoperation
-        let op: IFileOperation = CoCreateInstance(&FileOperation, None, CLSCTX_ALL)?;
-        if options & RECYCLE_DANGEROUSLY_IN_BACKGROUND == RECYCLE_DANGEROUSLY_IN_BACKGROUND {
-            op.SetOperationFlags(
-                FOF_SILENT | FOF_NOERRORUI | FOF_NOCONFIRMATION | FOFX_RECYCLEONDELETE,
-            )?;
-        } else {
-            op.SetOperationFlags(FOFX_ADDUNDORECORD | FOFX_RECYCLEONDELETE)?;
-        }
-
-        let mut paths_queued_for_deletion: Vec<String> = Vec::new();
+        let mut items: Vec<PendingItem> = Vec::new();
+        let mut queued: Vec<(usize, IShellItem)> = Vec::new();
 
         for path in paths {
             // Resolve relative paths and convert to a null-terminated UTF-16 string.
             // path::absolute() calls GetFullPathNameW internally on Windows.
             let rel_path = path.as_ref();
-            let mut abs_path = std::path::absolute(rel_path)
-                .map_err(|err| RecycleError::InvalidPath(rel_path.to_owned(), err.into()))?
+            let absolute_path = std::path::absolute(rel_path)
+                .map_err(|err| RecycleError::InvalidPath(rel_path.to_owned(), err.into()))?;
+            let mut abs_path = absolute_path
                 .as_os_str()
                 .encode_wide()
                 .chain(Some(0))
@@ -243,7 +346,12 @@ where
                 match SHCreateItemFromParsingName(PCWSTR::from_raw(abs_path.as_mut_ptr()), None) {
                     Ok(item) => Ok(item),
                     Err(err) if err.code() == FILE_NOT_FOUND => {
-                        if options & RECYCLE_IGNORE_NOT_FOUND == RECYCLE_IGNORE_NOT_FOUND {
+                        if options.ignore_not_found {
+                            items.push(PendingItem {
+                                path: rel_path.to_owned(),
+                                absolute_path,
+                                state: PendingState::Missing,
+                            });
                             continue;
                         }
                         Err(RecycleError::NotFound(rel_path.to_owned()))
@@ -251,51 +359,262 @@ where
                     Err(err) => Err(RecycleError::InvalidPath(rel_path.to_owned(), err.into())),
                 }?;
 
-            // Mark for deletion. Based on the example code for copying[0][1], it seems to be safe
-            // to drop the shell item (which calls Release) once it's been added to the operation.
-            //
-            // [0]: https://learn.microsoft.com/en-us/windows/win32/api/shobjidl_core/nf-shobjidl_core-ifileoperation-copyitem#examples
-            // [1]: https://github.com/microsoft/Windows-classic-samples/blob/main/Samples/Win7Samples/winui/shell/appplatform/FileOperationProgressSink/ProgressSinkSampleApp.cpp#L435
-            op.DeleteItem(&item, None)?;
-            paths_queued_for_deletion.push(rel_path.to_owned());
+            queued.push((items.len(), item));
+            items.push(PendingItem {
+                path: rel_path.to_owned(),
+                absolute_path,
+                state: PendingState::Queued { still_exists: true },
+            });
         }
 
         // Bail if there's nothing to recycle (empty paths or all nonexistent and ignored), as
         // PerformOperations will throw a "Catastrophic failure" if the operation is empty.
-        if paths_queued_for_deletion.is_empty() {
-            return Ok(());
+        if queued.is_empty() {
+            return Ok(recycle_report::resolve(items, &[], false));
+        }
+
+        if options.safe {
+            return recycle_safe_background(items, &queued, options);
         }
 
-        // Set up the sink if a callback was provided
-        let sink: IFileOperationProgressSink;
-        if let Some(cb) = callback {
-            sink = RecycleProgressSink::new(cb).into();
-            op.Advise(&sink)?;
+        let shell_items: Vec<&IShellItem> = queued.iter().map(|(_, item)| item).collect();
+        let (events, aborted) = perform(&shell_items, options)?;
+        check_still_exists(&mut items);
+        Ok(recycle_report::resolve(items, &events, aborted))
+    }
+}
+
+/// Gets the current user's SID as a string, which is the name of their folder in each drive's
+/// `$Recycle.Bin`.
+///
+/// # Errors
+///
+/// Failed to query the process token.
+pub fn current_user_sid() -> windows::core::Result<String> {
+    unsafe {
+        let mut token = HANDLE::default();
+        OpenProcessToken(GetCurrentProcess(), TOKEN_QUERY, &raw mut token)?;
+
+        // The first call fails with the required size. The buffer is u64s for TOKEN_USER's
+        // alignment.
+        let mut len = 0;
+        let _ = GetTokenInformation(token, TokenUser, None, 0, &raw mut len);
+        let mut buffer = vec![0u64; (len as usize).div_ceil(8)];
+        let result = GetTokenInformation(
+            token,
+            TokenUser,
+            Some(buffer.as_mut_ptr().cast()),
+            len,
+            &raw mut len,
+        );
+        let _ = CloseHandle(token);
+        result?;
+
+        let user = &*buffer.as_ptr().cast::<TOKEN_USER>();
+        let mut sid = PWSTR::null();
+        ConvertSidToStringSidW(user.User.Sid, &raw mut sid)?;
+        let string = String::from_utf16_lossy(sid.as_wide());
+        LocalFree(Some(HLOCAL(sid.0.cast())));
+
+        Ok(string)
+    }
+}
+
+/// Recycles the shell items in a single `IFileOperation`, returning the sink's events and whether
+/// the operation was aborted.
+unsafe fn perform(
+    shell_items: &[&IShellItem],
+    options: RecycleOptions,
+) -> Result<(Vec<SinkEvent>, bool), RecycleError> {
+    unsafe {
+        // Instantiate an IFileOperation and set flags for recycling.
+        // https://learn.microsoft.com/en-us/windows/win32/api/shobjidl_core/nn-shobjidl_core-ifileoperation
+        let op: IFileOperation = CoCreateInstance(&FileOperation, None, CLSCTX_ALL)?;
+        op.SetOperationFlags(options.operation_flags())?;
+
+        // Mark for deletion. Based on the example code for copying[0][1], it seems to be safe to
+        // drop the shell item (which calls Release) once it's been added to the operation.
+        //
+        // [0]: https://learn.microsoft.com/en-us/windows/win32/api/shobjidl_core/nf-shobjidl_core-ifileoperation-copyitem#examples
+        // [1]: https://github.com/microsoft/Windows-classic-samples/blob/main/Samples/Win7Samples/winui/shell/appplatform/FileOperationProgressSink/ProgressSinkSampleApp.cpp#L435
+        for item in shell_items {
+            op.DeleteItem(*item, None)?;
         }
 
+        // Set up the sink to record the result of each item
+        let events: Rc<RefCell<Vec<SinkEvent>>> = Rc::default();
+        let sink: IFileOperationProgressSink = RecycleProgressSink::new({
+            let events = Rc::clone(&events);
+            move |event| events.borrow_mut().push(event)
+        })
+        .refuse_permanent_delete(options.safe)
+        .into();
+        op.Advise(&sink)?;
+
         // Execute
-        op.PerformOperations().map_err(|err| match err.code() {
-            CANCELLED | COPYENGINE_E_USER_CANCELLED => RecycleError::Canceled,
-            _ => RecycleError::Win32(err),
-        })?;
+        let mut aborted = match op.PerformOperations() {
+            Ok(()) => false,
+            Err(err)
+                if matches!(
+                    err.code(),
+                    CANCELLED | COPYENGINE_E_USER_CANCELLED | E_ABORT
+                ) =>
+            {
+                true
+            }
+            Err(err) => return Err(RecycleError::Win32(err)),
+        };
 
         // It's important to check GetAnyOperationsAborted, since PerformOperations may succeed even
         // if the operation failed. The HRESULT of each failure can be accessed from the
         // PostDeleteItem hook in IFileOperationProgressSink.
-        if op.GetAnyOperationsAborted()?.as_bool() {
-            return Err(RecycleError::Canceled);
+        aborted |= op.GetAnyOperationsAborted()?.as_bool();
+
+        Ok((events.take(), aborted))
+    }
+}
+
+fn check_still_exists(items: &mut [PendingItem]) {
+    // Unfortunately, we're not done after PerformOperations, because IFileOperation will sometimes
+    // return OK despite not actually doing anything. This can happen when the file is in WSL and
+    // the default user doesn't have permission to delete it (e.g. directory owned by root). We
+    // can't rely on PostDeleteItem for this, either; see
+    // https://github.com/maxkagamine/wsl-tools/issues/5.
+    for item in items {
+        if let PendingState::Queued { still_exists } = &mut item.state {
+            *still_exists = fs::exists(&item.absolute_path).unwrap_or_default();
         }
+    }
+}
+
+/// An operation for `recycle_safe` over the queued items.
+struct SafeOperation<'a> {
+    items: &'a [PendingItem],
+    queued: &'a [(usize, IShellItem)],
+    options: RecycleOptions,
+}
+
+impl RecycleOperation for SafeOperation<'_> {
+    type Outcome = ItemReport;
+    type Error = RecycleError;
+
+    fn recycle(&mut self, indices: &[usize]) -> Result<Vec<Attempt<ItemReport>>, RecycleError> {
+        let shell_items: Vec<&IShellItem> = indices.iter().map(|&i| &self.queued[i].1).collect();
+        let (events, aborted) = unsafe { perform(&shell_items, self.options)? };
+
+        let mut items: Vec<PendingItem> = indices
+            .iter()
+            .map(|&i| self.items[self.queued[i].0].clone())
+            .collect();
+        check_still_exists(&mut items);
+
+        Ok(recycle_report::resolve(items, &events, aborted)
+            .items
+            .into_iter()
+            .map(|item| match item.outcome {
+                ItemOutcome::Failed(RecycleError::CannotRecycle) => Attempt::Refused,
+                ItemOutcome::Failed(RecycleError::Canceled) if item.hresult.is_none() => {
+                    Attempt::NotReached
+                }
+                _ => Attempt::Done(item),
+            })
+            .collect())
+    }
+}
+
+fn recycle_safe_background(
+    items: Vec<PendingItem>,
+    queued: &[(usize, IShellItem)],
+    options: RecycleOptions,
+) -> Result<RecycleReport, RecycleError> {
+    let attempts = recycle_safe::recycle_safely(
+        &mut SafeOperation {
+            items: &items,
+            queued,
+            options,
+        },
+        queued.len(),
+    )?;
 
-        // Unfortunately, we're not done, because IFileOperation will sometimes return OK despite
-        // not actually doing anything. This can happen when the file is in WSL and the default user
-        // doesn't have permission to delete it (e.g. directory owned by root). We can't rely on
-        // PostDeleteItem for this, either; see https://github.com/maxkagamine/wsl-tools/issues/5.
-        for path in paths_queued_for_deletion {
-            if fs::exists(&path).unwrap_or_default() {
-                return Err(RecycleError::Unknown);
+    // Missing items are skipped; everything else was queued and is replaced by its attempt
+    let mut report = recycle_report::resolve(items, &[], false);
+    for ((i, _), attempt) in queued.iter().zip(attempts) {
+        let item = &mut report.items[*i];
+        match attempt {
+            Attempt::Done(x) => *item = x,
+            Attempt::Refused => item.outcome = ItemOutcome::Failed(RecycleError::CannotRecycle),
+            Attempt::NotReached => {
+                item.outcome = ItemOutcome::Failed(RecycleError::Canceled);
+                report.aborted = true;
             }
         }
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_flags_match_explorer_without_connected_elements() {
+        assert_eq!(
+            RecycleOptions::new().operation_flags(),
+            FOFX_RECYCLEONDELETE | FOFX_ADDUNDORECORD | FOF_NO_CONNECTED_ELEMENTS
+        );
+    }
+
+    #[test]
+    fn background_hides_dialogs_and_skips_undo() {
+        let options = RecycleOptions::new().dangerously_in_background(true);
+        assert_eq!(
+            options.operation_flags(),
+            FOFX_RECYCLEONDELETE
+                | FOF_SILENT
+                | FOF_NOERRORUI
+                | FOF_NOCONFIRMATION
+                | FOF_NO_CONNECTED_ELEMENTS
+        );
+
+        // Explicitly setting undo overrides the default for background mode
+        assert!(
+            options
+                .undo(true)
+                .operation_flags()
+                .contains(FOFX_ADDUNDORECORD),
+            "undo(true) should add FOFX_ADDUNDORECORD"
+        );
+    }
+
+    #[test]
+    fn safe_background_hides_dialogs_except_nuke_warning() {
+        assert_eq!(
+            RecycleOptions::new()
+                .safe_background(true)
+                .operation_flags(),
+            FOFX_RECYCLEONDELETE
+                | FOF_SILENT
+                | FOF_NOERRORUI
+                | FOF_NOCONFIRMATION
+                | FOF_WANTNUKEWARNING
+                | FOF_NO_CONNECTED_ELEMENTS
+        );
+    }
+
+    #[test]
+    fn maps_each_option_to_its_flag() {
+        let flags = RecycleOptions::new()
+            .connected_elements(true)
+            .undo(false)
+            .require_elevation(true)
+            .nuke_warning(true)
+            .ignore_not_found(true) // Not a shell flag
+            .operation_flags();
 
-        Ok(())
+        assert_eq!(
+            flags,
+            FOFX_RECYCLEONDELETE | FOFX_REQUIREELEVATION | FOF_WANTNUKEWARNING
+        );
     }
 }
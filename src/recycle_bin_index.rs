@@ -0,0 +1,420 @@
+// Copyright (c) Max Kagamine
+// Licensed under the Apache License, Version 2.0
+
+// Each recycled item is stored in `$Recycle.Bin\<SID>` as a pair of files: `$R<id>.<ext>` with
+// the item itself, and `$I<id>.<ext>` with its original path, size, and deletion time. There's no
+// API for reading these from Linux, but since the bin is just a hidden folder on the drive, we can
+// look at them through /mnt.
+
+/// Seconds between 1601-01-01 (the FILETIME epoch) and 1970-01-01.
+const FILETIME_UNIX_EPOCH_SECS: u64 = 11_644_473_600;
+
+/// The contents of a `$I` file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecycledItem {
+    /// The item's full Windows path before it was recycled.
+    pub original_path: String,
+    /// Size of the file or directory in bytes.
+    pub size: u64,
+    /// When the item was recycled, in seconds since the Unix epoch.
+    pub deleted_at: u64,
+}
+
+/// Parses the contents of a `$I` file. Both the Vista (version 1, fixed 260-character path) and
+/// Windows 10 (version 2, length-prefixed path) formats are supported.
+#[must_use]
+pub fn parse_info_file(bytes: &[u8]) -> Option<RecycledItem> {
+    let read_u64 = |offset: usize| -> Option<u64> {
+        Some(u64::from_le_bytes(
+            bytes.get(offset..offset + 8)?.try_into().ok()?,
+        ))
+    };
+
+    let version = read_u64(0)?;
+    let size = read_u64(8)?;
+    let filetime = read_u64(16)?;
+
+    let path_bytes = match version {
+        1 => bytes.get(24..24 + 520)?,
+        2 => {
+            let len = u32::from_le_bytes(bytes.get(24..28)?.try_into().ok()?) as usize;
+            bytes.get(28..28 + len * 2)?
+        }
+        _ => return None,
+    };
+
+    let path_utf16: Vec<u16> = path_bytes
+        .chunks_exact(2)
+        .map(|x| u16::from_le_bytes([x[0], x[1]]))
+        .take_while(|x| *x != 0)
+        .collect();
+
+    Some(RecycledItem {
+        original_path: String::from_utf16(&path_utf16).ok()?,
+        size,
+        deleted_at: (filetime / 10_000_000).saturating_sub(FILETIME_UNIX_EPOCH_SECS),
+    })
+}
+
+#[cfg(unix)]
+pub use self::unix::*;
+
+#[cfg(unix)]
+mod unix {
+    use super::{RecycledItem, parse_info_file};
+    use crate::{recycle_result::Verification, wslpath};
+    use std::{
+        collections::{HashMap, HashSet},
+        ffi::OsString,
+        fs,
+        io::{self, ErrorKind},
+        path::{Path, PathBuf},
+    };
+
+    /// A recycled item found in a Recycle Bin.
+    #[derive(Debug, Clone)]
+    pub struct BinEntry {
+        pub item: RecycledItem,
+        /// Path to the `$I` file.
+        pub info_path: PathBuf,
+        /// Path to the `$R` file or directory.
+        pub data_path: PathBuf,
+    }
+
+    /// Returns the WSL path to the `$Recycle.Bin` for the drive that `windows_path` is on, or
+    /// `None` if it isn't on a drive letter (e.g. a UNC path, which don't have recycle bins).
+    #[must_use]
+    pub fn bin_for(windows_path: &str) -> Option<PathBuf> {
+        let drive = drive_letter(windows_path)?;
+        let root = wslpath::to_wsl(format!("{drive}:\\")).ok()?;
+        Some(Path::new(&root).join("$Recycle.Bin"))
+    }
+
+    /// Lists the items in every per-user bin under `bin` that we have permission to read (normally
+    /// only the current user's SID).
+    ///
+    /// # Errors
+    ///
+    /// The `$Recycle.Bin` itself couldn't be read, e.g. because the drive isn't mounted.
+    pub fn list(bin: &Path) -> io::Result<Vec<BinEntry>> {
+        Ok(fs::read_dir(bin)?
+            .flatten()
+            .filter_map(|sid_dir| list_user_bin(&sid_dir.path()).ok())
+            .flatten()
+            .collect())
+    }
+
+    /// Lists the items in a single per-user bin, `$Recycle.Bin\<SID>`.
+    ///
+    /// # Errors
+    ///
+    /// The directory couldn't be read.
+    pub fn list_user_bin(sid_dir: &Path) -> io::Result<Vec<BinEntry>> {
+        Ok(fs::read_dir(sid_dir)?
+            .flatten()
+            .filter_map(|file| {
+                let name = file.file_name().into_string().ok()?;
+                let id = name.strip_prefix("$I")?;
+                let item = parse_info_file(&fs::read(file.path()).ok()?)?;
+                Some(BinEntry {
+                    item,
+                    data_path: file.path().with_file_name(format!("$R{id}")),
+                    info_path: file.path(),
+                })
+            })
+            .collect())
+    }
+
+    /// Finds the most recent entry for the item originally at `windows_path` that was recycled at
+    /// or after `since`. Paths are compared case-insensitively.
+    #[must_use]
+    pub fn find<'a>(
+        entries: &'a [BinEntry],
+        windows_path: &str,
+        since: u64,
+    ) -> Option<&'a BinEntry> {
+        entries
+            .iter()
+            .filter(|x| {
+                x.item.deleted_at >= since
+                    && x.item.original_path.eq_ignore_ascii_case(windows_path)
+            })
+            .max_by_key(|x| x.item.deleted_at)
+    }
+
+    /// Looks up items in the current user's bin on each drive. Since the same path may have been
+    /// recycled before, each bin is snapshotted before recycling, and only the `$I` files that
+    /// weren't there then are counted.
+    #[derive(Debug, Default)]
+    pub struct BinCache {
+        sid: Option<String>,
+        /// Drive letter -> the user's bin on that drive, so that wslpath only runs once per drive.
+        user_bins: HashMap<char, Option<PathBuf>>,
+        /// Names of the files in each user bin before recycling.
+        snapshots: HashMap<PathBuf, io::Result<HashSet<OsString>>>,
+        entries: HashMap<PathBuf, io::Result<Vec<BinEntry>>>,
+    }
+
+    impl BinCache {
+        /// `sid` is the current user's SID, which names their folder in each `$Recycle.Bin`.
+        /// Without it, nothing can be verified.
+        #[must_use]
+        pub fn new(sid: Option<String>) -> Self {
+            Self {
+                sid,
+                ..Self::default()
+            }
+        }
+
+        /// Records what's already in the bin that the item at `windows_path` will be recycled to.
+        /// Must be called before recycling, for each item to be verified.
+        pub fn snapshot(&mut self, windows_path: &str) {
+            let Some(user_bin) = self.user_bin(windows_path) else {
+                return;
+            };
+
+            self.snapshots
+                .entry(user_bin)
+                .or_insert_with_key(|user_bin| match fs::read_dir(user_bin) {
+                    Ok(files) => Ok(files.flatten().map(|x| x.file_name()).collect()),
+                    // The shell creates it the first time something is recycled
+                    Err(err) if err.kind() == ErrorKind::NotFound => Ok(HashSet::new()),
+                    Err(err) => Err(err),
+                });
+        }
+
+        /// Checks whether the item originally at `windows_path` was recycled since its bin was
+        /// snapshotted. Items not on a drive letter are considered deleted, as they have no bin to
+        /// go to.
+        pub fn verify(&mut self, windows_path: &str) -> Verification {
+            if drive_letter(windows_path).is_none() {
+                return Verification::Deleted;
+            }
+
+            let Some(user_bin) = self.user_bin(windows_path) else {
+                return Verification::Unknown;
+            };
+            let Some(Ok(snapshot)) = self.snapshots.get(&user_bin) else {
+                return Verification::Unknown;
+            };
+
+            let entries = self.entries.entry(user_bin).or_insert_with_key(|user_bin| {
+                match list_user_bin(user_bin) {
+                    Err(err) if err.kind() == ErrorKind::NotFound => Ok(Vec::new()),
+                    result => result,
+                }
+            });
+
+            match entries {
+                Ok(entries)
+                    if entries.iter().any(|x| {
+                        x.info_path
+                            .file_name()
+                            .is_some_and(|name| !snapshot.contains(name))
+                            && x.item.original_path.eq_ignore_ascii_case(windows_path)
+                    }) =>
+                {
+                    Verification::Recycled
+                }
+                Ok(_) => Verification::Deleted,
+                Err(_) => Verification::Unknown,
+            }
+        }
+
+        /// Returns the path to the current user's bin on the drive that `windows_path` is on.
+        fn user_bin(&mut self, windows_path: &str) -> Option<PathBuf> {
+            let sid = self.sid.as_deref()?;
+            let drive = drive_letter(windows_path)?;
+            self.user_bins
+                .entry(drive)
+                .or_insert_with(|| bin_for(windows_path).map(|bin| bin.join(sid)))
+                .clone()
+        }
+
+        /// Uses `bin` as the `$Recycle.Bin` for `drive` rather than asking wslpath.
+        #[cfg(test)]
+        pub(crate) fn with_bin(mut self, drive: char, bin: &Path) -> Self {
+            let user_bin = self.sid.as_deref().map(|sid| bin.join(sid));
+            self.user_bins.insert(drive.to_ascii_uppercase(), user_bin);
+            self
+        }
+    }
+
+    /// Gets the (uppercased) drive letter that `windows_path` is on, if any.
+    fn drive_letter(windows_path: &str) -> Option<char> {
+        let mut chars = windows_path.chars();
+        match (chars.next(), chars.next()) {
+            (Some(drive), Some(':')) if drive.is_ascii_alphabetic() => {
+                Some(drive.to_ascii_uppercase())
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 2025-01-01T00:00:00Z
+    const FILETIME: u64 = 133_801_632_000_000_000;
+    const UNIX_TIME: u64 = 1_735_689_600;
+
+    fn utf16(s: &str) -> Vec<u8> {
+        s.encode_utf16().flat_map(u16::to_le_bytes).collect()
+    }
+
+    #[test]
+    fn parses_version_2() {
+        let path = r"C:\Users\Rin\ミカン.txt";
+        let mut bytes = Vec::new();
+        bytes.extend(2u64.to_le_bytes());
+        bytes.extend(1234u64.to_le_bytes());
+        bytes.extend(FILETIME.to_le_bytes());
+        bytes.extend(
+            u32::try_from(path.encode_utf16().count() + 1)
+                .unwrap()
+                .to_le_bytes(),
+        );
+        bytes.extend(utf16(path));
+        bytes.extend([0, 0]);
+
+        assert_eq!(
+            parse_info_file(&bytes),
+            Some(RecycledItem {
+                original_path: path.to_owned(),
+                size: 1234,
+                deleted_at: UNIX_TIME,
+            })
+        );
+    }
+
+    #[test]
+    fn parses_version_1() {
+        let path = r"D:\road roller.png";
+        let mut bytes = Vec::new();
+        bytes.extend(1u64.to_le_bytes());
+        bytes.extend(5678u64.to_le_bytes());
+        bytes.extend(FILETIME.to_le_bytes());
+        bytes.extend(utf16(path));
+        bytes.resize(24 + 520, 0);
+
+        assert_eq!(
+            parse_info_file(&bytes),
+            Some(RecycledItem {
+                original_path: path.to_owned(),
+                size: 5678,
+                deleted_at: UNIX_TIME,
+            })
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn lists_and_finds_entries() -> std::io::Result<()> {
+        use std::{env, fs};
+
+        fn info_file(path: &str, filetime: u64) -> Vec<u8> {
+            let mut bytes = Vec::new();
+            bytes.extend(1u64.to_le_bytes());
+            bytes.extend(0u64.to_le_bytes());
+            bytes.extend(filetime.to_le_bytes());
+            bytes.extend(utf16(path));
+            bytes.resize(24 + 520, 0);
+            bytes
+        }
+
+        let bin = env::temp_dir().join("wsl-tools-lists_and_finds_entries");
+        if bin.exists() {
+            fs::remove_dir_all(&bin)?;
+        }
+        let sid_dir = bin.join("S-1-5-21-1000");
+        fs::create_dir_all(&sid_dir)?;
+        fs::write(
+            sid_dir.join("$IOLD.txt"),
+            info_file(r"C:\foo.txt", FILETIME),
+        )?;
+        fs::write(
+            sid_dir.join("$INEW.txt"),
+            info_file(r"C:\foo.txt", FILETIME + 600 * 10_000_000),
+        )?;
+        fs::write(sid_dir.join("$RNEW.txt"), "foo")?;
+        fs::write(sid_dir.join("desktop.ini"), "")?;
+
+        let entries = list(&bin)?;
+        assert_eq!(entries.len(), 2);
+
+        let entry = find(&entries, r"c:\FOO.txt", UNIX_TIME).unwrap();
+        assert_eq!(entry.data_path, sid_dir.join("$RNEW.txt"));
+        assert_eq!(entry.item.deleted_at, UNIX_TIME + 600);
+
+        assert!(find(&entries, r"C:\foo.txt", UNIX_TIME + 601).is_none());
+        assert!(find(&entries, r"C:\bar.txt", 0).is_none());
+        assert!(list(&bin.join("nonexistent")).is_err());
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn verifies_only_new_entries_in_users_bin() -> std::io::Result<()> {
+        use crate::recycle_result::Verification;
+        use std::{env, fs};
+
+        let bin = env::temp_dir().join("wsl-tools-verifies_only_new_entries_in_users_bin");
+        if bin.exists() {
+            fs::remove_dir_all(&bin)?;
+        }
+        let user_bin = bin.join("S-1-5-21-1000");
+        let other_bin = bin.join("S-1-5-21-1001");
+        fs::create_dir_all(&user_bin)?;
+        fs::create_dir_all(&other_bin)?;
+
+        let info_file = |path: &str| {
+            let mut bytes = Vec::new();
+            bytes.extend(1u64.to_le_bytes());
+            bytes.extend(0u64.to_le_bytes());
+            bytes.extend(FILETIME.to_le_bytes());
+            bytes.extend(utf16(path));
+            bytes.resize(24 + 520, 0);
+            bytes
+        };
+
+        // Recycled earlier, e.g. `touch x; recycle x; touch x; recycle --verify x`
+        fs::write(user_bin.join("$IOLD.txt"), info_file(r"C:\x.txt"))?;
+
+        let mut cache = BinCache::new(Some("S-1-5-21-1000".into())).with_bin('c', &bin);
+        cache.snapshot(r"C:\x.txt");
+        cache.snapshot(r"C:\y.txt");
+
+        // Only another user's bin got an entry for y.txt
+        fs::write(other_bin.join("$INEW.txt"), info_file(r"C:\y.txt"))?;
+        assert_eq!(cache.verify(r"C:\x.txt"), Verification::Deleted);
+        assert_eq!(cache.verify(r"C:\y.txt"), Verification::Deleted);
+        assert_eq!(cache.verify(r"\\wsl.localhost\x"), Verification::Deleted);
+
+        let mut cache = BinCache::new(Some("S-1-5-21-1000".into())).with_bin('C', &bin);
+        cache.snapshot(r"C:\x.txt");
+        fs::write(user_bin.join("$INEW.txt"), info_file(r"c:\X.txt"))?;
+        assert_eq!(cache.verify(r"C:\x.txt"), Verification::Recycled);
+
+        let mut cache = BinCache::new(None).with_bin('C', &bin);
+        cache.snapshot(r"C:\x.txt");
+        assert_eq!(cache.verify(r"C:\x.txt"), Verification::Unknown);
+
+        fs::remove_dir_all(bin)
+    }
+
+    #[test]
+    fn rejects_truncated_or_unknown_versions() {
+        assert_eq!(parse_info_file(&[]), None);
+        assert_eq!(parse_info_file(&3u64.to_le_bytes().repeat(4)), None);
+
+        let mut bytes = Vec::new();
+        bytes.extend(2u64.to_le_bytes());
+        bytes.extend(0u64.to_le_bytes());
+        bytes.extend(FILETIME.to_le_bytes());
+        bytes.extend(100u32.to_le_bytes());
+        bytes.extend(utf16("C:\\"));
+        assert_eq!(parse_info_file(&bytes), None);
+    }
+}
@@ -3,6 +3,7 @@
 
 #![cfg(windows)]
 
+use crate::recycle_result::ErrorCode;
 use std::{error::Error, fmt::Display};
 use windows::core::Error as Win32Error;
 
@@ -18,6 +19,8 @@ pub enum RecycleError {
     FileInUse,
     FolderInUse,
     Unknown,
+    /// The shell would have deleted the item permanently, with `safe_background`.
+    CannotRecycle,
 }
 
 impl From<Win32Error> for RecycleError {
@@ -26,6 +29,22 @@ impl From<Win32Error> for RecycleError {
     }
 }
 
+impl From<&RecycleError> for ErrorCode {
+    fn from(value: &RecycleError) -> Self {
+        match value {
+            RecycleError::NotFound(_) => Self::NotFound,
+            RecycleError::InvalidPath(..) => Self::InvalidPath,
+            RecycleError::Win32(_) => Self::Win32,
+            RecycleError::Canceled => Self::Canceled,
+            RecycleError::AccessDenied => Self::AccessDenied,
+            RecycleError::FileInUse => Self::FileInUse,
+            RecycleError::FolderInUse => Self::FolderInUse,
+            RecycleError::Unknown => Self::Unknown,
+            RecycleError::CannotRecycle => Self::CannotRecycle,
+        }
+    }
+}
+
 impl Display for RecycleError {
     #[rustfmt::skip]
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -38,6 +57,7 @@ impl Display for RecycleError {
             Self::FileInUse => write!(f, "The file is open in another program."),
             Self::FolderInUse => write!(f, "The folder or a file in it is open in another program."),
             Self::Unknown => write!(f, "Unknown error (file or directory still exists)."),
+            Self::CannotRecycle => write!(f, "Cannot be recycled; refusing to delete it permanently."),
         }
     }
 }
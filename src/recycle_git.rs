@@ -0,0 +1,251 @@
+// Copyright (c) Max Kagamine
+// Licensed under the Apache License, Version 2.0
+
+#![cfg(unix)]
+
+// With `--git-safe`, each path is checked for uncommitted work before anything is removed: if the
+// path is in a git work tree, `git status` lists any modified or untracked (and not ignored) files
+// under it, which the user then has to confirm. A directory that isn't in a work tree, such as
+// ~/src, is searched for repos instead (up to `MAX_SEARCH_DEPTH` levels down), and each one found
+// is checked the same way. Removing a `.git` directory on its own is refused outright, since it
+// takes the history with it but leaves the files looking perfectly normal.
+
+use std::{
+    ffi::OsStr,
+    fs,
+    io::{self, ErrorKind},
+    os::unix::ffi::OsStrExt,
+    path::{Component, Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+/// How many levels below a directory that isn't in a work tree to look for repos.
+const MAX_SEARCH_DEPTH: usize = 6;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GitSafety {
+    /// Not in a git work tree and no repos under it, or nothing under the path has uncommitted
+    /// changes.
+    Safe,
+    /// Modified or untracked files under the path.
+    Dirty(Vec<DirtyFile>),
+    /// The path is a `.git` directory or inside one.
+    GitDirectory,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirtyFile {
+    /// The two-letter status code from `git status --porcelain`, e.g. ` M` or `??`.
+    pub status: String,
+    /// Absolute path to the file.
+    pub path: PathBuf,
+}
+
+/// Checks whether `path` (which should be absolute with symlinks in the dirname resolved, as by
+/// `resolve_path` in the bin) can be removed without losing uncommitted work.
+///
+/// # Errors
+///
+/// Git isn't installed, or `git status` failed in a work tree or a repo found under the path.
+pub fn check(path: &Path) -> io::Result<GitSafety> {
+    if path
+        .components()
+        .any(|x| x == Component::Normal(OsStr::new(".git")))
+    {
+        return Ok(GitSafety::GitDirectory);
+    }
+
+    // Run git in the directory itself or, for files, the directory containing it
+    let Ok(stat) = fs::symlink_metadata(path) else {
+        return Ok(GitSafety::Safe);
+    };
+    let dir = if stat.is_dir() {
+        path
+    } else {
+        match path.parent() {
+            Some(x) => x,
+            None => return Ok(GitSafety::Safe),
+        }
+    };
+
+    // Fails if not in a work tree (including a bare repo)
+    let output = git(dir, &["rev-parse", "--show-toplevel"])?;
+    if !output.status.success() {
+        return if stat.is_dir() {
+            check_nested(path)
+        } else {
+            Ok(GitSafety::Safe)
+        };
+    }
+    let toplevel = PathBuf::from(OsStr::from_bytes(trim_newline(&output.stdout)));
+
+    let Ok(relative) = path.strip_prefix(&toplevel) else {
+        return Ok(GitSafety::Safe);
+    };
+
+    let files = status(&toplevel, relative)?;
+    Ok(if files.is_empty() {
+        GitSafety::Safe
+    } else {
+        GitSafety::Dirty(files)
+    })
+}
+
+/// Searches `dir`, which isn't in a work tree, for repos and checks each one. Symlinks aren't
+/// followed, and neither are repos searched for further repos, as `git status` in the outer repo
+/// already lists a nested one as untracked.
+fn check_nested(dir: &Path) -> io::Result<GitSafety> {
+    let mut files = Vec::new();
+    let mut stack = vec![(dir.to_path_buf(), 0)];
+
+    while let Some((dir, depth)) = stack.pop() {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+
+        let mut subdirs = Vec::new();
+        let mut is_repo = false;
+
+        for entry in entries.flatten() {
+            // A file for worktrees and submodules
+            if entry.file_name() == ".git" {
+                is_repo = true;
+                break;
+            }
+
+            if depth < MAX_SEARCH_DEPTH && entry.file_type().is_ok_and(|x| x.is_dir()) {
+                subdirs.push((entry.path(), depth + 1));
+            }
+        }
+
+        if is_repo {
+            files.extend(status(&dir, Path::new(""))?);
+        } else {
+            stack.extend(subdirs);
+        }
+    }
+
+    Ok(if files.is_empty() {
+        GitSafety::Safe
+    } else {
+        GitSafety::Dirty(files)
+    })
+}
+
+/// Lists the uncommitted files under `relative` in the work tree at `toplevel`.
+fn status(toplevel: &Path, relative: &Path) -> io::Result<Vec<DirtyFile>> {
+    let mut pathspec = b":(literal)".to_vec();
+    pathspec.extend_from_slice(if relative.as_os_str().is_empty() {
+        b"."
+    } else {
+        relative.as_os_str().as_bytes()
+    });
+
+    let output = git(
+        toplevel,
+        &[
+            OsStr::new("status"),
+            OsStr::new("--porcelain"),
+            OsStr::new("-z"),
+            OsStr::new("--untracked-files=all"),
+            OsStr::new("--"),
+            OsStr::from_bytes(&pathspec),
+        ],
+    )?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+        return Err(io::Error::other(stderr));
+    }
+
+    Ok(parse_status(&output.stdout, toplevel))
+}
+
+fn git(dir: &Path, args: &[impl AsRef<OsStr>]) -> io::Result<std::process::Output> {
+    Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|err| {
+            if err.kind() == ErrorKind::NotFound {
+                io::Error::new(ErrorKind::NotFound, "git is not installed")
+            } else {
+                err
+            }
+        })
+}
+
+fn trim_newline(bytes: &[u8]) -> &[u8] {
+    bytes.strip_suffix(b"\n").unwrap_or(bytes)
+}
+
+/// Parses the output of `git status --porcelain -z`, whose paths are relative to the repo root.
+#[must_use]
+pub fn parse_status(output: &[u8], toplevel: &Path) -> Vec<DirtyFile> {
+    let mut files = Vec::new();
+    let mut entries = output.split(|&b| b == 0).filter(|x| !x.is_empty());
+
+    while let Some(entry) = entries.next() {
+        let (Some(status), Some(path)) = (entry.get(..2), entry.get(3..)) else {
+            continue;
+        };
+
+        // Renames and copies are followed by the original path, which is no longer there
+        if matches!(status[0], b'R' | b'C') {
+            entries.next();
+        }
+
+        files.push(DirtyFile {
+            status: String::from_utf8_lossy(status).into_owned(),
+            path: toplevel.join(OsStr::from_bytes(path)),
+        });
+    }
+
+    files
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_porcelain_status() {
+        let output = b" M src/main.rs\0?? notes.txt\0R  new.rs\0old.rs\0A  dir/a b.txt\0";
+        let files = parse_status(output, Path::new("/repo"));
+
+        assert_eq!(
+            files,
+            vec![
+                DirtyFile {
+                    status: " M".to_owned(),
+                    path: PathBuf::from("/repo/src/main.rs"),
+                },
+                DirtyFile {
+                    status: "??".to_owned(),
+                    path: PathBuf::from("/repo/notes.txt"),
+                },
+                DirtyFile {
+                    status: "R ".to_owned(),
+                    path: PathBuf::from("/repo/new.rs"),
+                },
+                DirtyFile {
+                    status: "A ".to_owned(),
+                    path: PathBuf::from("/repo/dir/a b.txt"),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn refuses_git_directories() {
+        assert_eq!(
+            check(Path::new("/repo/.git")).unwrap(),
+            GitSafety::GitDirectory
+        );
+        assert_eq!(
+            check(Path::new("/repo/.git/objects")).unwrap(),
+            GitSafety::GitDirectory
+        );
+    }
+}
@@ -0,0 +1,512 @@
+// Copyright (c) Max Kagamine
+// Licensed under the Apache License, Version 2.0
+
+#![cfg(unix)]
+
+use crate::{
+    freedesktop_trash,
+    recycle_bin_index::{self, BinEntry},
+    recycle_result::Backend,
+    remove_tree,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    env,
+    error::Error,
+    fmt::Display,
+    fs::{self, File, OpenOptions},
+    io::{self, ErrorKind, Write},
+    os::fd::AsRawFd,
+    path::{Path, PathBuf},
+    process,
+};
+
+const JOURNAL_FILENAME: &str = "recycle-journal.jsonl";
+
+/// Once the journal grows past this size, it's rewritten keeping only the newest entries.
+const MAX_JOURNAL_SIZE: u64 = 1024 * 1024;
+const ENTRIES_TO_KEEP: usize = 100;
+
+/// Leeway when matching deletion times against the time an invocation started, as WSL's clock is
+/// known to drift from Windows' (e.g. after the computer wakes from sleep).
+pub const CLOCK_SKEW_SECS: u64 = 60;
+
+/// A single invocation of `recycle`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JournalEntry {
+    /// When the invocation started, in seconds since the Unix epoch.
+    pub time: u64,
+    pub cwd: String,
+    pub args: Vec<String>,
+    pub items: Vec<JournalItem>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JournalItem {
+    /// Absolute WSL path of the item before it was removed.
+    pub path: String,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub windows_path: Option<String>,
+
+    pub backend: Backend,
+
+    /// The item's `.trashinfo` file, if it was moved to the Linux trash. Items sent to the Recycle
+    /// Bin are looked up by their original path when restoring, as the shell doesn't tell us where
+    /// they went.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trash_path: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum RestoreError {
+    /// The item was deleted permanently.
+    Unrecoverable,
+    /// The item couldn't be found in the trash or Recycle Bin (emptied, restored already, or
+    /// silently nuked by the shell).
+    NotInTrash,
+    /// Something else is in the item's original location.
+    AlreadyExists,
+    Io(io::Error),
+}
+
+impl From<io::Error> for RestoreError {
+    fn from(value: io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+
+impl Display for RestoreError {
+    #[rustfmt::skip]
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Unrecoverable => write!(f, "It was deleted permanently."),
+            Self::NotInTrash => write!(f, "Not found in the trash."),
+            Self::AlreadyExists => write!(f, "A file or directory already exists at that location."),
+            Self::Io(err) => Display::fmt(err, f),
+        }
+    }
+}
+
+impl Error for RestoreError {}
+
+/// Returns the path to the journal in `$XDG_STATE_HOME/wsl-tools`.
+#[must_use]
+pub fn journal_path() -> Option<PathBuf> {
+    env::var_os("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .filter(|x| x.is_absolute())
+        .or_else(|| env::var_os("HOME").map(|x| Path::new(&x).join(".local/state")))
+        .map(|x| x.join("wsl-tools").join(JOURNAL_FILENAME))
+}
+
+/// An exclusive lock on the journal, released when dropped. `save` replaces the journal file, so
+/// the lock is taken on a separate `.lock` file next to it.
+#[derive(Debug)]
+pub struct JournalLock {
+    _file: File,
+}
+
+/// Locks the journal, waiting for any other invocation holding the lock. Hold it from `load`
+/// through `save` so that entries appended in between aren't lost.
+///
+/// # Errors
+///
+/// Failed to create or lock the lock file.
+pub fn lock() -> io::Result<JournalLock> {
+    let path = journal_path().ok_or(ErrorKind::NotFound)?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    let file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(path.with_extension("lock"))?;
+
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(JournalLock { _file: file })
+}
+
+/// Reads the journal, oldest entries first. Lines that can't be parsed are skipped.
+///
+/// # Errors
+///
+/// Failed to read the journal. A journal that doesn't exist yet is treated as empty.
+pub fn load() -> io::Result<Vec<JournalEntry>> {
+    let path = journal_path().ok_or(ErrorKind::NotFound)?;
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err),
+    };
+
+    Ok(text
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect())
+}
+
+/// Replaces the journal with `entries`. The caller should be holding the `lock`.
+///
+/// # Errors
+///
+/// Failed to write the journal.
+pub fn save(entries: &[JournalEntry]) -> io::Result<()> {
+    let path = journal_path().ok_or(ErrorKind::NotFound)?;
+    let temp_path = path.with_extension(format!("{}.tmp", process::id()));
+
+    let mut text = String::new();
+    for entry in entries {
+        text += &serde_json::to_string(entry)?;
+        text.push('\n');
+    }
+
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    if let Err(err) = fs::write(&temp_path, text).and_then(|()| fs::rename(&temp_path, path)) {
+        let _ = fs::remove_file(temp_path);
+        return Err(err);
+    }
+
+    Ok(())
+}
+
+/// Adds `entry` to the end of the journal.
+///
+/// # Errors
+///
+/// Failed to write the journal.
+pub fn append(entry: &JournalEntry) -> io::Result<()> {
+    let path = journal_path().ok_or(ErrorKind::NotFound)?;
+    let _lock = lock()?;
+
+    if fs::metadata(&path).is_ok_and(|m| m.len() > MAX_JOURNAL_SIZE) {
+        let mut entries = load()?;
+        entries.drain(..entries.len().saturating_sub(ENTRIES_TO_KEEP - 1));
+        entries.push(entry.clone());
+        return save(&entries);
+    }
+
+    let mut line = serde_json::to_string(entry)?;
+    line.push('\n');
+
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?
+        .write_all(line.as_bytes())
+}
+
+/// Fills in `trash_path` for items moved to the Linux trash after `since` that don't have one
+/// (journal entries from older versions, which used `trash::delete`, didn't record it).
+pub fn locate_in_linux_trash(items: &mut [JournalItem], since: u64) {
+    if !items
+        .iter()
+        .any(|x| x.backend == Backend::LinuxTrash && x.trash_path.is_none())
+    {
+        return;
+    }
+
+    let Ok(trashed) = trash::os_limited::list() else {
+        return;
+    };
+
+    for item in items
+        .iter_mut()
+        .filter(|x| x.backend == Backend::LinuxTrash && x.trash_path.is_none())
+    {
+        item.trash_path = trashed
+            .iter()
+            .filter(|x| {
+                u64::try_from(x.time_deleted).is_ok_and(|t| t >= since)
+                    && x.original_path() == Path::new(&item.path)
+            })
+            .max_by_key(|x| x.time_deleted)
+            .and_then(|x| x.id.to_str().map(ToOwned::to_owned));
+    }
+}
+
+/// Moves each of the entry's items back to its original location from wherever it was sent,
+/// returning the results in the same order as `entry.items`. Items are restored last to first, so
+/// that e.g. `recycle foo/bar foo` restores `foo` before `foo/bar`.
+#[must_use]
+pub fn restore(entry: &JournalEntry) -> Vec<Result<(), RestoreError>> {
+    let since = entry.time.saturating_sub(CLOCK_SKEW_SECS);
+
+    let mut items = entry.items.clone();
+    locate_in_linux_trash(&mut items, since);
+
+    // Listing a bin means reading every $I file in it, so only do it once per drive
+    let mut bins: HashMap<PathBuf, Vec<BinEntry>> = HashMap::new();
+
+    let mut results: Vec<_> = items
+        .iter()
+        .rev()
+        .map(|item| match item.backend {
+            Backend::Permanent => Err(RestoreError::Unrecoverable),
+            Backend::LinuxTrash => {
+                let info_path =
+                    PathBuf::from(item.trash_path.as_ref().ok_or(RestoreError::NotInTrash)?);
+
+                // $trash/info/foo.trashinfo -> $trash/files/foo
+                let data_path = info_path
+                    .parent()
+                    .and_then(Path::parent)
+                    .zip(info_path.file_stem())
+                    .map(|(trash, name)| trash.join("files").join(name))
+                    .ok_or(RestoreError::NotInTrash)?;
+
+                move_back(&data_path, &info_path, Path::new(&item.path))
+            }
+            Backend::WindowsBin => {
+                let windows_path = item.windows_path.as_deref().unwrap_or_default();
+
+                // UNC paths (e.g. \\wsl.localhost) don't have a recycle bin; the shell would have
+                // deleted the item permanently.
+                let Some(bin) = recycle_bin_index::bin_for(windows_path) else {
+                    return Err(RestoreError::Unrecoverable);
+                };
+
+                let entries = bins
+                    .entry(bin)
+                    .or_insert_with_key(|bin| recycle_bin_index::list(bin).unwrap_or_default());
+                let bin_entry = recycle_bin_index::find(entries, windows_path, since)
+                    .ok_or(RestoreError::NotInTrash)?;
+
+                move_back(
+                    &bin_entry.data_path,
+                    &bin_entry.info_path,
+                    Path::new(&item.path),
+                )
+            }
+        })
+        .collect();
+
+    results.reverse();
+    results
+}
+
+/// Restores the items of the last `count` entries, newest first, calling `report` with the result
+/// for each item. Items that were restored, or can never be, are removed from `entries`, along with
+/// any entries left empty; the rest are kept so that they can be retried.
+///
+/// # Panics
+///
+/// Never; `restore` returns a result for every item.
+pub fn undo(
+    entries: &mut Vec<JournalEntry>,
+    count: usize,
+    mut report: impl FnMut(&JournalItem, &Result<(), RestoreError>),
+) {
+    let start = entries.len().saturating_sub(count);
+
+    for entry in entries[start..].iter_mut().rev() {
+        let mut results = restore(entry).into_iter();
+
+        entry.items.retain(|item| {
+            let result = results.next().unwrap();
+            report(item, &result);
+            matches!(
+                result,
+                Err(RestoreError::AlreadyExists | RestoreError::Io(_))
+            )
+        });
+    }
+
+    entries.retain(|x| !x.items.is_empty());
+}
+
+fn move_back(data_path: &Path, info_path: &Path, original_path: &Path) -> Result<(), RestoreError> {
+    if fs::symlink_metadata(data_path).is_err() {
+        return Err(RestoreError::NotInTrash);
+    }
+
+    if fs::symlink_metadata(original_path).is_ok() {
+        return Err(RestoreError::AlreadyExists);
+    }
+
+    if let Some(parent) = original_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    match fs::rename(data_path, original_path) {
+        // The home trash fallback may have copied the item from another file system
+        Err(err) if err.kind() == ErrorKind::CrossesDevices => copy_back(data_path, original_path)?,
+        result => result?,
+    }
+
+    fs::remove_file(info_path)?;
+
+    Ok(())
+}
+
+fn copy_back(data_path: &Path, original_path: &Path) -> io::Result<()> {
+    if let Err(err) = freedesktop_trash::copy_tree(data_path, original_path, &mut |_| {}) {
+        // Clean up the partial copy
+        let _ = freedesktop_trash::delete(original_path);
+        return Err(err);
+    }
+
+    freedesktop_trash::delete(data_path).map_err(|err| match err {
+        remove_tree::RemoveError::Io(err) => err,
+        err => io::Error::other(err.to_string()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Creates a temp dir with a trash can in it, returning the paths to both.
+    fn setup(name: &str) -> (PathBuf, PathBuf) {
+        let dir = env::temp_dir().join(format!("wsl-tools-recycle-journal-{name}"));
+        let _ = fs::remove_dir_all(&dir);
+        let trash = dir.join("trash");
+        fs::create_dir_all(trash.join("files")).unwrap();
+        fs::create_dir_all(trash.join("info")).unwrap();
+        (dir, trash)
+    }
+
+    /// Puts `contents` in the trash as `name`, returning a journal item for `original`.
+    fn trashed(trash: &Path, name: &str, original: &Path, contents: Option<&str>) -> JournalItem {
+        let data_path = trash.join("files").join(name);
+        match contents {
+            Some(contents) => fs::write(&data_path, contents).unwrap(),
+            None => fs::create_dir(&data_path).unwrap(),
+        }
+
+        let info_path = trash.join("info").join(format!("{name}.trashinfo"));
+        fs::write(&info_path, "").unwrap();
+
+        JournalItem {
+            path: original.to_str().unwrap().to_owned(),
+            windows_path: None,
+            backend: Backend::LinuxTrash,
+            trash_path: Some(info_path.to_str().unwrap().to_owned()),
+        }
+    }
+
+    fn entry(items: Vec<JournalItem>) -> JournalEntry {
+        JournalEntry {
+            time: 0,
+            cwd: "/".to_owned(),
+            args: Vec::new(),
+            items,
+        }
+    }
+
+    #[test]
+    fn restores_last_to_first() {
+        let (dir, trash) = setup("order");
+        let foo = dir.join("foo");
+
+        // recycle foo/bar foo
+        let entry = entry(vec![
+            trashed(&trash, "bar", &foo.join("bar"), Some("鏡音リン")),
+            trashed(&trash, "foo", &foo, None),
+        ]);
+
+        let results = restore(&entry);
+        assert!(results.iter().all(Result::is_ok), "{results:?}");
+        assert_eq!(fs::read_to_string(foo.join("bar")).unwrap(), "鏡音リン");
+        assert!(fs::read_dir(trash.join("files")).unwrap().next().is_none());
+        assert!(fs::read_dir(trash.join("info")).unwrap().next().is_none());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn permanent_deletions_are_unrecoverable() {
+        let entry = entry(vec![JournalItem {
+            path: "/tmp/gone".to_owned(),
+            windows_path: None,
+            backend: Backend::Permanent,
+            trash_path: None,
+        }]);
+
+        let results = restore(&entry);
+        assert!(matches!(results[..], [Err(RestoreError::Unrecoverable)]));
+    }
+
+    #[test]
+    fn undo_keeps_only_retryable_items() {
+        let (dir, trash) = setup("undo");
+        fs::write(dir.join("exists"), "").unwrap();
+
+        let older = entry(vec![trashed(&trash, "older", &dir.join("older"), Some(""))]);
+        let restored = trashed(&trash, "restored", &dir.join("restored"), Some(""));
+        let blocked = trashed(&trash, "exists", &dir.join("exists"), Some(""));
+        let mut missing = restored.clone();
+        missing.path = dir.join("missing").to_str().unwrap().to_owned();
+        missing.trash_path = None;
+        let permanent = JournalItem {
+            backend: Backend::Permanent,
+            trash_path: None,
+            ..missing.clone()
+        };
+        let all_restored = entry(vec![trashed(&trash, "also", &dir.join("also"), Some(""))]);
+
+        let mut entries = vec![
+            older,
+            entry(vec![restored, blocked, missing, permanent]),
+            all_restored,
+        ];
+
+        let mut reported = Vec::new();
+        undo(&mut entries, 2, |item, result| {
+            reported.push((item.path.clone(), result.is_ok()));
+        });
+
+        assert_eq!(reported.len(), 5);
+        assert_eq!(
+            reported[0],
+            (dir.join("also").to_str().unwrap().to_owned(), true)
+        );
+
+        // The older entry wasn't undone, the last entry was emptied, and of the middle one, only
+        // the item blocked by an existing file is kept for retrying
+        assert_eq!(entries.len(), 2);
+        assert_eq!(
+            entries[0].items[0].path,
+            dir.join("older").to_str().unwrap()
+        );
+        assert_eq!(entries[1].items.len(), 1);
+        assert_eq!(
+            entries[1].items[0].path,
+            dir.join("exists").to_str().unwrap()
+        );
+        assert!(dir.join("restored").exists());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn copies_back_across_file_systems() {
+        let (dir, trash) = setup("copy");
+        let data_path = trash.join("files/item");
+        fs::create_dir_all(data_path.join("sub")).unwrap();
+        fs::write(data_path.join("sub/file"), "鏡音リン").unwrap();
+        let original_path = dir.join("original/item");
+        fs::create_dir(dir.join("original")).unwrap();
+
+        // rename() fails with EXDEV when the home trash fallback copied the item from another file
+        // system, which can't be set up here, so test the fallback directly
+        copy_back(&data_path, &original_path).unwrap();
+
+        assert_eq!(
+            fs::read_to_string(original_path.join("sub/file")).unwrap(),
+            "鏡音リン"
+        );
+        assert!(!data_path.exists());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -4,13 +4,15 @@
 #![cfg(windows)]
 #![allow(clippy::pedantic, unused_variables)]
 
-use crate::recycle_error::RecycleError;
+use crate::recycle_report::SinkEvent;
 use std::{cell::UnsafeCell, fs};
 use windows::{
-    Win32::UI::Shell::{
-        COPYENGINE_E_ACCESS_DENIED_SRC, COPYENGINE_E_SHARING_VIOLATION_SRC,
-        COPYENGINE_E_USER_CANCELLED, IFileOperationProgressSink, IFileOperationProgressSink_Impl,
-        IShellItem, SIGDN_DESKTOPABSOLUTEEDITING,
+    Win32::{
+        Foundation::E_ABORT,
+        UI::Shell::{
+            IFileOperationProgressSink, IFileOperationProgressSink_Impl, IShellItem,
+            SIGDN_DESKTOPABSOLUTEEDITING, TSF_DELETE_RECYCLE_IF_POSSIBLE,
+        },
     },
     core::{Error, HRESULT, PCWSTR, Ref, Result, implement},
 };
@@ -29,18 +31,28 @@ fn get_shell_item_path(
 #[implement(IFileOperationProgressSink)]
 #[allow(clippy::type_complexity)]
 pub struct RecycleProgressSink<'a> {
-    callback: UnsafeCell<Box<dyn FnMut(String, Option<RecycleError>) + 'a>>,
+    callback: UnsafeCell<Box<dyn FnMut(SinkEvent) + 'a>>,
+    refuse_permanent_delete: bool,
 }
 
 impl<'a> RecycleProgressSink<'a> {
     pub fn new<T>(callback: T) -> Self
     where
-        T: FnMut(String, Option<RecycleError>) + 'a,
+        T: FnMut(SinkEvent) + 'a,
     {
         RecycleProgressSink {
             callback: UnsafeCell::new(Box::new(callback)),
+            refuse_permanent_delete: false,
         }
     }
+
+    /// Abort the operation if the shell is about to delete an item permanently rather than recycle
+    /// it. Note that `dwflags` applies to the whole operation, not just the item in question; see
+    /// `recycle_safe`.
+    pub fn refuse_permanent_delete(mut self, value: bool) -> Self {
+        self.refuse_permanent_delete = value;
+        self
+    }
 }
 
 impl IFileOperationProgressSink_Impl for RecycleProgressSink_Impl<'_> {
@@ -56,45 +68,39 @@ impl IFileOperationProgressSink_Impl for RecycleProgressSink_Impl<'_> {
             format!("<Error: {err}>")
         });
 
-        let error = if hrdelete.is_ok() {
-            // Note: PostDeleteItem is sometimes called *before* the item is actually deleted, so
-            // checking here to see if the file was actually deleted or not will not work. We'll
-            // assume it's ok, as that's an edge case, but we need to double-check after calling
-            // PerformOperations, even if GetAnyOperationsAborted returns false. IFileOperation is a
-            // buggy mess. See https://github.com/maxkagamine/wsl-tools/issues/5.
-            None
-        } else if hrdelete == COPYENGINE_E_ACCESS_DENIED_SRC {
-            Some(RecycleError::AccessDenied)
-        } else if hrdelete == COPYENGINE_E_SHARING_VIOLATION_SRC {
-            if fs::symlink_metadata(&path).is_ok_and(|m| m.is_dir()) {
-                Some(RecycleError::FolderInUse)
-            } else {
-                Some(RecycleError::FileInUse)
-            }
-        } else if hrdelete == COPYENGINE_E_USER_CANCELLED {
-            Some(RecycleError::Canceled)
-        } else {
-            Some(Error::from_hresult(hrdelete).into())
-        };
+        // Note: PostDeleteItem is sometimes called *before* the item is actually deleted, so
+        // checking here to see if the file was actually deleted or not will not work. We need to
+        // double-check after calling PerformOperations, even if the HRESULT is ok and
+        // GetAnyOperationsAborted returns false. IFileOperation is a buggy mess. See
+        // https://github.com/maxkagamine/wsl-tools/issues/5.
+        let is_dir = hrdelete.is_err() && fs::symlink_metadata(&path).is_ok_and(|m| m.is_dir());
 
         unsafe {
-            (*self.callback.get())(path, error);
+            (*self.callback.get())(SinkEvent::PostDeleteItem {
+                path,
+                hresult: hrdelete,
+                is_dir,
+            });
         }
 
         Ok(())
     }
 
     fn PreDeleteItem(&self, dwflags: u32, psiitem: Ref<'_, IShellItem>) -> Result<()> {
-        // This is how we might attempt to prevent the shell from permanently deleting items when
-        // dialogs are disabled. As discussed, though, this doesn't work in all circumstances.
-        // const TSF_DELETE_RECYCLE_IF_POSSIBLE: u32 = 0x80;
-        // if dwflags & TSF_DELETE_RECYCLE_IF_POSSIBLE == TSF_DELETE_RECYCLE_IF_POSSIBLE {
-        //     Ok(())
-        // } else {
-        //     println!("aborting from sink! dwflags = {dwflags}"); // TODO: Remove
-        //     Err(Error::from_hresult(E_ABORT))
-        // }
-        Ok(())
+        // This is how we prevent the shell from permanently deleting items when dialogs are
+        // disabled. As discussed, though, this doesn't work in all circumstances (namely files too
+        // big for the Recycle Bin, which the shell claims it can recycle).
+        let recycle = TSF_DELETE_RECYCLE_IF_POSSIBLE.0 as u32;
+        if !self.refuse_permanent_delete || dwflags & recycle == recycle {
+            return Ok(());
+        }
+
+        let path = get_shell_item_path(&psiitem).unwrap_or_else(|err| format!("<Error: {err}>"));
+        unsafe {
+            (*self.callback.get())(SinkEvent::Refused { path });
+        }
+
+        Err(Error::from_hresult(E_ABORT))
     }
 
     // region: No-ops
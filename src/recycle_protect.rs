@@ -0,0 +1,357 @@
+// Copyright (c) Max Kagamine
+// Licensed under the Apache License, Version 2.0
+
+/// Rules used when the ini doesn't have any `protect` keys.
+pub const DEFAULT_RULES: &[&str] = &[
+    "~",
+    "~/.ssh",
+    "~/.gnupg",
+    "/mnt/*/Windows",
+    "/mnt/*/Users/*",
+];
+
+/// A path pattern that `recycle` refuses to remove, or remove anything containing.
+///
+/// Patterns are matched one path component at a time: `*` and `?` match within a component, and
+/// `**` matches any number of components. Patterns starting with `/` (or `~`, which expands to the
+/// home directory) are matched against the full WSL path; patterns containing a backslash or
+/// starting with a drive letter are Windows patterns, matched case-insensitively against the
+/// Windows translation. Anything else, such as `*/.git`, can match at any depth.
+#[derive(Debug, Clone)]
+pub struct ProtectRule {
+    rule: String,
+    components: Vec<String>,
+    windows: bool,
+    anchored: bool,
+}
+
+/// Why a path was refused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Protection<'a> {
+    /// The path matches the rule.
+    Matches(&'a str),
+    /// The path is a directory that could contain something matching the rule.
+    Contains(&'a str),
+}
+
+impl ProtectRule {
+    /// Parses a rule as written in the config, expanding a leading `~` to `home`.
+    ///
+    /// # Returns
+    /// `None` if the rule starts with `~` but `home` isn't set (or isn't absolute). Left as-is, it
+    /// would match any directory named `~` rather than protecting the home directory.
+    #[must_use]
+    pub fn new(rule: &str, home: Option<&str>) -> Option<Self> {
+        let windows = rule.contains('\\') || is_drive_path(rule);
+
+        let expanded = match rule.strip_prefix('~') {
+            Some(rest) if !windows && (rest.is_empty() || rest.starts_with('/')) => {
+                let home = home.filter(|x| x.starts_with('/'))?.trim_end_matches('/');
+                match format!("{home}{rest}") {
+                    x if x.is_empty() => "/".to_owned(),
+                    x => x,
+                }
+            }
+            _ => rule.to_owned(),
+        };
+
+        let anchored = if windows {
+            is_drive_path(&expanded) || expanded.starts_with('\\')
+        } else {
+            expanded.starts_with('/')
+        };
+
+        Some(Self {
+            rule: rule.to_owned(),
+            components: split(&expanded, windows),
+            windows,
+            anchored,
+        })
+    }
+
+    /// The rule as written in the config.
+    #[must_use]
+    pub fn rule(&self) -> &str {
+        &self.rule
+    }
+
+    fn check(&self, linux_path: &str, windows_path: Option<&str>) -> Option<Protection<'_>> {
+        // drvfs is case-insensitive, so /mnt/c/windows needs to match /mnt/c/Windows
+        let (path, case_insensitive) = if self.windows {
+            (split(windows_path?, true), true)
+        } else {
+            (
+                split(linux_path, false),
+                windows_path.is_some_and(is_drive_path),
+            )
+        };
+
+        let matches = if self.anchored {
+            match_components(&self.components, &path, case_insensitive)
+        } else {
+            (0..=path.len())
+                .any(|i| match_components(&self.components, &path[i..], case_insensitive))
+        };
+
+        if matches {
+            return Some(Protection::Matches(&self.rule));
+        }
+
+        // Refuse parents of protected paths as well (e.g. /mnt/c for /mnt/*/Windows). Only
+        // possible for anchored rules; recycling a directory containing a .git is fine.
+        if self.anchored && could_contain(&self.components, &path, case_insensitive) {
+            return Some(Protection::Contains(&self.rule));
+        }
+
+        None
+    }
+}
+
+/// Parses the rules from the config, falling back to `DEFAULT_RULES` if none are set. An empty
+/// `protect =` disables the defaults.
+///
+/// # Returns
+/// The rules, and any that were skipped because they're relative to the home directory and `home`
+/// isn't set (see `ProtectRule::new`), for the caller to warn about.
+#[must_use]
+pub fn parse_rules<'a>(
+    rules: impl IntoIterator<Item = &'a str>,
+    home: Option<&str>,
+) -> (Vec<ProtectRule>, Vec<&'a str>) {
+    let rules: Vec<&str> = rules.into_iter().collect();
+    let rules = if rules.is_empty() {
+        DEFAULT_RULES
+    } else {
+        &rules
+    };
+
+    let mut parsed = Vec::new();
+    let mut skipped = Vec::new();
+
+    for rule in rules.iter().map(|x| x.trim()).filter(|x| !x.is_empty()) {
+        match ProtectRule::new(rule, home) {
+            Some(x) => parsed.push(x),
+            None => skipped.push(rule),
+        }
+    }
+
+    (parsed, skipped)
+}
+
+/// Returns the first rule protecting the item at `linux_path` (absolute, with the dirname's
+/// symlinks resolved), whose Windows translation is `windows_path`.
+#[must_use]
+pub fn check<'a>(
+    rules: &'a [ProtectRule],
+    linux_path: &str,
+    windows_path: Option<&str>,
+) -> Option<Protection<'a>> {
+    rules.iter().find_map(|x| x.check(linux_path, windows_path))
+}
+
+fn is_drive_path(path: &str) -> bool {
+    let mut chars = path.chars();
+    chars.next().is_some_and(|x| x.is_ascii_alphabetic()) && chars.next() == Some(':')
+}
+
+fn split(path: &str, windows: bool) -> Vec<String> {
+    path.split(|c| c == '/' || (windows && c == '\\'))
+        .filter(|x| !x.is_empty() && *x != ".")
+        .map(ToOwned::to_owned)
+        .collect()
+}
+
+fn match_components(pattern: &[String], path: &[String], case_insensitive: bool) -> bool {
+    match pattern.split_first() {
+        None => path.is_empty(),
+        Some((first, rest)) if first == "**" => {
+            (0..=path.len()).any(|i| match_components(rest, &path[i..], case_insensitive))
+        }
+        Some((first, rest)) => path.split_first().is_some_and(|(x, path_rest)| {
+            wildcard(first, x, case_insensitive)
+                && match_components(rest, path_rest, case_insensitive)
+        }),
+    }
+}
+
+/// Whether `path` is an ancestor of something the pattern could match.
+fn could_contain(pattern: &[String], path: &[String], case_insensitive: bool) -> bool {
+    if path.len() >= pattern.len() {
+        return false;
+    }
+
+    for (pattern, x) in pattern.iter().zip(path) {
+        if pattern == "**" {
+            return true;
+        }
+        if !wildcard(pattern, x, case_insensitive) {
+            return false;
+        }
+    }
+
+    true
+}
+
+fn wildcard(pattern: &str, text: &str, case_insensitive: bool) -> bool {
+    let normalize = |s: &str| -> Vec<char> {
+        if case_insensitive {
+            s.to_lowercase().chars().collect()
+        } else {
+            s.chars().collect()
+        }
+    };
+    let (pattern, text) = (normalize(pattern), normalize(text));
+
+    // Standard greedy matching with backtracking to the last *
+    let (mut p, mut t) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match star {
+                Some((star_p, star_t)) => {
+                    p = star_p + 1;
+                    t = star_t + 1;
+                    star = Some((star_p, star_t + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|&x| x == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOME: Option<&str> = Some("/home/rin");
+
+    fn rules(rules: &[&str]) -> Vec<ProtectRule> {
+        parse_rules(rules.iter().copied(), HOME).0
+    }
+
+    #[test]
+    fn expands_home() {
+        let rules = rules(&["~", "~/.ssh"]);
+        assert_eq!(
+            check(&rules, "/home/rin", None),
+            Some(Protection::Matches("~"))
+        );
+        assert_eq!(
+            check(&rules, "/home/rin/.ssh", None),
+            Some(Protection::Matches("~/.ssh"))
+        );
+        assert_eq!(check(&rules, "/home/rin/.ssh/known_hosts", None), None);
+        assert_eq!(
+            check(&rules, "/home", None),
+            Some(Protection::Contains("~"))
+        );
+        assert_eq!(check(&rules, "/home/len", None), None);
+    }
+
+    #[test]
+    fn skips_home_rules_without_home() {
+        let (rules, skipped) = parse_rules(["~", "~/.ssh", "/mnt/*/Windows"], None);
+        assert_eq!(skipped, ["~", "~/.ssh"]);
+        assert_eq!(rules.len(), 1);
+        assert_eq!(rules[0].rule(), "/mnt/*/Windows");
+        assert_eq!(check(&rules, "/tmp/~", None), None);
+
+        assert!(ProtectRule::new("~", Some("relative")).is_none());
+        assert!(ProtectRule::new("~foo", None).is_some());
+
+        let (rules, _) = parse_rules(["~"], Some("/"));
+        assert_eq!(check(&rules, "/", None), Some(Protection::Matches("~")));
+        assert_eq!(check(&rules, "/tmp", None), None);
+    }
+
+    #[test]
+    fn matches_globs() {
+        let rules = rules(&["/mnt/*/Users/*", "*/.git"]);
+        assert_eq!(
+            check(&rules, "/mnt/c/Users/Rin", Some(r"C:\Users\Rin")),
+            Some(Protection::Matches("/mnt/*/Users/*"))
+        );
+        assert_eq!(
+            check(&rules, "/mnt/c/users", Some(r"C:\users")),
+            Some(Protection::Contains("/mnt/*/Users/*"))
+        );
+        assert_eq!(
+            check(&rules, "/mnt/c", Some(r"C:\")),
+            Some(Protection::Contains("/mnt/*/Users/*"))
+        );
+        assert_eq!(
+            check(
+                &rules,
+                "/mnt/c/Users/Rin/foo.txt",
+                Some(r"C:\Users\Rin\foo.txt")
+            ),
+            None
+        );
+        assert_eq!(
+            check(&rules, "/home/rin/src/wsl-tools/.git", None),
+            Some(Protection::Matches("*/.git"))
+        );
+        assert_eq!(check(&rules, "/home/rin/src/wsl-tools", None), None);
+    }
+
+    #[test]
+    fn linux_rules_are_case_sensitive_outside_drvfs() {
+        let rules = rules(&["~/Music"]);
+        assert_eq!(check(&rules, "/home/rin/music", None), None);
+        assert_eq!(
+            check(&rules, "/home/rin/Music", None),
+            Some(Protection::Matches("~/Music"))
+        );
+    }
+
+    #[test]
+    fn matches_windows_rules_against_translation() {
+        let rules = rules(&[r"C:\Windows", r"**\node_modules"]);
+        assert_eq!(
+            check(&rules, "/mnt/c/WINDOWS", Some(r"C:\WINDOWS")),
+            Some(Protection::Matches(r"C:\Windows"))
+        );
+        assert_eq!(
+            check(
+                &rules,
+                "/home/rin/app/node_modules",
+                Some(r"\\wsl.localhost\Ubuntu\home\rin\app\node_modules")
+            ),
+            Some(Protection::Matches(r"**\node_modules"))
+        );
+        assert_eq!(
+            check(
+                &rules,
+                "/home/rin/Windows",
+                Some(r"\\wsl.localhost\Ubuntu\home\rin\Windows")
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn falls_back_to_defaults() {
+        assert_eq!(rules(&[]).len(), DEFAULT_RULES.len());
+        assert!(rules(&[""]).is_empty());
+    }
+
+    #[test]
+    fn wildcards() {
+        assert!(wildcard("*.txt", "a.b.txt", false));
+        assert!(wildcard("a?c", "abc", false));
+        assert!(wildcard("*", "", false));
+        assert!(!wildcard("a*c", "abd", false));
+        assert!(wildcard("ミ*ン", "ミカン", false));
+    }
+}
@@ -0,0 +1,290 @@
+// Copyright (c) Max Kagamine
+// Licensed under the Apache License, Version 2.0
+
+#![cfg(windows)]
+
+use crate::recycle_error::RecycleError;
+use std::path::PathBuf;
+use windows::{
+    Win32::UI::Shell::{
+        COPYENGINE_E_ACCESS_DENIED_SRC, COPYENGINE_E_SHARING_VIOLATION_SRC,
+        COPYENGINE_E_USER_CANCELLED,
+    },
+    core::{Error, HRESULT},
+};
+
+/// The result of `recycle_with_report`: one entry per input path, in the same order.
+#[derive(Debug)]
+pub struct RecycleReport {
+    pub items: Vec<ItemReport>,
+    /// `GetAnyOperationsAborted` returned true, or the operation was canceled.
+    pub aborted: bool,
+}
+
+#[derive(Debug)]
+pub struct ItemReport {
+    /// The path as given.
+    pub path: String,
+    /// The path made absolute, as passed to the shell.
+    pub absolute_path: PathBuf,
+    pub outcome: ItemOutcome,
+    /// The HRESULT from `PostDeleteItem`, if the sink was called for this item.
+    pub hresult: Option<HRESULT>,
+}
+
+#[derive(Debug)]
+pub enum ItemOutcome {
+    /// The shell reported success and the item is gone. With `dangerously_in_background`,
+    /// it may have been deleted permanently instead; see `recycle`.
+    Recycled,
+    /// Didn't exist and was skipped due to `ignore_not_found`.
+    Skipped,
+    Failed(RecycleError),
+    /// The shell reported success, but the item is still there. This can happen when the file is
+    /// in WSL and the default user doesn't have permission to delete it; see
+    /// <https://github.com/maxkagamine/wsl-tools/issues/5>.
+    StillExists,
+}
+
+/// What the `IFileOperationProgressSink` saw, recorded for `resolve`.
+#[derive(Debug, Clone)]
+pub enum SinkEvent {
+    PostDeleteItem {
+        /// The item's absolute path from `GetDisplayName`.
+        path: String,
+        hresult: HRESULT,
+        /// Whether the item was a directory at the time, to tell "file in use" from "folder in
+        /// use".
+        is_dir: bool,
+    },
+    /// `PreDeleteItem` aborted the operation because the shell was going to delete the item
+    /// permanently, with `safe_background`.
+    Refused { path: String },
+}
+
+/// An input path after the pre-checks, before `resolve`.
+#[derive(Debug, Clone)]
+pub struct PendingItem {
+    pub path: String,
+    pub absolute_path: PathBuf,
+    pub state: PendingState,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PendingState {
+    /// Didn't exist and was skipped.
+    Missing,
+    /// Added to the operation. `still_exists` is checked after `PerformOperations`.
+    Queued { still_exists: bool },
+}
+
+impl RecycleReport {
+    /// Converts the report into the single result returned by `recycle`.
+    ///
+    /// # Errors
+    ///
+    /// `Canceled` if the operation was aborted or any item failed, or `Unknown` if any items still
+    /// exist despite the shell reporting success.
+    pub fn into_result(self) -> Result<(), RecycleError> {
+        if self.aborted
+            || self
+                .items
+                .iter()
+                .any(|x| matches!(x.outcome, ItemOutcome::Failed(_)))
+        {
+            return Err(RecycleError::Canceled);
+        }
+
+        if self
+            .items
+            .iter()
+            .any(|x| matches!(x.outcome, ItemOutcome::StillExists))
+        {
+            return Err(RecycleError::Unknown);
+        }
+
+        Ok(())
+    }
+}
+
+/// Works out the outcome of each item from the sink's events. Events are matched to items by
+/// absolute path, case-insensitively. Queued items the sink wasn't called for are considered
+/// canceled if the operation was aborted (the shell never got to them), or otherwise judged by
+/// whether they still exist.
+#[must_use]
+pub fn resolve(items: Vec<PendingItem>, events: &[SinkEvent], aborted: bool) -> RecycleReport {
+    let items = items
+        .into_iter()
+        .map(|item| {
+            let absolute = item.absolute_path.to_string_lossy().to_lowercase();
+            let event = events.iter().rev().find_map(|event| match event {
+                SinkEvent::PostDeleteItem {
+                    path,
+                    hresult,
+                    is_dir,
+                } if path.to_lowercase() == absolute => Some((*hresult, *is_dir)),
+                _ => None,
+            });
+            let refused = events.iter().any(|event| {
+                matches!(event, SinkEvent::Refused { path } if path.to_lowercase() == absolute)
+            });
+
+            let outcome = match (item.state, event) {
+                (PendingState::Missing, _) => ItemOutcome::Skipped,
+                _ if refused => ItemOutcome::Failed(RecycleError::CannotRecycle),
+                (_, Some((hresult, is_dir))) if hresult.is_err() => {
+                    ItemOutcome::Failed(error_from_hresult(hresult, is_dir))
+                }
+                (
+                    PendingState::Queued {
+                        still_exists: false,
+                    },
+                    _,
+                ) => ItemOutcome::Recycled,
+                // The shell never got to it
+                (_, None) if aborted => ItemOutcome::Failed(RecycleError::Canceled),
+                _ => ItemOutcome::StillExists,
+            };
+
+            ItemReport {
+                path: item.path,
+                absolute_path: item.absolute_path,
+                outcome,
+                hresult: event.map(|(hresult, _)| hresult),
+            }
+        })
+        .collect();
+
+    RecycleReport { items, aborted }
+}
+
+fn error_from_hresult(hresult: HRESULT, is_dir: bool) -> RecycleError {
+    if hresult == COPYENGINE_E_ACCESS_DENIED_SRC {
+        RecycleError::AccessDenied
+    } else if hresult == COPYENGINE_E_SHARING_VIOLATION_SRC {
+        if is_dir {
+            RecycleError::FolderInUse
+        } else {
+            RecycleError::FileInUse
+        }
+    } else if hresult == COPYENGINE_E_USER_CANCELLED {
+        RecycleError::Canceled
+    } else {
+        Error::from_hresult(hresult).into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use windows::Win32::Foundation::{E_ABORT, E_FAIL, S_OK};
+
+    fn queued(path: &str, still_exists: bool) -> PendingItem {
+        PendingItem {
+            path: path.to_owned(),
+            absolute_path: PathBuf::from(format!(r"C:\test\{path}")),
+            state: PendingState::Queued { still_exists },
+        }
+    }
+
+    fn deleted(path: &str, hresult: HRESULT) -> SinkEvent {
+        SinkEvent::PostDeleteItem {
+            path: format!(r"C:\Test\{path}"),
+            hresult,
+            is_dir: false,
+        }
+    }
+
+    #[test]
+    fn resolves_outcomes_from_events() {
+        let items = vec![
+            queued("ok.txt", false),
+            PendingItem {
+                state: PendingState::Missing,
+                ..queued("missing.txt", false)
+            },
+            queued("locked.txt", true),
+            queued("lied.txt", true),
+        ];
+        let events = [
+            deleted("ok.txt", S_OK),
+            deleted("locked.txt", COPYENGINE_E_SHARING_VIOLATION_SRC),
+            deleted("lied.txt", S_OK),
+        ];
+
+        let report = resolve(items, &events, true);
+
+        assert!(report.aborted);
+        assert!(matches!(report.items[0].outcome, ItemOutcome::Recycled));
+        assert_eq!(report.items[0].hresult, Some(S_OK));
+        assert!(matches!(report.items[1].outcome, ItemOutcome::Skipped));
+        assert_eq!(report.items[1].hresult, None);
+        assert!(matches!(
+            report.items[2].outcome,
+            ItemOutcome::Failed(RecycleError::FileInUse)
+        ));
+        assert_eq!(
+            report.items[2].hresult,
+            Some(COPYENGINE_E_SHARING_VIOLATION_SRC)
+        );
+        assert!(matches!(report.items[3].outcome, ItemOutcome::StillExists));
+    }
+
+    #[test]
+    fn refused_items_cannot_be_recycled() {
+        let events = [
+            SinkEvent::Refused {
+                path: r"C:\Test\a.txt".to_owned(),
+            },
+            deleted("a.txt", E_ABORT),
+        ];
+        let report = resolve(
+            vec![queued("a.txt", true), queued("b.txt", true)],
+            &events,
+            true,
+        );
+
+        assert!(matches!(
+            report.items[0].outcome,
+            ItemOutcome::Failed(RecycleError::CannotRecycle)
+        ));
+        assert!(matches!(
+            report.items[1].outcome,
+            ItemOutcome::Failed(RecycleError::Canceled)
+        ));
+    }
+
+    #[test]
+    fn items_without_events() {
+        let report = resolve(
+            vec![queued("a.txt", true), queued("b.txt", false)],
+            &[],
+            true,
+        );
+        assert!(matches!(
+            report.items[0].outcome,
+            ItemOutcome::Failed(RecycleError::Canceled)
+        ));
+        assert!(matches!(report.items[1].outcome, ItemOutcome::Recycled));
+
+        let report = resolve(vec![queued("a.txt", true)], &[], false);
+        assert!(matches!(report.items[0].outcome, ItemOutcome::StillExists));
+        assert!(matches!(report.into_result(), Err(RecycleError::Unknown)));
+    }
+
+    #[test]
+    fn maps_hresults_to_errors() {
+        assert!(matches!(
+            error_from_hresult(COPYENGINE_E_ACCESS_DENIED_SRC, false),
+            RecycleError::AccessDenied
+        ));
+        assert!(matches!(
+            error_from_hresult(COPYENGINE_E_SHARING_VIOLATION_SRC, true),
+            RecycleError::FolderInUse
+        ));
+        assert!(matches!(
+            error_from_hresult(E_FAIL, false),
+            RecycleError::Win32(err) if err.code() == E_FAIL
+        ));
+    }
+}
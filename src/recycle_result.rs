@@ -0,0 +1,184 @@
+// Copyright (c) Max Kagamine
+// Licensed under the Apache License, Version 2.0
+
+use serde::{Deserialize, Serialize};
+
+/// Where an item was sent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Backend {
+    /// Handed to `recycle.exe`. Note that with `--rm`, the shell may have nuked it instead.
+    WindowsBin,
+    /// Moved to the Freedesktop.org trash can.
+    LinuxTrash,
+    /// Deleted permanently Linux-side.
+    Permanent,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Outcome {
+    Removed,
+    /// Nonexistent and ignored due to `--force`.
+    Skipped,
+    Failed,
+}
+
+/// With `--verify`, whether an item handed to `recycle.exe` was actually found in the Recycle Bin
+/// afterwards.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Verification {
+    Recycled,
+    /// Not in the bin, so the shell must have deleted it permanently.
+    Deleted,
+    /// The bin couldn't be read.
+    Unknown,
+}
+
+/// Machine-readable reason for an item failing. The Windows-side codes correspond to the variants
+/// of `RecycleError`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ErrorCode {
+    NotFound,
+    InvalidPath,
+    AccessDenied,
+    FileInUse,
+    FolderInUse,
+    IsDirectory,
+    RefusedRoot,
+    /// The item's mount has no Linux trash can, and the fallback is to refuse.
+    NoTrash,
+    /// Skipped due to `--one-file-system`.
+    OtherFileSystem,
+    /// Matched a `protect` rule in the config.
+    Protected,
+    /// Likely too big for the Recycle Bin with `--rm`.
+    TooLarge,
+    /// Contains modified or untracked files in a git work tree, with `--git-safe`.
+    Uncommitted,
+    /// A `.git` directory (or inside one) other than as part of the whole repo, with `--git-safe`.
+    GitDirectory,
+    /// The shell couldn't recycle the item and `--safe` prevented it from being nuked.
+    CannotRecycle,
+    Canceled,
+    Win32,
+    Io,
+    Unknown,
+}
+
+/// The result of recycling a single item, as output by `recycle --json` (one object per line).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecycleResult {
+    /// The path as given on the command line.
+    pub path: String,
+
+    /// The path as passed to the shell, if it could be translated.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub windows_path: Option<String>,
+
+    pub backend: Backend,
+    pub outcome: Outcome,
+
+    /// The item's `.trashinfo` file, if it was moved to the Linux trash.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trash_path: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<ErrorCode>,
+
+    /// Human-readable description of the error, the same as would be printed without `--json`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+
+    /// Set for Recycle Bin items with `--verify`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub verified: Option<Verification>,
+}
+
+impl RecycleResult {
+    #[must_use]
+    pub fn removed(path: String, windows_path: Option<String>, backend: Backend) -> Self {
+        Self {
+            path,
+            windows_path,
+            backend,
+            outcome: Outcome::Removed,
+            trash_path: None,
+            error: None,
+            message: None,
+            verified: None,
+        }
+    }
+
+    #[must_use]
+    pub fn skipped(path: String, windows_path: Option<String>, backend: Backend) -> Self {
+        Self {
+            outcome: Outcome::Skipped,
+            ..Self::removed(path, windows_path, backend)
+        }
+    }
+
+    #[must_use]
+    pub fn failed(
+        path: String,
+        windows_path: Option<String>,
+        backend: Backend,
+        error: ErrorCode,
+        message: String,
+    ) -> Self {
+        Self {
+            outcome: Outcome::Failed,
+            error: Some(error),
+            message: Some(message),
+            ..Self::removed(path, windows_path, backend)
+        }
+    }
+
+    /// Serializes the result as a single line of JSON (without the trailing newline).
+    ///
+    /// # Panics
+    /// Never; all of the fields are infallibly serializable.
+    #[must_use]
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn serializes_with_kebab_case_codes_and_omits_empty_fields() {
+        let removed = RecycleResult::removed("foo".into(), None, Backend::LinuxTrash);
+        assert_eq!(
+            removed.to_json(),
+            r#"{"path":"foo","backend":"linux-trash","outcome":"removed"}"#
+        );
+
+        let failed = RecycleResult::failed(
+            "bar".into(),
+            Some(r"C:\bar".into()),
+            Backend::WindowsBin,
+            ErrorCode::FileInUse,
+            "The file is open in another program.".into(),
+        );
+        assert_eq!(
+            failed.to_json(),
+            r#"{"path":"bar","windows_path":"C:\\bar","backend":"windows-bin","outcome":"failed","error":"file-in-use","message":"The file is open in another program."}"#
+        );
+    }
+
+    #[test]
+    fn round_trips() {
+        let result = RecycleResult::skipped(
+            "リン.txt".into(),
+            Some(r"C:\リン.txt".into()),
+            Backend::WindowsBin,
+        );
+        let parsed: RecycleResult = serde_json::from_str(&result.to_json()).unwrap();
+        assert_eq!(parsed, result);
+    }
+}
@@ -0,0 +1,205 @@
+// Copyright (c) Max Kagamine
+// Licensed under the Apache License, Version 2.0
+
+// The only way to recycle without dialogs that won't nuke files is to abort from `PreDeleteItem`
+// whenever the shell says it can't recycle. But the `dwflags` it gives us are per operation, not
+// per item, so one unrecyclable file makes the whole batch look unrecyclable. And since running an
+// operation per item is several times slower, we try the whole batch first and only fall back to
+// one-by-one if it was refused. See the remarks on `recycle_bin::recycle`.
+//
+// The decision logic lives here, separate from `IFileOperation`, so that it can be tested.
+
+/// What happened to an item in a single operation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Attempt<T> {
+    /// Recycled, failed, or otherwise finished with; won't be retried.
+    Done(T),
+    /// The shell was going to delete it permanently, so the operation was aborted.
+    Refused,
+    /// The operation was aborted before the shell got to it.
+    NotReached,
+}
+
+/// A recycle operation, such as an `IFileOperation` with a sink that aborts instead of letting the
+/// shell delete anything permanently.
+pub trait RecycleOperation {
+    type Outcome;
+    type Error;
+
+    /// Recycles the items at the given indices in a single operation, returning one `Attempt` per
+    /// index, in the same order.
+    ///
+    /// # Errors
+    ///
+    /// The operation couldn't be performed at all.
+    fn recycle(&mut self, indices: &[usize]) -> Result<Vec<Attempt<Self::Outcome>>, Self::Error>;
+}
+
+/// Recycles `count` items using `op`, first as one batch and then, if that was refused, one-by-one
+/// so that only the items that can't be recycled are refused. Items that the batch finished with
+/// (including those recycled before it was aborted) aren't retried. Returns one `Attempt` per item.
+///
+/// # Errors
+///
+/// Returns the first error from `op`.
+pub fn recycle_safely<O: RecycleOperation>(
+    op: &mut O,
+    count: usize,
+) -> Result<Vec<Attempt<O::Outcome>>, O::Error> {
+    let indices: Vec<usize> = (0..count).collect();
+    let mut attempts = op.recycle(&indices)?;
+
+    // Nothing to gain from retrying a batch of one, or one that was aborted for some other reason
+    // (e.g. the user clicked cancel on a dialog)
+    if count <= 1 || !attempts.iter().any(|x| matches!(x, Attempt::Refused)) {
+        return Ok(attempts);
+    }
+
+    for (i, attempt) in attempts.iter_mut().enumerate() {
+        if matches!(attempt, Attempt::Refused | Attempt::NotReached) {
+            *attempt = op.recycle(&[i])?.pop().unwrap_or(Attempt::NotReached);
+        }
+    }
+
+    Ok(attempts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    /// Behaves like the shell: if any item in an operation can't be recycled, `PreDeleteItem` says
+    /// none of them can, so the first item not yet recycled is refused and the rest never reached.
+    #[derive(Default)]
+    struct MockOperation {
+        unrecyclable: HashSet<usize>,
+        /// Items the shell gets through before noticing (`dwflags` is only mostly consistent).
+        recycled_before_refusing: usize,
+        fail_on_call: Option<usize>,
+        calls: Vec<Vec<usize>>,
+    }
+
+    impl RecycleOperation for MockOperation {
+        type Outcome = &'static str;
+        type Error = &'static str;
+
+        fn recycle(
+            &mut self,
+            indices: &[usize],
+        ) -> Result<Vec<Attempt<&'static str>>, &'static str> {
+            if self.fail_on_call == Some(self.calls.len()) {
+                return Err("catastrophic failure");
+            }
+            self.calls.push(indices.to_vec());
+
+            if !indices.iter().any(|i| self.unrecyclable.contains(i)) {
+                return Ok(indices.iter().map(|_| Attempt::Done("recycled")).collect());
+            }
+
+            let mut attempts = Vec::new();
+            let mut refused = false;
+            for (n, i) in indices.iter().enumerate() {
+                attempts.push(if refused {
+                    Attempt::NotReached
+                } else if n < self.recycled_before_refusing && !self.unrecyclable.contains(i) {
+                    Attempt::Done("recycled")
+                } else {
+                    // Not necessarily this item that can't be recycled
+                    refused = true;
+                    Attempt::Refused
+                });
+            }
+            Ok(attempts)
+        }
+    }
+
+    /// Aborted without refusing anything, as if the user canceled.
+    struct Canceled(usize);
+
+    impl RecycleOperation for Canceled {
+        type Outcome = ();
+        type Error = ();
+
+        fn recycle(&mut self, indices: &[usize]) -> Result<Vec<Attempt<()>>, ()> {
+            self.0 += 1;
+            Ok(indices.iter().map(|_| Attempt::NotReached).collect())
+        }
+    }
+
+    #[test]
+    fn recycles_in_one_batch_when_possible() {
+        let mut op = MockOperation::default();
+        let attempts = recycle_safely(&mut op, 3).unwrap();
+
+        assert_eq!(attempts, vec![Attempt::Done("recycled"); 3]);
+        assert_eq!(op.calls, vec![vec![0, 1, 2]]);
+    }
+
+    #[test]
+    fn falls_back_to_one_by_one_and_refuses_only_unrecyclable_items() {
+        let mut op = MockOperation {
+            unrecyclable: HashSet::from([1]),
+            ..Default::default()
+        };
+        let attempts = recycle_safely(&mut op, 3).unwrap();
+
+        assert_eq!(
+            attempts,
+            vec![
+                Attempt::Done("recycled"),
+                Attempt::Refused,
+                Attempt::Done("recycled"),
+            ]
+        );
+        assert_eq!(op.calls, vec![vec![0, 1, 2], vec![0], vec![1], vec![2]]);
+    }
+
+    #[test]
+    fn does_not_retry_items_the_batch_finished() {
+        let mut op = MockOperation {
+            unrecyclable: HashSet::from([3]),
+            recycled_before_refusing: 2,
+            ..Default::default()
+        };
+        let attempts = recycle_safely(&mut op, 4).unwrap();
+
+        assert_eq!(
+            attempts,
+            vec![
+                Attempt::Done("recycled"),
+                Attempt::Done("recycled"),
+                Attempt::Done("recycled"),
+                Attempt::Refused,
+            ]
+        );
+        assert_eq!(op.calls, vec![vec![0, 1, 2, 3], vec![2], vec![3]]);
+    }
+
+    #[test]
+    fn does_not_retry_a_single_item_or_other_aborts() {
+        let mut op = MockOperation {
+            unrecyclable: HashSet::from([0]),
+            ..Default::default()
+        };
+        let attempts = recycle_safely(&mut op, 1).unwrap();
+        assert_eq!(attempts, vec![Attempt::Refused]);
+        assert_eq!(op.calls.len(), 1);
+
+        let mut op = Canceled(0);
+        let attempts = recycle_safely(&mut op, 2).unwrap();
+        assert_eq!(attempts, vec![Attempt::NotReached; 2]);
+        assert_eq!(op.0, 1);
+    }
+
+    #[test]
+    fn stops_on_error() {
+        let mut op = MockOperation {
+            unrecyclable: HashSet::from([0]),
+            fail_on_call: Some(2),
+            ..Default::default()
+        };
+        assert_eq!(recycle_safely(&mut op, 3), Err("catastrophic failure"));
+        assert_eq!(op.calls, vec![vec![0, 1, 2], vec![0]]);
+    }
+}
@@ -0,0 +1,210 @@
+// Copyright (c) Max Kagamine
+// Licensed under the Apache License, Version 2.0
+
+// With dangerously_in_background (--rm), anything too big for the Recycle Bin gets deleted
+// permanently without a word (and `dwflags` still claims it was recycled). The bin's actual size
+// limit is stored per volume in the registry of the Windows user, which we can't easily read from
+// Linux, so instead we estimate it: by default, Windows allows the bin ~5% of the volume.
+
+/// The estimated capacity of a drive's Recycle Bin, as set by `max_recycle_size` in the config.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SizeLimit {
+    Bytes(u64),
+    /// Percentage of the volume's total size.
+    Percent(f64),
+}
+
+impl Default for SizeLimit {
+    fn default() -> Self {
+        Self::Percent(5.0)
+    }
+}
+
+impl SizeLimit {
+    /// Parses a size like `5%`, `500M`, `10G`, or `1073741824`. Suffixes are binary (K = 1024), and
+    /// anything printed by `format_size` (e.g. `1.5 TiB`) is accepted too.
+    #[must_use]
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss,
+        clippy::cast_precision_loss
+    )]
+    pub fn parse(value: &str) -> Option<Self> {
+        let value = value.trim();
+
+        if let Some(percent) = value.strip_suffix('%') {
+            let percent: f64 = percent.trim().parse().ok()?;
+            return (0.0..=100.0)
+                .contains(&percent)
+                .then_some(Self::Percent(percent));
+        }
+
+        let (number, unit) = value.split_at(
+            value
+                .find(|c: char| !c.is_ascii_digit() && c != '.')
+                .unwrap_or(value.len()),
+        );
+        let multiplier: u64 = match unit.trim().to_ascii_lowercase().as_str() {
+            "" | "b" | "bytes" => 1,
+            "k" | "kb" | "kib" => 1 << 10,
+            "m" | "mb" | "mib" => 1 << 20,
+            "g" | "gb" | "gib" => 1 << 30,
+            "t" | "tb" | "tib" => 1 << 40,
+            _ => return None,
+        };
+
+        if let Ok(number) = number.parse::<u64>() {
+            return number.checked_mul(multiplier).map(Self::Bytes);
+        }
+
+        let bytes = number.parse::<f64>().ok()? * multiplier as f64;
+        (bytes < u64::MAX as f64).then_some(Self::Bytes(bytes as u64))
+    }
+
+    /// Resolves the limit in bytes for a volume of the given total size.
+    #[must_use]
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss,
+        clippy::cast_precision_loss
+    )]
+    pub fn bytes(self, volume_size: u64) -> u64 {
+        match self {
+            Self::Bytes(x) => x,
+            Self::Percent(x) => (volume_size as f64 * x / 100.0) as u64,
+        }
+    }
+}
+
+/// Formats a byte count for humans, e.g. `1.5 GiB`.
+#[must_use]
+#[allow(clippy::cast_precision_loss)]
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["bytes", "KiB", "MiB", "GiB", "TiB"];
+
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{bytes} bytes")
+    } else {
+        format!("{size:.1} {}", UNITS[unit])
+    }
+}
+
+#[cfg(unix)]
+pub use self::unix::*;
+
+#[cfg(unix)]
+mod unix {
+    use std::{ffi::CString, fs, io, mem::MaybeUninit, os::unix::ffi::OsStrExt, path::Path};
+
+    /// Returns the total size of the filesystem containing `path`.
+    ///
+    /// # Errors
+    ///
+    /// statvfs failed.
+    pub fn volume_size(path: &Path) -> io::Result<u64> {
+        let path = CString::new(path.as_os_str().as_bytes())?;
+        let mut stat = MaybeUninit::<libc::statvfs>::uninit();
+
+        // SAFETY: `path` is a valid C string and `stat` is only read if statvfs succeeds
+        let stat = unsafe {
+            if libc::statvfs(path.as_ptr(), stat.as_mut_ptr()) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            stat.assume_init()
+        };
+
+        Ok(stat.f_blocks * stat.f_frsize)
+    }
+
+    /// Adds up the size of the file or directory tree at `path` without following symlinks,
+    /// stopping early once it exceeds `stop_after` (walking a large tree through drvfs is slow).
+    /// Entries that can't be read are ignored.
+    #[must_use]
+    pub fn tree_size(path: &Path, stop_after: u64) -> u64 {
+        let Ok(stat) = fs::symlink_metadata(path) else {
+            return 0;
+        };
+
+        if !stat.is_dir() {
+            return stat.len();
+        }
+
+        let mut total = 0;
+        let mut stack = vec![path.to_path_buf()];
+
+        while let Some(dir) = stack.pop() {
+            let Ok(entries) = fs::read_dir(&dir) else {
+                continue;
+            };
+
+            for entry in entries.flatten() {
+                let Ok(stat) = entry.metadata() else {
+                    continue;
+                };
+
+                if stat.is_dir() {
+                    stack.push(entry.path());
+                } else {
+                    total += stat.len();
+                    if total > stop_after {
+                        return total;
+                    }
+                }
+            }
+        }
+
+        total
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_sizes() {
+        assert_eq!(SizeLimit::parse("5%"), Some(SizeLimit::Percent(5.0)));
+        assert_eq!(SizeLimit::parse(" 12.5 % "), Some(SizeLimit::Percent(12.5)));
+        assert_eq!(SizeLimit::parse("1234"), Some(SizeLimit::Bytes(1234)));
+        assert_eq!(SizeLimit::parse("500M"), Some(SizeLimit::Bytes(500 << 20)));
+        assert_eq!(SizeLimit::parse("10gb"), Some(SizeLimit::Bytes(10 << 30)));
+        assert_eq!(SizeLimit::parse("2 TiB"), Some(SizeLimit::Bytes(2 << 40)));
+        assert_eq!(SizeLimit::parse("3t"), Some(SizeLimit::Bytes(3 << 40)));
+        assert_eq!(SizeLimit::parse("1.5 KiB"), Some(SizeLimit::Bytes(1536)));
+        assert_eq!(SizeLimit::parse("512 bytes"), Some(SizeLimit::Bytes(512)));
+        assert_eq!(SizeLimit::parse("5 PiB"), None);
+        assert_eq!(SizeLimit::parse("-5G"), None);
+        assert_eq!(SizeLimit::parse("150%"), None);
+        assert_eq!(SizeLimit::parse(""), None);
+    }
+
+    #[test]
+    fn resolves_percentages() {
+        assert_eq!(SizeLimit::Percent(5.0).bytes(1000), 50);
+        assert_eq!(SizeLimit::Bytes(42).bytes(1000), 42);
+    }
+
+    #[test]
+    fn formats_sizes() {
+        assert_eq!(format_size(512), "512 bytes");
+        assert_eq!(format_size(1536), "1.5 KiB");
+        assert_eq!(format_size(10 << 30), "10.0 GiB");
+    }
+
+    #[test]
+    fn parses_formatted_sizes() {
+        for bytes in [512, 1536, 10 << 30, 3 << 40] {
+            assert_eq!(
+                SizeLimit::parse(&format_size(bytes)),
+                Some(SizeLimit::Bytes(bytes))
+            );
+        }
+    }
+}
@@ -0,0 +1,880 @@
+// Copyright (c) Max Kagamine
+// Licensed under the Apache License, Version 2.0
+
+#![cfg(unix)]
+
+use crate::{recycle_result::ErrorCode, worker_pool};
+use std::{
+    error::Error,
+    ffi::{CStr, CString, OsStr},
+    fmt::Display,
+    fs::{self, OpenOptions},
+    io::{self, ErrorKind},
+    mem::{self, MaybeUninit},
+    os::{
+        fd::{AsRawFd, FromRawFd, OwnedFd},
+        unix::{ffi::OsStrExt, fs::OpenOptionsExt},
+    },
+    path::{Path, PathBuf},
+    sync::{
+        Arc, Condvar, Mutex, MutexGuard, PoisonError,
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc,
+    },
+    thread,
+};
+
+pub type RemoveOptions = u8;
+
+/// Remove files only; directories produce `RemoveError::IsDirectory`.
+pub const REMOVE_NORMAL: RemoveOptions = 0x0;
+
+/// Allow removing directories and their contents.
+pub const REMOVE_RECURSIVE: RemoveOptions = 0x1;
+
+/// Skip files/directories that don't exist instead of returning `RemoveError::NotFound`.
+pub const REMOVE_IGNORE_NOT_FOUND: RemoveOptions = 0x2;
+
+/// Skip directories on a different file system than the item being removed, like `rm
+/// --one-file-system`.
+pub const REMOVE_ONE_FILE_SYSTEM: RemoveOptions = 0x4;
+
+#[derive(Debug)]
+pub enum RemoveError {
+    NotFound(String),
+    IsDirectory(String),
+    /// The path ends in `.` or `..`.
+    InvalidPath(String),
+    /// Some entries could not be removed; they were passed to the callback.
+    Incomplete,
+
+    // Per-entry errors only applicable in the callback.
+    OtherFileSystem,
+    /// A directory was swapped out (e.g. for a symlink) between being checked and opened.
+    Replaced,
+    Io(io::Error),
+}
+
+impl From<io::Error> for RemoveError {
+    fn from(value: io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+
+impl From<&RemoveError> for ErrorCode {
+    fn from(value: &RemoveError) -> Self {
+        match value {
+            RemoveError::NotFound(_) => Self::NotFound,
+            RemoveError::IsDirectory(_) => Self::IsDirectory,
+            RemoveError::InvalidPath(_) => Self::InvalidPath,
+            RemoveError::Incomplete | RemoveError::Replaced => Self::Unknown,
+            RemoveError::OtherFileSystem => Self::OtherFileSystem,
+            RemoveError::Io(err) if err.kind() == ErrorKind::PermissionDenied => Self::AccessDenied,
+            RemoveError::Io(_) => Self::Io,
+        }
+    }
+}
+
+impl Display for RemoveError {
+    #[rustfmt::skip]
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NotFound(path) => write!(f, "Failed to delete \"{path}\": No such file or directory."),
+            Self::IsDirectory(path) => write!(f, "Cannot remove \"{path}\": Is a directory."),
+            Self::InvalidPath(path) => write!(f, "Refusing to remove \".\" or \"..\" directory: \"{path}\"."),
+            Self::Incomplete => write!(f, "Some files could not be deleted."),
+            Self::OtherFileSystem => write!(f, "It's on a different file system."),
+            Self::Replaced => write!(f, "The directory was replaced while being deleted."),
+            Self::Io(err) => Display::fmt(err, f),
+        }
+    }
+}
+
+impl Error for RemoveError {}
+
+/// Permanently deletes the given files/directories, like `rm`. Paths may be relative to the
+/// current directory.
+///
+/// Unlike `fs::remove_dir_all` after a `fs::symlink_metadata` check, every step is performed
+/// relative to an open directory fd (`openat`/`fstatat`/`unlinkat`), so there's no window in which
+/// swapping a directory for a symlink could redirect the deletion elsewhere. Symlinks are never
+/// followed, including the paths themselves (the link is removed, not its target); only the
+/// dirname of each path is resolved normally.
+///
+/// All paths are checked before anything is removed: if any do not exist (and
+/// `REMOVE_IGNORE_NOT_FOUND` is not set) or are directories (and `REMOVE_RECURSIVE` is not set),
+/// an error is returned without deleting anything.
+///
+/// The callback is called with each path once it's been removed, or with the path of any entry
+/// that couldn't be removed (which may be somewhere inside one of the directories) and the reason.
+///
+/// # Errors
+///
+/// `NotFound`, `IsDirectory`, or `InvalidPath` if a path failed the checks; nothing was removed.
+///
+/// `Incomplete` if any entries couldn't be removed.
+pub fn remove_with_callback<TIter, TItem, TCallback>(
+    paths: TIter,
+    options: RemoveOptions,
+    mut callback: TCallback,
+) -> Result<(), RemoveError>
+where
+    TIter: IntoIterator<Item = TItem>,
+    TItem: AsRef<str>,
+    TCallback: FnMut(String, Option<RemoveError>),
+{
+    let items = check_paths(paths, options)?;
+    remove_sequentially(items, options, &mut callback)
+}
+
+/// Like `remove_with_callback`, but removes the items, and the directories inside them, on up to
+/// `workers` threads. The callback is still only called on the current thread: each item is
+/// reported once it's done, in input order, preceded by any errors for entries inside it.
+///
+/// If any of the paths are inside one another, they're removed one at a time instead, since the
+/// threads would otherwise race to delete the same entries.
+///
+/// # Errors
+///
+/// Same as `remove_with_callback`.
+pub fn remove_parallel<TIter, TItem, TCallback>(
+    paths: TIter,
+    options: RemoveOptions,
+    workers: usize,
+    mut callback: TCallback,
+) -> Result<(), RemoveError>
+where
+    TIter: IntoIterator<Item = TItem>,
+    TItem: AsRef<str>,
+    TCallback: FnMut(String, Option<RemoveError>),
+{
+    let items = check_paths(paths, options)?;
+    if workers <= 1 || any_nested(&items) {
+        return remove_sequentially(items, options, &mut callback);
+    }
+
+    let (done, finished) = mpsc::channel();
+    let shared = Shared {
+        items: &items,
+        one_file_system: options & REMOVE_ONE_FILE_SYSTEM != 0,
+        queue: Mutex::new(
+            items
+                .iter()
+                .enumerate()
+                .rev() // Popped from the end
+                .map(|(i, item)| Task {
+                    parent: Parent::Item(i),
+                    name: item.name.clone(),
+                    stat: item.stat,
+                    path: PathBuf::from(&item.path),
+                    depth: 0,
+                })
+                .collect(),
+        ),
+        wake: Condvar::new(),
+        remaining: AtomicUsize::new(items.len()),
+        errors: items.iter().map(|_| Mutex::default()).collect(),
+    };
+
+    let mut complete = true;
+    let mut reorder = worker_pool::Reorder::new(items.len());
+
+    thread::scope(|scope| {
+        for _ in 0..workers.min(worker_pool::MAX_WORKERS) {
+            let done = done.clone();
+            let shared = &shared;
+            scope.spawn(move || shared.work(&done));
+        }
+        drop(done);
+
+        for (i, errors, result) in finished {
+            reorder.push(i, (errors, result), |i, (errors, result)| {
+                for (path, err) in errors {
+                    callback(path, Some(err));
+                }
+                complete &= report_item(items[i].path.clone(), result, options, &mut callback);
+            });
+        }
+    });
+
+    if complete {
+        Ok(())
+    } else {
+        Err(RemoveError::Incomplete)
+    }
+}
+
+/// A path that passed the checks in `check_paths`.
+struct Item {
+    path: String,
+    parent_fd: OwnedFd,
+    name: CString,
+    stat: libc::stat,
+}
+
+/// Opens the parent of each path and checks that it can be removed, before anything is.
+fn check_paths<TIter, TItem>(paths: TIter, options: RemoveOptions) -> Result<Vec<Item>, RemoveError>
+where
+    TIter: IntoIterator<Item = TItem>,
+    TItem: AsRef<str>,
+{
+    let mut items = Vec::new();
+
+    for path in paths {
+        let path = path.as_ref();
+
+        // Path::file_name() ignores a trailing `.`, so check for that ourselves
+        let trimmed = path.trim_end_matches('/');
+        let name = Path::new(trimmed).file_name();
+        if trimmed.ends_with("/.") || trimmed == "." || name.is_none_or(|x| x == "..") {
+            return Err(RemoveError::InvalidPath(path.to_owned()));
+        }
+
+        let parent = Path::new(trimmed)
+            .parent()
+            .filter(|x| !x.as_os_str().is_empty())
+            .unwrap_or(Path::new("."));
+        let name = cstring(name.unwrap_or_default());
+
+        // O_PATH, since we only need to be able to search the parent, not read it
+        let parent_fd = match OpenOptions::new()
+            .read(true)
+            .custom_flags(libc::O_PATH | libc::O_DIRECTORY)
+            .open(parent)
+        {
+            Ok(file) => OwnedFd::from(file),
+            Err(err) if err.kind() == ErrorKind::NotFound => {
+                if options & REMOVE_IGNORE_NOT_FOUND != 0 {
+                    continue;
+                }
+                return Err(RemoveError::NotFound(path.to_owned()));
+            }
+            Err(err) => return Err(err.into()),
+        };
+
+        let stat = match stat_at(&parent_fd, &name) {
+            Ok(stat) => stat,
+            Err(err) if err.kind() == ErrorKind::NotFound => {
+                if options & REMOVE_IGNORE_NOT_FOUND != 0 {
+                    continue;
+                }
+                return Err(RemoveError::NotFound(path.to_owned()));
+            }
+            Err(err) => return Err(err.into()),
+        };
+
+        if is_dir(&stat) && options & REMOVE_RECURSIVE == 0 {
+            return Err(RemoveError::IsDirectory(path.to_owned()));
+        }
+
+        items.push(Item {
+            path: path.to_owned(),
+            parent_fd,
+            name,
+            stat,
+        });
+    }
+
+    Ok(items)
+}
+
+fn remove_sequentially<TCallback: FnMut(String, Option<RemoveError>)>(
+    items: Vec<Item>,
+    options: RemoveOptions,
+    callback: &mut TCallback,
+) -> Result<(), RemoveError> {
+    let mut complete = true;
+
+    for item in items {
+        let mut remover = Remover {
+            one_file_system: options & REMOVE_ONE_FILE_SYSTEM != 0,
+            dev: item.stat.st_dev,
+            callback,
+        };
+
+        let result = remover.remove(
+            &item.parent_fd,
+            &item.name,
+            &item.stat,
+            Path::new(&item.path),
+        );
+        complete &= report_item(item.path, result, options, callback);
+    }
+
+    if complete {
+        Ok(())
+    } else {
+        Err(RemoveError::Incomplete)
+    }
+}
+
+/// Passes an item's result to the callback. Returns false if it wasn't removed.
+fn report_item<TCallback: FnMut(String, Option<RemoveError>)>(
+    path: String,
+    result: Result<(), Option<io::Error>>,
+    options: RemoveOptions,
+    callback: &mut TCallback,
+) -> bool {
+    match result {
+        Ok(()) => {
+            callback(path, None);
+            true
+        }
+        Err(Some(err)) if err.kind() == ErrorKind::NotFound => {
+            if options & REMOVE_IGNORE_NOT_FOUND != 0 {
+                return true;
+            }
+            callback(path.clone(), Some(RemoveError::NotFound(path)));
+            false
+        }
+        Err(Some(err)) => {
+            callback(path, Some(err.into()));
+            false
+        }
+        Err(None) => false,
+    }
+}
+
+/// Whether any of the items are inside (or the same as) another, going by the real paths of their
+/// parent directories.
+fn any_nested(items: &[Item]) -> bool {
+    let mut paths = Vec::with_capacity(items.len());
+
+    for item in items {
+        let Ok(parent) = fs::read_link(format!("/proc/self/fd/{}", item.parent_fd.as_raw_fd()))
+        else {
+            return true; // Better safe than sorry
+        };
+        paths.push(parent.join(OsStr::from_bytes(item.name.as_bytes())));
+    }
+
+    worker_pool::chain_nested(&paths).len() < paths.len()
+}
+
+struct Remover<'a, TCallback: FnMut(String, Option<RemoveError>)> {
+    one_file_system: bool,
+    /// Device of the item being removed, for `REMOVE_ONE_FILE_SYSTEM`.
+    dev: libc::dev_t,
+    callback: &'a mut TCallback,
+}
+
+/// A directory being emptied by `Remover`.
+struct Frame {
+    name: CString,
+    stat: libc::stat,
+    path: PathBuf,
+    children: std::vec::IntoIter<CString>,
+    /// Cleared if anything inside it couldn't be removed.
+    emptied: bool,
+}
+
+impl<TCallback: FnMut(String, Option<RemoveError>)> Remover<'_, TCallback> {
+    /// Removes `name` in `parent_fd`, which `stat` was obtained for. `path` is only used for
+    /// reporting errors. Returns `Err(None)` if it couldn't be removed because of an error inside
+    /// it which was already passed to the callback.
+    ///
+    /// Rather than recursing, which would need an fd for every level, only the directory being
+    /// emptied is kept open. Once it's done, its parent is reopened through `..` and checked to be
+    /// the same directory as before, so the depth of the tree isn't limited by `ulimit -n`.
+    fn remove(
+        &mut self,
+        parent_fd: &OwnedFd,
+        name: &CStr,
+        stat: &libc::stat,
+        path: &Path,
+    ) -> Result<(), Option<io::Error>> {
+        if !is_dir(stat) {
+            return Ok(unlink_at(parent_fd, name, 0)?);
+        }
+
+        let (mut fd, children) = self.open(parent_fd, name, stat, path)?;
+        let mut stack = vec![Frame {
+            name: name.to_owned(),
+            stat: *stat,
+            path: path.to_owned(),
+            children: children.into_iter(),
+            emptied: true,
+        }];
+
+        while let Some(frame) = stack.last_mut() {
+            if let Some(child_name) = frame.children.next() {
+                let child_path = frame.path.join(OsStr::from_bytes(child_name.as_bytes()));
+
+                let child_stat = match stat_at(&fd, &child_name) {
+                    Ok(x) => x,
+                    Err(err) if err.kind() == ErrorKind::NotFound => continue,
+                    Err(err) => {
+                        self.fail(&child_path, err.into());
+                        frame.emptied = false;
+                        continue;
+                    }
+                };
+
+                if !is_dir(&child_stat) {
+                    match unlink_at(&fd, &child_name, 0) {
+                        Ok(()) => {}
+                        Err(err) if err.kind() == ErrorKind::NotFound => {}
+                        Err(err) => {
+                            self.fail(&child_path, err.into());
+                            frame.emptied = false;
+                        }
+                    }
+                    continue;
+                }
+
+                if self.one_file_system && child_stat.st_dev != self.dev {
+                    self.fail(&child_path, RemoveError::OtherFileSystem);
+                    frame.emptied = false;
+                    continue;
+                }
+
+                match self.open(&fd, &child_name, &child_stat, &child_path) {
+                    Ok((child_fd, children)) => {
+                        fd = child_fd;
+                        stack.push(Frame {
+                            name: child_name,
+                            stat: child_stat,
+                            path: child_path,
+                            children: children.into_iter(),
+                            emptied: true,
+                        });
+                    }
+                    Err(Some(err)) if err.kind() == ErrorKind::NotFound => {}
+                    Err(Some(err)) => {
+                        self.fail(&child_path, err.into());
+                        frame.emptied = false;
+                    }
+                    Err(None) => frame.emptied = false,
+                }
+
+                continue;
+            }
+
+            // Everything in the directory has been dealt with; go back up and remove it
+            let Some(done) = stack.pop() else { break };
+            let Some(parent) = stack.last_mut() else {
+                drop(fd);
+
+                // No point trying to rmdir it (and reporting "directory not empty") if something
+                // failed
+                if !done.emptied {
+                    return Err(None);
+                }
+                return Ok(unlink_at(parent_fd, name, libc::AT_REMOVEDIR)?);
+            };
+
+            let parent_fd = match open_dir_at(&fd, c"..").and_then(|x| Ok((fstat(&x)?, x))) {
+                Ok((opened, x))
+                    if opened.st_dev == parent.stat.st_dev
+                        && opened.st_ino == parent.stat.st_ino =>
+                {
+                    x
+                }
+                // Moved elsewhere while being deleted, so we've lost our place
+                Ok(_) => {
+                    self.fail(&done.path, RemoveError::Replaced);
+                    return Err(None);
+                }
+                Err(err) => {
+                    self.fail(&done.path, err.into());
+                    return Err(None);
+                }
+            };
+            fd = parent_fd;
+
+            if !done.emptied {
+                parent.emptied = false;
+                continue;
+            }
+
+            match unlink_at(&fd, &done.name, libc::AT_REMOVEDIR) {
+                Ok(()) => {}
+                Err(err) if err.kind() == ErrorKind::NotFound => {}
+                Err(err) => {
+                    self.fail(&done.path, err.into());
+                    parent.emptied = false;
+                }
+            }
+        }
+
+        unreachable!("returns once the item's frame is popped")
+    }
+
+    /// Opens the directory and lists its entries, checking that it's the one `stat` was obtained
+    /// for: `O_NOFOLLOW` guarantees we didn't open a symlink, but it could still be a different
+    /// directory than the one we checked.
+    fn open(
+        &mut self,
+        parent_fd: &OwnedFd,
+        name: &CStr,
+        stat: &libc::stat,
+        path: &Path,
+    ) -> Result<(OwnedFd, Vec<CString>), Option<io::Error>> {
+        let dir_fd = open_dir_at(parent_fd, name)?;
+
+        let opened = fstat(&dir_fd)?;
+        if opened.st_dev != stat.st_dev || opened.st_ino != stat.st_ino {
+            self.fail(path, RemoveError::Replaced);
+            return Err(None);
+        }
+
+        let names = read_names(&dir_fd)?;
+        Ok((dir_fd, names))
+    }
+
+    fn fail(&mut self, path: &Path, err: RemoveError) {
+        (self.callback)(path.to_string_lossy().into_owned(), Some(err));
+    }
+}
+
+/// What a directory is removed from once it's empty.
+#[derive(Clone)]
+enum Parent {
+    /// The item's parent, for the item itself.
+    Item(usize),
+    Dir(Arc<Dir>),
+}
+
+impl Parent {
+    fn item(&self) -> usize {
+        match self {
+            Self::Item(i) => *i,
+            Self::Dir(dir) => dir.item,
+        }
+    }
+}
+
+/// A directory whose subdirectories are being removed by the workers. Whichever finishes last
+/// removes the directory itself.
+struct Dir {
+    fd: OwnedFd,
+    parent: Parent,
+    name: CString,
+    path: PathBuf,
+    item: usize,
+    /// Subdirectories not yet finished.
+    pending: AtomicUsize,
+    /// Cleared if anything inside it couldn't be removed.
+    emptied: AtomicBool,
+}
+
+struct Task {
+    parent: Parent,
+    name: CString,
+    stat: libc::stat,
+    /// Only used for reporting errors.
+    path: PathBuf,
+    /// Levels below the item.
+    depth: usize,
+}
+
+/// Directories this far below an item are removed by a single worker using `Remover`, rather than
+/// being split up further. Every `Dir` keeps its fd open until its subdirectories are done, so
+/// this bounds how many each chain of them can hold open.
+const MAX_PARALLEL_DEPTH: usize = 16;
+
+type Finished = (
+    usize,
+    Vec<(String, RemoveError)>,
+    Result<(), Option<io::Error>>,
+);
+
+/// State shared between the `remove_parallel` workers.
+struct Shared<'a> {
+    items: &'a [Item],
+    one_file_system: bool,
+    /// Used as a stack, so that the workers go depth-first and only have a few directories open.
+    queue: Mutex<Vec<Task>>,
+    wake: Condvar,
+    /// Items not yet finished; the workers exit once it reaches zero.
+    remaining: AtomicUsize,
+    /// Errors for entries inside each item, reported along with it.
+    errors: Vec<Mutex<Vec<(String, RemoveError)>>>,
+}
+
+impl Shared<'_> {
+    fn work(&self, done: &mpsc::Sender<Finished>) {
+        while let Some(task) = self.next_task() {
+            self.run(task, done);
+        }
+    }
+
+    fn next_task(&self) -> Option<Task> {
+        let mut queue = lock(&self.queue);
+        loop {
+            if let Some(task) = queue.pop() {
+                return Some(task);
+            }
+            if self.remaining.load(Ordering::Acquire) == 0 {
+                return None;
+            }
+            queue = self
+                .wake
+                .wait(queue)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+    }
+
+    fn run(&self, task: Task, done: &mpsc::Sender<Finished>) {
+        let result = if is_dir(&task.stat) && task.depth >= MAX_PARALLEL_DEPTH {
+            let item = task.parent.item();
+            let mut remover = Remover {
+                one_file_system: self.one_file_system,
+                dev: self.items[item].stat.st_dev,
+                callback: &mut |path, err: Option<RemoveError>| {
+                    if let Some(err) = err {
+                        lock(&self.errors[item]).push((path, err));
+                    }
+                },
+            };
+            remover.remove(self.fd(&task.parent), &task.name, &task.stat, &task.path)
+        } else if is_dir(&task.stat) {
+            match self.empty_dir(&task) {
+                Ok((fd, subdirs, emptied)) if !subdirs.is_empty() => {
+                    let dir = Arc::new(Dir {
+                        fd,
+                        item: task.parent.item(),
+                        parent: task.parent,
+                        name: task.name,
+                        path: task.path,
+                        pending: AtomicUsize::new(subdirs.len()),
+                        emptied: AtomicBool::new(emptied),
+                    });
+
+                    lock(&self.queue).extend(subdirs.into_iter().map(|(name, stat)| Task {
+                        path: dir.path.join(OsStr::from_bytes(name.as_bytes())),
+                        parent: Parent::Dir(dir.clone()),
+                        name,
+                        stat,
+                        depth: task.depth + 1,
+                    }));
+                    self.wake.notify_all();
+                    return;
+                }
+                Ok((_, _, true)) => {
+                    unlink_at(self.fd(&task.parent), &task.name, libc::AT_REMOVEDIR).map_err(Some)
+                }
+                Ok((_, _, false)) => Err(None),
+                Err(err) => Err(err),
+            }
+        } else {
+            unlink_at(self.fd(&task.parent), &task.name, 0).map_err(Some)
+        };
+
+        self.finish(task.parent, task.path, result, done);
+    }
+
+    /// Opens the directory and removes everything in it other than subdirectories, which are
+    /// returned to be queued. The bool is false if anything couldn't be removed. See
+    /// `Remover::open`.
+    #[allow(clippy::type_complexity)]
+    fn empty_dir(
+        &self,
+        task: &Task,
+    ) -> Result<(OwnedFd, Vec<(CString, libc::stat)>, bool), Option<io::Error>> {
+        let item = task.parent.item();
+        let dir_fd = open_dir_at(self.fd(&task.parent), &task.name)?;
+
+        let opened = fstat(&dir_fd)?;
+        if opened.st_dev != task.stat.st_dev || opened.st_ino != task.stat.st_ino {
+            self.fail(item, &task.path, RemoveError::Replaced);
+            return Err(None);
+        }
+
+        let mut emptied = true;
+        let mut subdirs = Vec::new();
+
+        for child_name in read_names(&dir_fd)? {
+            let child_path = task.path.join(OsStr::from_bytes(child_name.as_bytes()));
+
+            let result = match stat_at(&dir_fd, &child_name) {
+                Ok(stat) if !is_dir(&stat) => unlink_at(&dir_fd, &child_name, 0),
+                Ok(stat) => {
+                    if self.one_file_system && stat.st_dev != self.items[item].stat.st_dev {
+                        self.fail(item, &child_path, RemoveError::OtherFileSystem);
+                        emptied = false;
+                    } else {
+                        subdirs.push((child_name, stat));
+                    }
+                    continue;
+                }
+                Err(err) => Err(err),
+            };
+
+            match result {
+                Ok(()) => {}
+                Err(err) if err.kind() == ErrorKind::NotFound => {}
+                Err(err) => {
+                    self.fail(item, &child_path, err.into());
+                    emptied = false;
+                }
+            }
+        }
+
+        Ok((dir_fd, subdirs, emptied))
+    }
+
+    /// Passes a task's result up to its parent directory, removing the directory if this was the
+    /// last thing in it, and so on up to the item.
+    fn finish(
+        &self,
+        mut parent: Parent,
+        mut path: PathBuf,
+        mut result: Result<(), Option<io::Error>>,
+        done: &mpsc::Sender<Finished>,
+    ) {
+        loop {
+            let dir = match parent {
+                Parent::Item(i) => {
+                    let errors = mem::take(&mut *lock(&self.errors[i]));
+                    let _ = done.send((i, errors, result));
+
+                    if self.remaining.fetch_sub(1, Ordering::AcqRel) == 1 {
+                        // Taking the lock so that no worker is between checking and waiting
+                        drop(lock(&self.queue));
+                        self.wake.notify_all();
+                    }
+                    return;
+                }
+                Parent::Dir(dir) => dir,
+            };
+
+            match result {
+                Ok(()) => {}
+                Err(Some(err)) if err.kind() == ErrorKind::NotFound => {}
+                Err(Some(err)) => {
+                    self.fail(dir.item, &path, err.into());
+                    dir.emptied.store(false, Ordering::Relaxed);
+                }
+                Err(None) => dir.emptied.store(false, Ordering::Relaxed),
+            }
+
+            if dir.pending.fetch_sub(1, Ordering::AcqRel) != 1 {
+                return;
+            }
+
+            result = if dir.emptied.load(Ordering::Relaxed) {
+                unlink_at(self.fd(&dir.parent), &dir.name, libc::AT_REMOVEDIR).map_err(Some)
+            } else {
+                Err(None)
+            };
+            path.clone_from(&dir.path);
+            parent = dir.parent.clone();
+        }
+    }
+
+    fn fd<'b>(&'b self, parent: &'b Parent) -> &'b OwnedFd {
+        match parent {
+            Parent::Item(i) => &self.items[*i].parent_fd,
+            Parent::Dir(dir) => &dir.fd,
+        }
+    }
+
+    fn fail(&self, item: usize, path: &Path, err: RemoveError) {
+        lock(&self.errors[item]).push((path.to_string_lossy().into_owned(), err));
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn cstring(value: &OsStr) -> CString {
+    // File names can't contain nul bytes, so this would have failed to stat anyway
+    CString::new(value.as_bytes()).unwrap_or_default()
+}
+
+fn is_dir(stat: &libc::stat) -> bool {
+    stat.st_mode & libc::S_IFMT == libc::S_IFDIR
+}
+
+fn check(result: libc::c_int) -> io::Result<libc::c_int> {
+    if result < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(result)
+    }
+}
+
+fn stat_at(dir: &OwnedFd, name: &CStr) -> io::Result<libc::stat> {
+    let mut stat = MaybeUninit::<libc::stat>::uninit();
+
+    // SAFETY: `name` is a valid C string, and `stat` is only read if fstatat succeeds
+    unsafe {
+        check(libc::fstatat(
+            dir.as_raw_fd(),
+            name.as_ptr(),
+            stat.as_mut_ptr(),
+            libc::AT_SYMLINK_NOFOLLOW,
+        ))?;
+        Ok(stat.assume_init())
+    }
+}
+
+fn fstat(fd: &OwnedFd) -> io::Result<libc::stat> {
+    let mut stat = MaybeUninit::<libc::stat>::uninit();
+
+    // SAFETY: `stat` is only read if fstat succeeds
+    unsafe {
+        check(libc::fstat(fd.as_raw_fd(), stat.as_mut_ptr()))?;
+        Ok(stat.assume_init())
+    }
+}
+
+fn open_dir_at(dir: &OwnedFd, name: &CStr) -> io::Result<OwnedFd> {
+    // SAFETY: `name` is a valid C string, and the returned fd is owned by us
+    unsafe {
+        let fd = check(libc::openat(
+            dir.as_raw_fd(),
+            name.as_ptr(),
+            libc::O_RDONLY | libc::O_DIRECTORY | libc::O_NOFOLLOW | libc::O_CLOEXEC,
+        ))?;
+        Ok(OwnedFd::from_raw_fd(fd))
+    }
+}
+
+fn unlink_at(dir: &OwnedFd, name: &CStr, flags: libc::c_int) -> io::Result<()> {
+    // SAFETY: `name` is a valid C string
+    unsafe {
+        check(libc::unlinkat(dir.as_raw_fd(), name.as_ptr(), flags))?;
+    }
+    Ok(())
+}
+
+/// Lists the directory's entries (excluding `.` and `..`). The names are collected up front, as
+/// the directory shouldn't be modified while reading it.
+fn read_names(dir: &OwnedFd) -> io::Result<Vec<CString>> {
+    let mut names = Vec::new();
+
+    // SAFETY: fdopendir takes ownership of the dup'd fd, which closedir closes. Each dirent is
+    // only accessed before the next call to readdir.
+    unsafe {
+        let fd = check(libc::fcntl(dir.as_raw_fd(), libc::F_DUPFD_CLOEXEC, 0))?;
+        let stream = libc::fdopendir(fd);
+        if stream.is_null() {
+            let err = io::Error::last_os_error();
+            libc::close(fd);
+            return Err(err);
+        }
+
+        loop {
+            *libc::__errno_location() = 0;
+            let entry = libc::readdir(stream);
+            if entry.is_null() {
+                let errno = *libc::__errno_location();
+                libc::closedir(stream);
+                if errno != 0 {
+                    return Err(io::Error::from_raw_os_error(errno));
+                }
+                break;
+            }
+
+            let name = CStr::from_ptr((*entry).d_name.as_ptr());
+            if name != c"." && name != c".." {
+                names.push(name.to_owned());
+            }
+        }
+    }
+
+    Ok(names)
+}
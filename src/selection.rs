@@ -0,0 +1,436 @@
+// Copyright (c) Max Kagamine
+// Licensed under the Apache License, Version 2.0
+
+#![cfg(unix)]
+
+// PRIMARY and SECONDARY selections have no equivalent on Windows, so by default xsel treats them
+// as the clipboard. With `separate_selections = yes` in wsl-tools.ini, it keeps them in files under
+// `$XDG_RUNTIME_DIR` instead, which like X selections don't outlive the session. Only text is
+// supported, and nothing else (Vim, terminals) will see them unless it goes through xsel.
+
+use crate::follow_buffer::FollowBuffer;
+use std::{
+    env,
+    fs::{self, DirBuilder},
+    io::{self, ErrorKind, Read, Write},
+    os::unix::fs::{DirBuilderExt, MetadataExt, OpenOptionsExt},
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Selection {
+    Primary,
+    Secondary,
+}
+
+impl Selection {
+    fn filename(self) -> &'static str {
+        match self {
+            Self::Primary => "primary",
+            Self::Secondary => "secondary",
+        }
+    }
+}
+
+/// What xsel was asked to do with a selection, already worked out from its options and whether
+/// stdin and stdout are terminals.
+#[derive(Debug, Clone, Copy, Default)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct SelectionOptions {
+    /// Write the selection to the output.
+    pub output: bool,
+    /// Replace the selection with the input, after outputting it.
+    pub input: bool,
+    /// Add the input to the end of the selection instead of replacing it.
+    pub append: bool,
+    /// Clear the selection instead of reading input.
+    pub clear: bool,
+    /// Update the selection as input arrives rather than once it ends.
+    pub follow: bool,
+    /// With `follow`, a NUL in the input starts the selection over.
+    pub zero_flush: bool,
+    /// Remove trailing newlines from the output and the new selection.
+    pub trim: bool,
+}
+
+/// Where the emulated selections are stored.
+pub struct SelectionStore {
+    dir: PathBuf,
+}
+
+impl SelectionStore {
+    /// Uses `$XDG_RUNTIME_DIR/wsl-tools`, or if that's not set, a per-user directory in /tmp. Since
+    /// the latter is predictable, the directory is only used if it belongs to the current user and
+    /// no one else can access it.
+    #[must_use]
+    pub fn new() -> Self {
+        let dir = env::var_os("XDG_RUNTIME_DIR")
+            .map(PathBuf::from)
+            .filter(|x| x.is_absolute())
+            .map_or_else(
+                || env::temp_dir().join(format!("wsl-tools-{}", unsafe { libc::getuid() })),
+                |x| x.join("wsl-tools"),
+            );
+
+        Self { dir }
+    }
+
+    /// Uses the given directory.
+    #[must_use]
+    pub fn in_dir(dir: &Path) -> Self {
+        Self {
+            dir: dir.to_path_buf(),
+        }
+    }
+
+    /// Gets the contents of `selection`.
+    ///
+    /// # Returns
+    /// `None` if the selection hasn't been set.
+    ///
+    /// # Errors
+    /// Failed to read the file.
+    pub fn get(&self, selection: Selection) -> io::Result<Option<Vec<u8>>> {
+        match self.check_dir() {
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+            result => result?,
+        }
+
+        match fs::read(self.dir.join(selection.filename())) {
+            Ok(data) => Ok(Some(data)),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Replaces the contents of `selection`. The file is only readable by the current user, and is
+    /// replaced atomically so that a concurrent `get` never sees it half-written.
+    ///
+    /// # Errors
+    /// Failed to create the directory or write the file, or the directory isn't safe to use.
+    pub fn set(&self, selection: Selection, data: &[u8]) -> io::Result<()> {
+        DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(&self.dir)?;
+        self.check_dir()?;
+
+        let path = self.dir.join(selection.filename());
+        let temp_path = path.with_extension(format!("{}.tmp", std::process::id()));
+
+        // Left behind if a previous process with the same pid was killed mid-write
+        match fs::remove_file(&temp_path) {
+            Err(err) if err.kind() != ErrorKind::NotFound => return Err(err),
+            _ => {}
+        }
+
+        fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .custom_flags(libc::O_NOFOLLOW)
+            .mode(0o600)
+            .open(&temp_path)?
+            .write_all(data)?;
+
+        fs::rename(temp_path, path)
+    }
+
+    /// Makes sure the directory is a real directory owned by the current user, with no access for
+    /// anyone else. If another user created it first (it may be in /tmp), they could read the
+    /// selections or swap the files for symlinks.
+    fn check_dir(&self) -> io::Result<()> {
+        let metadata = fs::symlink_metadata(&self.dir)?;
+        let uid = unsafe { libc::getuid() };
+
+        if !metadata.is_dir() || metadata.uid() != uid || metadata.mode() & 0o077 != 0 {
+            return Err(io::Error::new(
+                ErrorKind::PermissionDenied,
+                format!(
+                    "{} must be a directory owned by uid {uid} with permissions 0700",
+                    self.dir.display()
+                ),
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Clears `selection`.
+    ///
+    /// # Errors
+    /// Failed to delete the file.
+    pub fn clear(&self, selection: Selection) -> io::Result<()> {
+        match self.check_dir() {
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(()),
+            result => result?,
+        }
+
+        match fs::remove_file(self.dir.join(selection.filename())) {
+            Err(err) if err.kind() != ErrorKind::NotFound => Err(err),
+            _ => Ok(()),
+        }
+    }
+
+    /// Outputs, replaces, appends to, or clears `selection` as xsel would the clipboard.
+    ///
+    /// # Errors
+    /// Failed to read or write the selection, the input, or the output.
+    pub fn apply(
+        &self,
+        selection: Selection,
+        options: &SelectionOptions,
+        mut input: impl Read,
+        mut output: impl Write,
+    ) -> io::Result<()> {
+        let trim = |data: &[u8]| -> Vec<u8> {
+            let len = if options.trim {
+                data.iter()
+                    .rposition(|&x| x != b'\r' && x != b'\n')
+                    .map_or(0, |i| i + 1)
+            } else {
+                data.len()
+            };
+            data[..len].to_vec()
+        };
+
+        let old_sel = if options.output {
+            let old_sel = self.get(selection)?;
+            if let Some(data) = &old_sel {
+                output.write_all(&trim(data))?;
+                output.flush()?;
+            }
+            old_sel
+        } else {
+            None
+        };
+
+        if options.clear {
+            return self.clear(selection);
+        }
+        if !options.input {
+            return Ok(());
+        }
+
+        let mut new_sel = if !options.append {
+            Vec::new()
+        } else if options.output {
+            old_sel.unwrap_or_default()
+        } else {
+            self.get(selection)?.unwrap_or_default()
+        };
+
+        if !options.follow {
+            input.read_to_end(&mut new_sel)?;
+            return self.set(selection, &trim(&new_sel));
+        }
+
+        // Writing a file is cheap, so unlike the clipboard, there's no need to batch updates
+        let initial = String::from_utf8_lossy(&new_sel).into_owned();
+        let mut buffer = FollowBuffer::new(initial, options.zero_flush);
+        let mut buf = [0; 8192];
+        loop {
+            if let Some(text) = buffer.take_update() {
+                self.set(selection, &trim(text.as_bytes()))?;
+            }
+            match input.read(&mut buf) {
+                Ok(0) => return Ok(()),
+                Ok(len) => buffer.push(&buf[..len]),
+                Err(err) if err.kind() == ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
+
+impl Default for SelectionStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    fn store(name: &str) -> (SelectionStore, PathBuf) {
+        let dir = env::temp_dir().join(format!("wsl-tools-{name}"));
+        let _ = fs::remove_dir_all(&dir);
+        (SelectionStore::in_dir(&dir), dir)
+    }
+
+    #[test]
+    fn sets_gets_and_clears_selections() {
+        let (store, dir) = store("sets_gets_and_clears_selections");
+
+        assert_eq!(store.get(Selection::Primary).unwrap(), None);
+
+        store
+            .set(Selection::Primary, "鏡音リン".as_bytes())
+            .unwrap();
+        store.set(Selection::Secondary, b"\xff binary").unwrap();
+        assert_eq!(
+            store.get(Selection::Primary).unwrap().as_deref(),
+            Some("鏡音リン".as_bytes())
+        );
+        assert_eq!(
+            store.get(Selection::Secondary).unwrap().as_deref(),
+            Some(&b"\xff binary"[..])
+        );
+
+        let mode = |path: &Path| fs::metadata(path).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode(&dir), 0o700);
+        assert_eq!(mode(&dir.join("primary")), 0o600);
+
+        store.clear(Selection::Primary).unwrap();
+        store.clear(Selection::Primary).unwrap();
+        assert_eq!(store.get(Selection::Primary).unwrap(), None);
+        assert!(store.get(Selection::Secondary).unwrap().is_some());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn refuses_unsafe_directory() {
+        let (store, dir) = store("refuses_unsafe_directory");
+
+        DirBuilder::new().mode(0o777).create(&dir).unwrap();
+        fs::set_permissions(&dir, fs::Permissions::from_mode(0o777)).unwrap();
+        let planted = env::temp_dir().join("wsl-tools-refuses_unsafe_directory-target");
+        fs::write(&planted, b"not yours").unwrap();
+        std::os::unix::fs::symlink(
+            &planted,
+            dir.join(format!("primary.{}.tmp", std::process::id())),
+        )
+        .unwrap();
+
+        let err = store.set(Selection::Primary, b"password").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::PermissionDenied);
+        assert_eq!(
+            store.get(Selection::Primary).unwrap_err().kind(),
+            ErrorKind::PermissionDenied
+        );
+        assert_eq!(fs::read(&planted).unwrap(), b"not yours");
+
+        // A symlink to a safe directory isn't accepted either
+        fs::remove_dir_all(&dir).unwrap();
+        let real_dir = env::temp_dir().join("wsl-tools-refuses_unsafe_directory-real");
+        let _ = fs::remove_dir_all(&real_dir);
+        DirBuilder::new().mode(0o700).create(&real_dir).unwrap();
+        std::os::unix::fs::symlink(&real_dir, &dir).unwrap();
+        let err = store.set(Selection::Primary, b"password").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::PermissionDenied);
+
+        fs::remove_file(dir).unwrap();
+        fs::remove_dir_all(real_dir).unwrap();
+        fs::remove_file(planted).unwrap();
+    }
+
+    /// Runs `apply` with `input`, returning the output.
+    fn apply(store: &SelectionStore, options: SelectionOptions, input: &str) -> String {
+        let mut output = Vec::new();
+        store
+            .apply(Selection::Primary, &options, input.as_bytes(), &mut output)
+            .unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    fn primary(store: &SelectionStore) -> Option<String> {
+        store
+            .get(Selection::Primary)
+            .unwrap()
+            .map(|x| String::from_utf8(x).unwrap())
+    }
+
+    #[test]
+    fn outputs_and_replaces_selection() {
+        let (store, dir) = store("outputs_and_replaces_selection");
+        let output = SelectionOptions {
+            output: true,
+            ..Default::default()
+        };
+        let input = SelectionOptions {
+            input: true,
+            ..Default::default()
+        };
+
+        assert_eq!(apply(&store, output, "ignored"), "");
+        assert_eq!(primary(&store), None, "should only read input with -i");
+
+        assert_eq!(apply(&store, input, "鏡音リン\n\n"), "");
+        assert_eq!(apply(&store, output, ""), "鏡音リン\n\n");
+
+        let both = SelectionOptions {
+            output: true,
+            input: true,
+            trim: true,
+            ..Default::default()
+        };
+        assert_eq!(apply(&store, both, "鏡音レン\r\n"), "鏡音リン");
+        assert_eq!(primary(&store).as_deref(), Some("鏡音レン"));
+
+        let clear = SelectionOptions {
+            output: true,
+            input: true,
+            clear: true,
+            ..Default::default()
+        };
+        assert_eq!(apply(&store, clear, "ignored"), "鏡音レン");
+        assert_eq!(primary(&store), None);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn appends_to_selection() {
+        let (store, dir) = store("appends_to_selection");
+        let append = SelectionOptions {
+            input: true,
+            append: true,
+            ..Default::default()
+        };
+
+        apply(&store, append, "foo");
+        assert_eq!(apply(&store, append, "bar"), "");
+        assert_eq!(primary(&store).as_deref(), Some("foobar"));
+
+        let with_output = SelectionOptions {
+            output: true,
+            ..append
+        };
+        assert_eq!(apply(&store, with_output, "baz"), "foobar");
+        assert_eq!(primary(&store).as_deref(), Some("foobarbaz"));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn follows_input() {
+        let (store, dir) = store("follows_input");
+        let follow = SelectionOptions {
+            input: true,
+            follow: true,
+            trim: true,
+            ..Default::default()
+        };
+
+        apply(&store, follow, "foo\nbar\n");
+        assert_eq!(primary(&store).as_deref(), Some("foo\nbar"));
+
+        let append = SelectionOptions {
+            append: true,
+            ..follow
+        };
+        apply(&store, append, "\nbaz\n");
+        assert_eq!(primary(&store).as_deref(), Some("foo\nbar\nbaz"));
+
+        let zero_flush = SelectionOptions {
+            zero_flush: true,
+            ..follow
+        };
+        apply(&store, zero_flush, "one\0two\n");
+        assert_eq!(primary(&store).as_deref(), Some("two"));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
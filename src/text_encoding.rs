@@ -0,0 +1,403 @@
+// Copyright (c) Max Kagamine
+// Licensed under the Apache License, Version 2.0
+
+// xsel reads and writes UTF-8 by default, but input may come from a Windows program that writes
+// UTF-16 or the system code page, and output may be going to one that expects the same. UTF-8 and
+// UTF-16 are handled here; code pages are converted by Windows.
+// https://learn.microsoft.com/en-us/windows/win32/intl/code-page-identifiers
+
+use anyhow::{Result, bail};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    /// UTF-8, or UTF-16 if there's a BOM. Only for decoding.
+    Auto,
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    /// A Windows code page. `CP_ACP` (0) and `CP_OEMCP` (1) are the system's "ANSI" and OEM code
+    /// pages.
+    CodePage(u32),
+}
+
+const CP_ACP: u32 = 0;
+const CP_OEMCP: u32 = 1;
+
+/// Common names for code pages, with dashes and underscores removed.
+const CODE_PAGE_NAMES: &[(&str, u32)] = &[
+    ("ansi", CP_ACP),
+    ("oem", CP_OEMCP),
+    ("ascii", 20127),
+    ("usascii", 20127),
+    ("latin1", 28591),
+    ("iso88591", 28591),
+    ("shiftjis", 932),
+    ("sjis", 932),
+    ("eucjp", 20932),
+    ("iso2022jp", 50220),
+    ("gbk", 936),
+    ("gb2312", 936),
+    ("gb18030", 54936),
+    ("euckr", 949),
+    ("big5", 950),
+    ("koi8r", 20866),
+];
+
+/// Code pages for which Windows rejects the usual flags with `ERROR_INVALID_FLAGS`, and when
+/// encoding, can't report whether the default character was used. (GB18030 does allow
+/// `MB_ERR_INVALID_CHARS` when decoding.)
+#[cfg(windows)]
+fn rejects_flags(code_page: u32) -> bool {
+    matches!(
+        code_page,
+        42 | 50220..=50229 | 52936 | 54936 | 57002..=57011 | 65000
+    )
+}
+
+impl Encoding {
+    /// Parses an encoding name (case-insensitive): `auto`, `utf-8`, `utf-16le` (or `utf-16`),
+    /// `utf-16be`, `ansi` or `oem` for the system code pages, a code page by number (`932`,
+    /// `cp1252`, `windows-1252`), or a few common names like `shift_jis` and `latin1`.
+    #[must_use]
+    pub fn parse(name: &str) -> Option<Self> {
+        let name = name.to_ascii_lowercase().replace(['-', '_'], "");
+
+        let code_page = match name.as_str() {
+            "auto" => return Some(Self::Auto),
+            "utf8" => 65001,
+            "utf16" | "utf16le" => 1200,
+            "utf16be" => 1201,
+            _ => CODE_PAGE_NAMES
+                .iter()
+                .find(|x| x.0 == name)
+                .map(|x| x.1)
+                .or_else(|| {
+                    ["cp", "windows", "ibm", ""]
+                        .iter()
+                        .find_map(|prefix| name.strip_prefix(prefix)?.parse().ok())
+                })?,
+        };
+
+        Some(match code_page {
+            65001 => Self::Utf8,
+            1200 => Self::Utf16Le,
+            1201 => Self::Utf16Be,
+            _ => Self::CodePage(code_page),
+        })
+    }
+}
+
+/// Decodes `bytes` as text. A BOM for the encoding is removed; with `Auto`, it decides the
+/// encoding. Invalid input is an error unless `lossy`, in which case it's replaced with U+FFFD.
+///
+/// # Errors
+/// The input is invalid, or the code page isn't available.
+pub fn decode(bytes: &[u8], encoding: Encoding, lossy: bool) -> Result<String> {
+    let encoding = match (encoding, bytes) {
+        (Encoding::Auto, [0xff, 0xfe, ..]) => Encoding::Utf16Le,
+        (Encoding::Auto, [0xfe, 0xff, ..]) => Encoding::Utf16Be,
+        (Encoding::Auto, _) => Encoding::Utf8,
+        (encoding, _) => encoding,
+    };
+
+    match encoding {
+        Encoding::Utf8 => {
+            let (bom_len, bytes) = match bytes.strip_prefix(b"\xef\xbb\xbf") {
+                Some(rest) => (3, rest),
+                None => (0, bytes),
+            };
+            match std::str::from_utf8(bytes) {
+                Ok(text) => Ok(text.to_string()),
+                Err(_) if lossy => Ok(String::from_utf8_lossy(bytes).into_owned()),
+                Err(err) => bail!("invalid UTF-8 at byte {}", bom_len + err.valid_up_to()),
+            }
+        }
+        Encoding::Utf16Le | Encoding::Utf16Be => {
+            let big_endian = encoding == Encoding::Utf16Be;
+            let bom: &[u8] = if big_endian { b"\xfe\xff" } else { b"\xff\xfe" };
+            decode_utf16(bytes.strip_prefix(bom).unwrap_or(bytes), big_endian, lossy)
+        }
+        Encoding::CodePage(code_page) => decode_code_page(bytes, code_page, lossy),
+        Encoding::Auto => unreachable!(),
+    }
+}
+
+fn decode_utf16(bytes: &[u8], big_endian: bool, lossy: bool) -> Result<String> {
+    let units = bytes.chunks_exact(2).map(|x| {
+        if big_endian {
+            u16::from_be_bytes([x[0], x[1]])
+        } else {
+            u16::from_le_bytes([x[0], x[1]])
+        }
+    });
+
+    let mut text = String::with_capacity(bytes.len() / 2);
+    let mut unit = 0;
+    for result in char::decode_utf16(units) {
+        match result {
+            Ok(c) => {
+                text.push(c);
+                unit += c.len_utf16();
+            }
+            Err(_) if lossy => {
+                text.push(char::REPLACEMENT_CHARACTER);
+                unit += 1;
+            }
+            Err(_) => bail!("unpaired surrogate in UTF-16 at byte {}", unit * 2),
+        }
+    }
+
+    if !bytes.len().is_multiple_of(2) {
+        if !lossy {
+            bail!("UTF-16 input has an odd number of bytes");
+        }
+        text.push(char::REPLACEMENT_CHARACTER);
+    }
+
+    Ok(text)
+}
+
+/// Encodes `text`. Characters that the code page can't represent are an error unless `lossy`, in
+/// which case Windows replaces them with a similar character or its default (usually `?`).
+///
+/// # Errors
+/// A character can't be represented, or the code page isn't available.
+pub fn encode(text: &str, encoding: Encoding, lossy: bool) -> Result<Vec<u8>> {
+    match encoding {
+        Encoding::Auto | Encoding::Utf8 => Ok(text.as_bytes().to_vec()),
+        Encoding::Utf16Le => Ok(text.encode_utf16().flat_map(u16::to_le_bytes).collect()),
+        Encoding::Utf16Be => Ok(text.encode_utf16().flat_map(u16::to_be_bytes).collect()),
+        Encoding::CodePage(code_page) => encode_code_page(text, code_page, lossy),
+    }
+}
+
+#[cfg(windows)]
+fn decode_code_page(bytes: &[u8], code_page: u32, lossy: bool) -> Result<String> {
+    use windows::Win32::Globalization::{
+        MB_ERR_INVALID_CHARS, MULTI_BYTE_TO_WIDE_CHAR_FLAGS, MultiByteToWideChar,
+    };
+
+    if bytes.is_empty() {
+        return Ok(String::new());
+    }
+
+    let no_flags = rejects_flags(code_page) && code_page != 54936;
+    let flags = if lossy || no_flags {
+        MULTI_BYTE_TO_WIDE_CHAR_FLAGS(0)
+    } else {
+        MB_ERR_INVALID_CHARS
+    };
+
+    let text = unsafe {
+        let len = MultiByteToWideChar(code_page, flags, bytes, None);
+        let Ok(len @ 1..) = usize::try_from(len) else {
+            return Err(code_page_error(code_page));
+        };
+
+        let mut wide = vec![0u16; len];
+        MultiByteToWideChar(code_page, flags, bytes, Some(&mut wide));
+        String::from_utf16_lossy(&wide)
+    };
+
+    // Without the flag, invalid input is replaced instead. None of these code pages can encode
+    // U+FFFD itself, so if it's there, the input was invalid.
+    if no_flags && !lossy && text.contains(char::REPLACEMENT_CHARACTER) {
+        bail!("invalid input for code page {code_page}");
+    }
+
+    Ok(text)
+}
+
+#[cfg(windows)]
+fn encode_code_page(text: &str, code_page: u32, lossy: bool) -> Result<Vec<u8>> {
+    use windows::{
+        Win32::Globalization::{WC_NO_BEST_FIT_CHARS, WideCharToMultiByte},
+        core::{BOOL, PCSTR},
+    };
+
+    if text.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    // Without the flags, check that the result decodes back to the same text instead
+    let no_flags = rejects_flags(code_page);
+
+    let wide: Vec<u16> = text.encode_utf16().collect();
+    let flags = if lossy || no_flags {
+        0
+    } else {
+        WC_NO_BEST_FIT_CHARS
+    };
+    let mut used_default = BOOL(0);
+    let used_default_ptr = (!lossy && !no_flags).then_some(&raw mut used_default);
+
+    unsafe {
+        let len = WideCharToMultiByte(
+            code_page,
+            flags,
+            &wide,
+            None,
+            PCSTR::null(),
+            used_default_ptr,
+        );
+        let Ok(len @ 1..) = usize::try_from(len) else {
+            return Err(code_page_error(code_page));
+        };
+
+        let mut bytes = vec![0u8; len];
+        WideCharToMultiByte(
+            code_page,
+            flags,
+            &wide,
+            Some(&mut bytes),
+            PCSTR::null(),
+            used_default_ptr,
+        );
+
+        if used_default.as_bool()
+            || (no_flags
+                && !lossy
+                && decode_code_page(&bytes, code_page, true).is_ok_and(|x| x != text))
+        {
+            bail!("some characters can't be represented in code page {code_page}");
+        }
+
+        Ok(bytes)
+    }
+}
+
+#[cfg(windows)]
+fn code_page_error(code_page: u32) -> anyhow::Error {
+    use windows::Win32::Foundation::{ERROR_INVALID_PARAMETER, ERROR_NO_UNICODE_TRANSLATION};
+
+    let err = windows::core::Error::from_win32();
+    if err.code() == ERROR_NO_UNICODE_TRANSLATION.to_hresult() {
+        anyhow::anyhow!("invalid input for code page {code_page}")
+    } else if err.code() == ERROR_INVALID_PARAMETER.to_hresult() {
+        anyhow::anyhow!("code page {code_page} isn't available")
+    } else {
+        anyhow::Error::new(err).context(format!("code page {code_page}"))
+    }
+}
+
+#[cfg(not(windows))]
+fn decode_code_page(_: &[u8], _: u32, _: bool) -> Result<String> {
+    bail!("code pages are only supported on Windows")
+}
+
+#[cfg(not(windows))]
+fn encode_code_page(_: &str, _: u32, _: bool) -> Result<Vec<u8>> {
+    bail!("code pages are only supported on Windows")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_encodings() {
+        assert_eq!(Encoding::parse("auto"), Some(Encoding::Auto));
+        assert_eq!(Encoding::parse("UTF-8"), Some(Encoding::Utf8));
+        assert_eq!(Encoding::parse("utf8"), Some(Encoding::Utf8));
+        assert_eq!(Encoding::parse("cp65001"), Some(Encoding::Utf8));
+        assert_eq!(Encoding::parse("utf-16"), Some(Encoding::Utf16Le));
+        assert_eq!(Encoding::parse("UTF-16LE"), Some(Encoding::Utf16Le));
+        assert_eq!(Encoding::parse("utf-16be"), Some(Encoding::Utf16Be));
+        assert_eq!(Encoding::parse("ansi"), Some(Encoding::CodePage(0)));
+        assert_eq!(Encoding::parse("OEM"), Some(Encoding::CodePage(1)));
+        assert_eq!(Encoding::parse("932"), Some(Encoding::CodePage(932)));
+        assert_eq!(Encoding::parse("cp1252"), Some(Encoding::CodePage(1252)));
+        assert_eq!(
+            Encoding::parse("windows-1252"),
+            Some(Encoding::CodePage(1252))
+        );
+        assert_eq!(Encoding::parse("IBM437"), Some(Encoding::CodePage(437)));
+        assert_eq!(Encoding::parse("Shift_JIS"), Some(Encoding::CodePage(932)));
+        assert_eq!(Encoding::parse("latin-1"), Some(Encoding::CodePage(28591)));
+        assert_eq!(
+            Encoding::parse("ISO-2022-JP"),
+            Some(Encoding::CodePage(50220))
+        );
+        assert_eq!(Encoding::parse("utf-32"), None);
+        assert_eq!(Encoding::parse("cp"), None);
+        assert_eq!(Encoding::parse(""), None);
+    }
+
+    #[test]
+    fn auto_detects_bom() {
+        let little_endian = b"\xff\xfe\xe1\x93\xf3\x97";
+        let big_endian = b"\xfe\xff\x93\xe1\x97\xf3";
+        let utf8 = "\u{feff}鏡音".as_bytes();
+
+        assert_eq!(
+            decode(little_endian, Encoding::Auto, false).unwrap(),
+            "鏡音"
+        );
+        assert_eq!(decode(big_endian, Encoding::Auto, false).unwrap(), "鏡音");
+        assert_eq!(decode(utf8, Encoding::Auto, false).unwrap(), "鏡音");
+        assert_eq!(decode(b"plain", Encoding::Auto, false).unwrap(), "plain");
+    }
+
+    #[test]
+    fn decodes_utf16_without_bom() {
+        assert_eq!(
+            decode(b"R\0i\0n\0", Encoding::Utf16Le, false).unwrap(),
+            "Rin"
+        );
+        assert_eq!(
+            decode(b"\0R\0i\0n", Encoding::Utf16Be, false).unwrap(),
+            "Rin"
+        );
+        assert_eq!(
+            decode(b"\xd8\x3d\xde\x00", Encoding::Utf16Be, false).unwrap(),
+            "😀"
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_input_unless_lossy() {
+        let invalid_utf8 = b"ab\xffc";
+        let err = decode(invalid_utf8, Encoding::Utf8, false).unwrap_err();
+        assert_eq!(err.to_string(), "invalid UTF-8 at byte 2");
+        let err = decode(b"\xef\xbb\xbfab\xffc", Encoding::Auto, false).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid UTF-8 at byte 5",
+            "should count the BOM"
+        );
+        assert_eq!(
+            decode(invalid_utf8, Encoding::Auto, true).unwrap(),
+            "ab\u{fffd}c"
+        );
+
+        let unpaired = b"a\0\x00\xdcb\0";
+        let err = decode(unpaired, Encoding::Utf16Le, false).unwrap_err();
+        assert_eq!(err.to_string(), "unpaired surrogate in UTF-16 at byte 2");
+        assert_eq!(
+            decode(unpaired, Encoding::Utf16Le, true).unwrap(),
+            "a\u{fffd}b"
+        );
+
+        // The offset counts both units of a surrogate pair
+        let after_pair = b"\x3d\xd8\x00\xde\x00\xdc";
+        let err = decode(after_pair, Encoding::Utf16Le, false).unwrap_err();
+        assert_eq!(err.to_string(), "unpaired surrogate in UTF-16 at byte 4");
+
+        let odd = b"a\0b";
+        assert!(decode(odd, Encoding::Utf16Le, false).is_err());
+        assert_eq!(decode(odd, Encoding::Utf16Le, true).unwrap(), "a\u{fffd}");
+    }
+
+    #[test]
+    fn encodes_unicode() {
+        assert_eq!(encode("Rin", Encoding::Utf8, false).unwrap(), b"Rin");
+        assert_eq!(
+            encode("鏡音", Encoding::Utf16Le, false).unwrap(),
+            b"\xe1\x93\xf3\x97"
+        );
+        assert_eq!(
+            encode("鏡音", Encoding::Utf16Be, false).unwrap(),
+            b"\x93\xe1\x97\xf3"
+        );
+    }
+}
@@ -0,0 +1,250 @@
+// Copyright (c) Max Kagamine
+// Licensed under the Apache License, Version 2.0
+
+// The Linux side of `recycle` trashes and deletes items on several threads, since most of the time
+// is spent waiting on syscalls (especially over 9p). Results are still handed back to the calling
+// thread in input order, so that the output and the journal read the same as if the items had been
+// removed one at a time.
+
+use std::{
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc,
+    },
+    thread,
+};
+
+/// Lower limit on the default number of threads. They're mostly waiting on syscalls rather than
+/// using the CPU, so even a single-CPU machine benefits from a few.
+pub const MIN_WORKERS: usize = 4;
+
+/// Upper limit on the number of threads. Beyond this the disk is the bottleneck, not us.
+pub const MAX_WORKERS: usize = 8;
+
+/// One thread per CPU, but no fewer than `MIN_WORKERS` even on a machine with fewer CPUs, and no
+/// more than `MAX_WORKERS`.
+#[must_use]
+pub fn default_workers() -> usize {
+    thread::available_parallelism()
+        .map_or(1, NonZeroUsize::get)
+        .clamp(MIN_WORKERS, MAX_WORKERS)
+}
+
+/// Buffers results that arrive out of order and releases them in order.
+#[derive(Debug)]
+pub struct Reorder<R> {
+    results: Vec<Option<R>>,
+    next: usize,
+}
+
+impl<R> Reorder<R> {
+    #[must_use]
+    pub fn new(len: usize) -> Self {
+        Self {
+            results: (0..len).map(|_| None).collect(),
+            next: 0,
+        }
+    }
+
+    /// Stores the result for index `i`, then passes `emit` every result that's now next in line.
+    pub fn push(&mut self, i: usize, result: R, mut emit: impl FnMut(usize, R)) {
+        self.results[i] = Some(result);
+        while let Some(result) = self.results.get_mut(self.next).and_then(Option::take) {
+            emit(self.next, result);
+            self.next += 1;
+        }
+    }
+
+    /// Passes `emit` any results still waiting on an index that will never arrive, in order.
+    pub fn finish(self, mut emit: impl FnMut(usize, R)) {
+        for (i, result) in self.results.into_iter().enumerate().skip(self.next) {
+            if let Some(result) = result {
+                emit(i, result);
+            }
+        }
+    }
+}
+
+/// Runs `work` for each item on up to `workers` threads, calling `report` on the current thread
+/// with each item's index and result in input order.
+///
+/// Each chain of indices (see `chain_nested`) is run in order on a single thread. If `report`
+/// returns false, no more items are started, but those already running are finished and reported,
+/// skipping over any that never ran.
+pub fn run_ordered<T, R>(
+    items: &[T],
+    chains: &[Vec<usize>],
+    workers: usize,
+    work: impl Fn(&T) -> R + Sync,
+    mut report: impl FnMut(usize, R) -> bool,
+) where
+    T: Sync,
+    R: Send,
+{
+    let next_chain = AtomicUsize::new(0);
+    let stop = AtomicBool::new(false);
+    let mut reorder = Reorder::new(items.len());
+    let (tx, rx) = mpsc::channel();
+
+    thread::scope(|scope| {
+        for _ in 0..workers.clamp(1, chains.len().max(1)) {
+            let tx = tx.clone();
+            let (next_chain, stop, work) = (&next_chain, &stop, &work);
+            scope.spawn(move || {
+                while let Some(chain) = chains.get(next_chain.fetch_add(1, Ordering::Relaxed)) {
+                    for &i in chain {
+                        if stop.load(Ordering::Relaxed) || tx.send((i, work(&items[i]))).is_err() {
+                            return;
+                        }
+                    }
+                }
+            });
+        }
+        drop(tx);
+
+        for (i, result) in rx {
+            reorder.push(i, result, |i, result| {
+                if !report(i, result) {
+                    stop.store(true, Ordering::Relaxed);
+                }
+            });
+        }
+    });
+
+    reorder.finish(|i, result| {
+        report(i, result);
+    });
+}
+
+/// Groups the indices of paths that are inside (or the same as) one another into chains, so that
+/// they run in order on one thread rather than racing. Paths should be absolute, with symlinks in
+/// the dirname resolved. Each chain is in ascending order, and the chains are ordered by their
+/// first index.
+#[must_use]
+pub fn chain_nested(paths: &[PathBuf]) -> Vec<Vec<usize>> {
+    // Paths compare by component, so anything inside a path sorts right after it
+    let mut sorted: Vec<usize> = (0..paths.len()).collect();
+    sorted.sort_by(|&a, &b| paths[a].cmp(&paths[b]));
+
+    let mut chains: Vec<Vec<usize>> = Vec::new();
+    let mut root: Option<&Path> = None;
+
+    for i in sorted {
+        if let Some(chain) = chains.last_mut()
+            && root.is_some_and(|x| paths[i].starts_with(x))
+        {
+            chain.push(i);
+        } else {
+            root = Some(&paths[i]);
+            chains.push(vec![i]);
+        }
+    }
+
+    for chain in &mut chains {
+        chain.sort_unstable();
+    }
+    chains.sort_unstable_by_key(|x| x[0]);
+    chains
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn singles(n: usize) -> Vec<Vec<usize>> {
+        (0..n).map(|i| vec![i]).collect()
+    }
+
+    #[test]
+    fn reports_in_input_order() {
+        let items: Vec<u32> = (0..20).collect();
+        let mut reported = Vec::new();
+
+        // Earlier items take longer, so they finish last
+        run_ordered(
+            &items,
+            &singles(items.len()),
+            4,
+            |&x| {
+                thread::sleep(Duration::from_millis(1) * (20 - x));
+                x * 10
+            },
+            |i, result| {
+                reported.push((i, result));
+                true
+            },
+        );
+
+        let expected: Vec<_> = items.iter().enumerate().map(|(i, x)| (i, x * 10)).collect();
+        assert_eq!(reported, expected);
+    }
+
+    #[test]
+    fn stops_starting_items_when_report_returns_false() {
+        let items: Vec<usize> = (0..10).collect();
+        let started = std::sync::Mutex::new(Vec::new());
+        let mut reported = Vec::new();
+
+        run_ordered(
+            &items,
+            &singles(items.len()),
+            2,
+            |&x| {
+                started.lock().unwrap().push(x);
+                if x > 0 {
+                    thread::sleep(Duration::from_millis(20));
+                }
+                x
+            },
+            |i, _| {
+                reported.push(i);
+                false
+            },
+        );
+
+        let mut started = started.into_inner().unwrap();
+        started.sort_unstable();
+        assert!(started.len() < items.len(), "should have stopped");
+        assert_eq!(
+            reported, started,
+            "items already running should be reported"
+        );
+    }
+
+    #[test]
+    fn reorder_skips_gaps_when_finished() {
+        let mut reorder = Reorder::new(4);
+        let mut emitted = Vec::new();
+
+        reorder.push(1, 'b', |i, x| emitted.push((i, x)));
+        assert!(emitted.is_empty());
+        reorder.push(0, 'a', |i, x| emitted.push((i, x)));
+        reorder.push(3, 'd', |i, x| emitted.push((i, x)));
+        reorder.finish(|i, x| emitted.push((i, x)));
+
+        assert_eq!(emitted, [(0, 'a'), (1, 'b'), (3, 'd')]);
+    }
+
+    #[test]
+    fn chains_nested_paths() {
+        let paths: Vec<PathBuf> = [
+            "/home/rin/foo/bar",
+            "/home/rin/baz",
+            "/home/rin/foo",
+            "/home/rin/foo-bar",
+            "/home/rin/foo/bar/qux",
+            "/home/rin/baz",
+        ]
+        .into_iter()
+        .map(PathBuf::from)
+        .collect();
+
+        assert_eq!(
+            chain_nested(&paths),
+            vec![vec![0, 2, 4], vec![1, 5], vec![3]]
+        );
+    }
+}
@@ -3,7 +3,7 @@
 
 #![cfg(windows)]
 
-use wsl_tools::clipboard;
+use wsl_tools::{clipboard, clipboard_html, clipboard_image};
 
 mod clipboard_via_powershell;
 use clipboard_via_powershell::*;
@@ -100,3 +100,162 @@ fn clears_clipboard() {
 
     assert!(!clipboard_contains_text());
 }
+
+#[test]
+fn sets_html() {
+    let fragment = "<b>鏡音リン</b> &amp; <i>レン</i>";
+
+    clear_clipboard_via_powershell();
+
+    clipboard::set_html(fragment).unwrap_or_else(|err| panic!("set_html() failed: {err:?}"));
+
+    let actual = clipboard_html::parse(get_html_via_powershell().as_bytes())
+        .unwrap_or_else(|| panic!("invalid CF_HTML"));
+    assert_eq!(actual.fragment, fragment);
+}
+
+#[test]
+fn gets_html() {
+    let fragment = "<table><tr><td>初音ミク</td></tr></table>";
+
+    set_html_via_powershell(&clipboard_html::build(fragment));
+
+    let actual = clipboard::get_html()
+        .unwrap_or_else(|err| panic!("get_html() failed: {err:?}"))
+        .unwrap_or_else(|| panic!("get_html() returned None"));
+
+    assert_eq!(actual.fragment, fragment);
+    assert!(actual.document.contains(fragment));
+}
+
+#[test]
+fn get_html_returns_none_for_text() {
+    set_clipboard_via_powershell("not html");
+
+    let actual = clipboard::get_html().unwrap_or_else(|err| panic!("get_html() failed: {err:?}"));
+
+    assert_eq!(actual, None);
+}
+
+fn fixture_png() -> Vec<u8> {
+    let dib = std::fs::read(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/fixtures/dib/v5_alpha.dib"
+    ))
+    .unwrap();
+    clipboard_image::dib_to_png(&dib).unwrap()
+}
+
+#[test]
+fn sets_and_gets_png() {
+    let png = fixture_png();
+
+    clear_clipboard_via_powershell();
+
+    clipboard::set_png(&png).unwrap_or_else(|err| panic!("set_png() failed: {err:?}"));
+    assert!(
+        clipboard_contains_image(),
+        "should have placed a bitmap too"
+    );
+
+    let actual = clipboard::get_png()
+        .unwrap_or_else(|err| panic!("get_png() failed: {err:?}"))
+        .unwrap_or_else(|| panic!("get_png() returned None"));
+
+    assert_eq!(actual, png, "should have gotten the PNG back as-is");
+}
+
+#[test]
+fn gets_bitmap_as_png() {
+    set_image_via_powershell(&fixture_png());
+
+    let actual = clipboard::get_png()
+        .unwrap_or_else(|err| panic!("get_png() failed: {err:?}"))
+        .unwrap_or_else(|| panic!("get_png() returned None"));
+
+    assert!(actual.starts_with(b"\x89PNG"));
+}
+
+#[test]
+fn get_png_returns_none_for_text() {
+    set_clipboard_via_powershell("not an image");
+
+    let actual = clipboard::get_png().unwrap_or_else(|err| panic!("get_png() failed: {err:?}"));
+
+    assert_eq!(actual, None);
+}
+
+#[test]
+fn sets_and_gets_files() {
+    let files = vec![
+        "C:\\Windows\\notepad.exe".to_string(),
+        "C:\\Users\\Public\\鏡音リン.txt".to_string(),
+    ];
+
+    clear_clipboard_via_powershell();
+
+    clipboard::set_files(&files).unwrap_or_else(|err| panic!("set_files() failed: {err:?}"));
+    assert_eq!(get_file_drop_list_via_powershell(), files);
+
+    let actual = clipboard::get_files()
+        .unwrap_or_else(|err| panic!("get_files() failed: {err:?}"))
+        .unwrap_or_else(|| panic!("get_files() returned None"));
+
+    assert_eq!(actual, files);
+}
+
+#[test]
+fn lists_formats() {
+    clipboard::set(&[
+        clipboard::Content::Text("鏡音リン"),
+        clipboard::Content::Html("<b>鏡音リン</b>"),
+    ])
+    .unwrap_or_else(|err| panic!("set() failed: {err:?}"));
+
+    let formats = clipboard::formats().unwrap_or_else(|err| panic!("formats() failed: {err:?}"));
+
+    assert_eq!(formats[0].id, 13, "should be in the order they were set");
+    assert_eq!(formats[1].name.as_deref(), Some("HTML Format"));
+    assert!(
+        formats.iter().any(|x| x.id == 1),
+        "should include synthesized formats"
+    );
+}
+
+#[test]
+fn marks_content_as_sensitive() {
+    clipboard::set(&[
+        clipboard::Content::Text("hunter2"),
+        clipboard::Content::Sensitive,
+    ])
+    .unwrap_or_else(|err| panic!("set() failed: {err:?}"));
+
+    assert_eq!(get_clipboard_via_powershell(), "hunter2");
+    assert!(clipboard::is_sensitive().unwrap());
+
+    let formats = clipboard::formats().unwrap_or_else(|err| panic!("formats() failed: {err:?}"));
+    for name in [
+        "ExcludeClipboardContentFromMonitorProcessing",
+        "CanIncludeInClipboardHistory",
+        "CanUploadToCloudClipboard",
+    ] {
+        assert!(
+            formats.iter().any(|x| x.name.as_deref() == Some(name)),
+            "should include {name}"
+        );
+    }
+
+    clipboard::set_text("not a secret").unwrap();
+    assert!(!clipboard::is_sensitive().unwrap());
+}
+
+#[test]
+fn sequence_number_changes_with_clipboard() {
+    clipboard::set_text("before").unwrap();
+    let before = clipboard::sequence_number();
+
+    assert_eq!(clipboard::sequence_number(), before);
+
+    clipboard::set_text("after").unwrap();
+    assert_ne!(clipboard::sequence_number(), before);
+}
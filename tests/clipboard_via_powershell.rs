@@ -60,6 +60,128 @@ pub fn get_clipboard_via_powershell() -> String {
     String::from_utf8(bytes).unwrap()
 }
 
+/// Calls `System.Windows.Forms.Clipboard.SetText(data, TextDataFormat.Html)` via PowerShell. The
+/// data should include the CF_HTML header.
+///
+/// # Panics
+/// Command failed (check output in terminal).
+pub fn set_html_via_powershell(data: &str) {
+    let base64str = base64.encode(data);
+
+    Command::new("powershell.exe")
+        .arg("-NoProfile")
+        .arg("-Command")
+        .arg(format!(
+            r#"
+            Add-Type -AssemblyName System.Windows.Forms;
+            $bytes = [System.Convert]::FromBase64String("{base64str}");
+            $text = [System.Text.Encoding]::UTF8.GetString($bytes);
+            [System.Windows.Forms.Clipboard]::SetText($text, [System.Windows.Forms.TextDataFormat]::Html);
+            "#
+        ))
+        .assert()
+        .success();
+}
+
+/// Calls `System.Windows.Forms.Clipboard.GetText(TextDataFormat.Html)` via PowerShell, which
+/// returns the raw CF_HTML including the header.
+///
+/// # Panics
+/// Command failed (check output in terminal).
+#[must_use]
+pub fn get_html_via_powershell() -> String {
+    let base64str = Command::new("powershell.exe")
+        .arg("-NoProfile")
+        .arg("-Command")
+        .arg(
+            "
+            Add-Type -AssemblyName System.Windows.Forms;
+            $text = [System.Windows.Forms.Clipboard]::GetText([System.Windows.Forms.TextDataFormat]::Html);
+            $bytes = [System.Text.Encoding]::UTF8.GetBytes($text);
+            [System.Console]::Write([System.Convert]::ToBase64String($bytes));
+            ",
+        )
+        .unwrap()
+        .stdout;
+
+    let bytes = base64.decode(base64str).unwrap();
+    String::from_utf8(bytes).unwrap()
+}
+
+/// Loads a PNG into a `System.Drawing.Bitmap` and calls `System.Windows.Forms.Clipboard.SetImage()`
+/// via PowerShell, which places it on the clipboard as a bitmap only (no "PNG" format).
+///
+/// # Panics
+/// Command failed (check output in terminal).
+pub fn set_image_via_powershell(png: &[u8]) {
+    let base64str = base64.encode(png);
+
+    Command::new("powershell.exe")
+        .arg("-NoProfile")
+        .arg("-Command")
+        .arg(format!(
+            r#"
+            Add-Type -AssemblyName System.Windows.Forms, System.Drawing;
+            $bytes = [System.Convert]::FromBase64String("{base64str}");
+            $stream = New-Object System.IO.MemoryStream(,$bytes);
+            $image = [System.Drawing.Image]::FromStream($stream);
+            [System.Windows.Forms.Clipboard]::SetImage($image);
+            "#
+        ))
+        .assert()
+        .success();
+}
+
+/// Checks `System.Windows.Forms.Clipboard.ContainsImage()` via PowerShell.
+///
+/// # Panics
+/// Command failed (check output in terminal).
+#[must_use]
+pub fn clipboard_contains_image() -> bool {
+    let output = Command::new("powershell.exe")
+        .arg("-NoProfile")
+        .arg("-Command")
+        .arg(
+            "
+            Add-Type -AssemblyName System.Windows.Forms;
+            [System.Windows.Forms.Clipboard]::ContainsImage();
+            ",
+        )
+        .unwrap()
+        .stdout;
+
+    output == b"True\r\n"
+}
+
+/// Calls `System.Windows.Forms.Clipboard.GetFileDropList()` via PowerShell.
+///
+/// # Panics
+/// Command failed (check output in terminal).
+#[must_use]
+pub fn get_file_drop_list_via_powershell() -> Vec<String> {
+    let base64str = Command::new("powershell.exe")
+        .arg("-NoProfile")
+        .arg("-Command")
+        .arg(
+            r#"
+            Add-Type -AssemblyName System.Windows.Forms;
+            $text = [System.Windows.Forms.Clipboard]::GetFileDropList() -join "`n";
+            $bytes = [System.Text.Encoding]::UTF8.GetBytes($text);
+            [System.Console]::Write([System.Convert]::ToBase64String($bytes));
+            "#,
+        )
+        .unwrap()
+        .stdout;
+
+    let bytes = base64.decode(base64str).unwrap();
+    String::from_utf8(bytes)
+        .unwrap()
+        .split('\n')
+        .filter(|x| !x.is_empty())
+        .map(ToString::to_string)
+        .collect()
+}
+
 /// Calls `System.Windows.Forms.Clipboard.Clear()` via PowerShell.
 ///
 /// # Panics
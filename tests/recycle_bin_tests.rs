@@ -11,8 +11,9 @@ use std::{
     process::Command,
 };
 use windows::{Win32::Foundation::ERROR_INVALID_PARAMETER, core::HRESULT};
-use wsl_tools::recycle_bin::{
-    self, RECYCLE_DANGEROUSLY_IN_BACKGROUND, RECYCLE_IGNORE_NOT_FOUND, RECYCLE_NORMAL, RecycleError,
+use wsl_tools::{
+    recycle_bin::{self, RecycleError, RecycleOptions},
+    recycle_report::ItemOutcome,
 };
 
 // This is merely a smoke test; we would need a way to click on dialog buttons in order to fully
@@ -42,7 +43,7 @@ fn recycles_relative_paths() -> Result<(), Box<dyn Error>> {
             r"recycles_relative_paths\file.txt",
             "recycles_relative_paths.txt",
         ],
-        RECYCLE_NORMAL,
+        RecycleOptions::new(),
     )?;
 
     // Check that they were removed
@@ -73,7 +74,7 @@ fn recycles_absolute_paths() -> Result<(), Box<dyn Error>> {
     // Try to recycle them
     recycle_bin::recycle(
         [subdir.to_str().unwrap(), curdir_file.to_str().unwrap()],
-        RECYCLE_NORMAL,
+        RecycleOptions::new(),
     )?;
 
     // Check that they were removed
@@ -94,7 +95,7 @@ fn supports_unicode() -> Result<(), Box<dyn Error>> {
     assert!(fs::exists(&file)?);
 
     // Try to recycle it
-    recycle_bin::recycle([file.to_str().unwrap()], RECYCLE_NORMAL)?;
+    recycle_bin::recycle([file.to_str().unwrap()], RecycleOptions::new())?;
 
     // Check that it was removed
     assert!(!fs::exists(&file)?);
@@ -107,7 +108,8 @@ fn errors_if_not_found() {
     let file = "does-not-exist.txt";
     assert!(!fs::exists(file).unwrap());
 
-    let err = recycle_bin::recycle([file], RECYCLE_NORMAL).expect_err("should not have succeeded");
+    let err =
+        recycle_bin::recycle([file], RecycleOptions::new()).expect_err("should not have succeeded");
 
     if let RecycleError::NotFound(str) = err {
         assert_eq!(str, file);
@@ -119,7 +121,7 @@ fn errors_if_not_found() {
 #[test]
 fn errors_if_invalid_path() {
     // std::path::absolute errors if empty string
-    let err = recycle_bin::recycle([""], RECYCLE_NORMAL)
+    let err = recycle_bin::recycle([""], RecycleOptions::new())
         .expect_err("empty string should not have succeeded");
 
     assert!(
@@ -128,8 +130,8 @@ fn errors_if_invalid_path() {
     );
 
     // SHCreateItemFromParsingName errors if the path contains invalid characters
-    let err =
-        recycle_bin::recycle(["foo?"], RECYCLE_NORMAL).expect_err("foo? should not have succeeded");
+    let err = recycle_bin::recycle(["foo?"], RecycleOptions::new())
+        .expect_err("foo? should not have succeeded");
 
     match err {
         RecycleError::InvalidPath(_, ref inner) if inner.is::<windows::core::Error>() => {
@@ -161,7 +163,7 @@ fn option_to_ignore_not_found() -> Result<(), Box<dyn Error>> {
             not_exist.to_str().unwrap(),
             exists_2.to_str().unwrap(),
         ],
-        RECYCLE_IGNORE_NOT_FOUND,
+        RecycleOptions::new().ignore_not_found(true),
     )
     .expect("should not have failed due to option_to_ignore_not_found_3.test missing");
 
@@ -177,7 +179,7 @@ fn option_to_ignore_not_found() -> Result<(), Box<dyn Error>> {
 fn does_nothing_if_nothing_to_do() -> Result<(), Box<dyn Error>> {
     // Check with empty paths
     let empty: [&str; 0] = [];
-    recycle_bin::recycle(empty, RECYCLE_NORMAL)
+    recycle_bin::recycle(empty, RecycleOptions::new())
         .expect("if paths is empty, it should not call PerformOperations as that will cause a 'Catastrophic failure'");
 
     // Check with ignore not found and only nonexistent files
@@ -188,7 +190,7 @@ fn does_nothing_if_nothing_to_do() -> Result<(), Box<dyn Error>> {
         [
             not_exist.to_str().unwrap(),
         ],
-        RECYCLE_IGNORE_NOT_FOUND,
+        RecycleOptions::new().ignore_not_found(true),
     )
         .expect("if all of the paths were ignored, it should not call PerformOperations as that will cause a 'Catastrophic failure'");
 
@@ -216,7 +218,10 @@ fn option_to_silently_nuke() -> Result<(), Box<dyn Error>> {
 
     // The failure mode is a bit awkward for an integration test: if the correct flags aren't set,
     // a dialog will appear and the test will block until it's dismissed.
-    recycle_bin::recycle([&file], RECYCLE_DANGEROUSLY_IN_BACKGROUND)?;
+    recycle_bin::recycle(
+        [&file],
+        RecycleOptions::new().dangerously_in_background(true),
+    )?;
 
     assert!(!fs::exists(&file)?);
 
@@ -235,7 +240,9 @@ fn options_can_be_combined() -> Result<(), Box<dyn Error>> {
 
     recycle_bin::recycle(
         [&exists, &not_exist],
-        RECYCLE_DANGEROUSLY_IN_BACKGROUND | RECYCLE_IGNORE_NOT_FOUND,
+        RecycleOptions::new()
+            .dangerously_in_background(true)
+            .ignore_not_found(true),
     )?;
 
     assert!(!fs::exists(&exists)?);
@@ -244,23 +251,29 @@ fn options_can_be_combined() -> Result<(), Box<dyn Error>> {
 }
 
 #[test]
-fn fires_callback() -> Result<(), Box<dyn Error>> {
+fn reports_each_item() -> Result<(), Box<dyn Error>> {
     // Create test file
     let temp_dir = env::temp_dir();
-    let file = temp_dir.join("fires_callback.test");
+    let file = temp_dir.join("reports_each_item.test");
+    let not_exist = temp_dir.join("reports_each_item_missing.test");
     drop(File::create(&file)?);
 
-    let mut callback_fired = false;
+    let report = recycle_bin::recycle_with_report(
+        [file.to_str().unwrap(), not_exist.to_str().unwrap()],
+        RecycleOptions::new().ignore_not_found(true),
+    )?;
+
+    assert!(!report.aborted);
+    assert_eq!(report.items.len(), 2, "should have one entry per input");
 
-    recycle_bin::recycle_with_callback([file.to_str().unwrap()], RECYCLE_NORMAL, |item, error| {
-        assert!(!callback_fired);
-        assert!(error.is_none());
-        assert_eq!(file.to_str().unwrap(), item);
+    assert_eq!(report.items[0].path, file.to_str().unwrap());
+    assert_eq!(report.items[0].absolute_path, file);
+    assert!(matches!(report.items[0].outcome, ItemOutcome::Recycled));
+    assert!(report.items[0].hresult.is_some_and(HRESULT::is_ok));
 
-        callback_fired = true;
-    })?;
+    assert!(matches!(report.items[1].outcome, ItemOutcome::Skipped));
+    assert!(report.items[1].hresult.is_none());
 
-    assert!(callback_fired);
     assert!(!fs::exists(&file)?);
 
     Ok(())
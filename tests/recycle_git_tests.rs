@@ -0,0 +1,138 @@
+// Copyright (c) Max Kagamine
+// Licensed under the Apache License, Version 2.0
+
+#![cfg(unix)]
+
+use std::{
+    env,
+    error::Error,
+    fs::{self, File},
+    path::{Path, PathBuf},
+    process::Command,
+};
+use wsl_tools::recycle_git::{self, GitSafety};
+
+// These need git, but not WSL.
+
+fn git(dir: &Path, args: &[&str]) -> Result<(), Box<dyn Error>> {
+    let status = Command::new("git")
+        .args(["-c", "user.name=Test", "-c", "user.email=test@example.com"])
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .output()?
+        .status;
+    assert!(status.success(), "git {args:?} failed");
+    Ok(())
+}
+
+/// Creates a repo with a committed `clean/file.txt`, a modified `dirty/tracked.txt`, an untracked
+/// `dirty/new.txt`, and an ignored `ignored/build.log`.
+fn create_test_repo(name: &str) -> Result<PathBuf, Box<dyn Error>> {
+    let dir = env::temp_dir().join(format!("wsl-tools-{name}"));
+    if dir.exists() {
+        fs::remove_dir_all(&dir)?;
+    }
+    fs::create_dir_all(&dir)?;
+    let dir = fs::canonicalize(dir)?;
+
+    fs::create_dir(dir.join("clean"))?;
+    fs::create_dir(dir.join("dirty"))?;
+    fs::create_dir(dir.join("ignored"))?;
+    fs::write(dir.join("clean/file.txt"), "foo")?;
+    fs::write(dir.join("dirty/tracked.txt"), "foo")?;
+    fs::write(dir.join(".gitignore"), "ignored/\n")?;
+
+    git(&dir, &["init", "-q"])?;
+    git(&dir, &["add", "."])?;
+    git(&dir, &["commit", "-q", "-m", "Initial commit"])?;
+
+    fs::write(dir.join("dirty/tracked.txt"), "bar")?;
+    File::create(dir.join("dirty/new.txt"))?;
+    File::create(dir.join("ignored/build.log"))?;
+
+    Ok(dir)
+}
+
+fn dirty_paths(safety: GitSafety) -> Vec<PathBuf> {
+    match safety {
+        GitSafety::Dirty(files) => files.into_iter().map(|x| x.path).collect(),
+        other => panic!("expected Dirty, got {other:?}"),
+    }
+}
+
+#[test]
+fn lists_uncommitted_files_under_path() -> Result<(), Box<dyn Error>> {
+    let repo = create_test_repo("lists_uncommitted_files_under_path")?;
+
+    let mut paths = dirty_paths(recycle_git::check(&repo.join("dirty"))?);
+    paths.sort();
+    assert_eq!(
+        paths,
+        vec![repo.join("dirty/new.txt"), repo.join("dirty/tracked.txt")]
+    );
+
+    // The whole repo is allowed, but still has to be confirmed
+    assert_eq!(dirty_paths(recycle_git::check(&repo)?).len(), 2);
+
+    assert_eq!(
+        dirty_paths(recycle_git::check(&repo.join("dirty/new.txt"))?),
+        vec![repo.join("dirty/new.txt")]
+    );
+    Ok(())
+}
+
+#[test]
+fn clean_and_ignored_paths_are_safe() -> Result<(), Box<dyn Error>> {
+    let repo = create_test_repo("clean_and_ignored_paths_are_safe")?;
+
+    assert_eq!(recycle_git::check(&repo.join("clean"))?, GitSafety::Safe);
+    assert_eq!(
+        recycle_git::check(&repo.join("clean/file.txt"))?,
+        GitSafety::Safe
+    );
+    assert_eq!(recycle_git::check(&repo.join("ignored"))?, GitSafety::Safe);
+    assert_eq!(
+        recycle_git::check(&repo.join("nonexistent"))?,
+        GitSafety::Safe
+    );
+    assert_eq!(
+        recycle_git::check(&repo.join(".git"))?,
+        GitSafety::GitDirectory
+    );
+    Ok(())
+}
+
+#[test]
+fn outside_a_repo_is_safe() -> Result<(), Box<dyn Error>> {
+    let dir = env::temp_dir().join("wsl-tools-outside_a_repo_is_safe");
+    fs::create_dir_all(&dir)?;
+    let dir = fs::canonicalize(dir)?;
+    File::create(dir.join("file.txt"))?;
+
+    // Unless the temp dir is itself in a repo, which would be odd
+    assert_eq!(recycle_git::check(&dir.join("file.txt"))?, GitSafety::Safe);
+    Ok(())
+}
+
+#[test]
+fn checks_repos_under_directory_outside_a_repo() -> Result<(), Box<dyn Error>> {
+    let dir = env::temp_dir().join("wsl-tools-checks_repos_under_directory");
+    if dir.exists() {
+        fs::remove_dir_all(&dir)?;
+    }
+    let repo = create_test_repo("checks_repos_under_directory/src/rin/repo")?;
+    let dir = fs::canonicalize(dir)?;
+    fs::create_dir(dir.join("empty"))?;
+
+    // e.g. recycle -r --git-safe ~/src
+    let mut paths = dirty_paths(recycle_git::check(&dir)?);
+    paths.sort();
+    assert_eq!(
+        paths,
+        vec![repo.join("dirty/new.txt"), repo.join("dirty/tracked.txt")]
+    );
+
+    assert_eq!(recycle_git::check(&dir.join("empty"))?, GitSafety::Safe);
+    Ok(())
+}
@@ -0,0 +1,315 @@
+// Copyright (c) Max Kagamine
+// Licensed under the Apache License, Version 2.0
+
+#![cfg(unix)]
+
+use std::{
+    env,
+    error::Error,
+    fs::{self, File},
+    os::{
+        fd::AsRawFd,
+        unix::fs::{PermissionsExt, symlink},
+    },
+    path::{Path, PathBuf},
+};
+use wsl_tools::remove_tree::{
+    self, REMOVE_IGNORE_NOT_FOUND, REMOVE_NORMAL, REMOVE_ONE_FILE_SYSTEM, REMOVE_RECURSIVE,
+    RemoveError,
+};
+
+// These only need a writable temp directory (tmpfs is fine), not WSL.
+
+fn create_test_dir(name: &str) -> Result<PathBuf, Box<dyn Error>> {
+    let dir = env::temp_dir().join(format!("wsl-tools-{name}"));
+    if fs::symlink_metadata(&dir).is_ok() {
+        fs::set_permissions(&dir, fs::Permissions::from_mode(0o755))?;
+
+        // fs::remove_dir_all() overflows the stack on the deep trees
+        remove_tree::remove_with_callback([path_str(&dir)], REMOVE_RECURSIVE, |_, _| {})?;
+    }
+    fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
+
+fn path_str(path: &Path) -> String {
+    path.to_str().unwrap().to_owned()
+}
+
+/// Paths and error messages passed to the callback.
+type Calls = Vec<(String, Option<String>)>;
+
+/// Removes the paths, returning the result and everything passed to the callback.
+fn remove(paths: &[&Path], options: u8) -> (Result<(), RemoveError>, Calls) {
+    let mut calls = Vec::new();
+    let result = remove_tree::remove_with_callback(
+        paths.iter().map(|x| path_str(x)),
+        options,
+        |item, err| calls.push((item, err.map(|e| e.to_string()))),
+    );
+    (result, calls)
+}
+
+/// Same as `remove`, but using `remove_parallel`.
+fn remove_in_parallel(paths: &[&Path], options: u8) -> (Result<(), RemoveError>, Calls) {
+    let mut calls = Vec::new();
+    let result = remove_tree::remove_parallel(
+        paths.iter().map(|x| path_str(x)),
+        options,
+        4,
+        |item, err| calls.push((item, err.map(|e| e.to_string()))),
+    );
+    (result, calls)
+}
+
+#[test]
+fn removes_nested_directories() -> Result<(), Box<dyn Error>> {
+    let dir = create_test_dir("removes_nested_directories")?;
+    let tree = dir.join("tree");
+    fs::create_dir_all(tree.join("a/b/c"))?;
+    File::create(tree.join("a/b/c/file.txt"))?;
+    File::create(tree.join("a/file.txt"))?;
+    let file = dir.join("file.txt");
+    File::create(&file)?;
+
+    let (result, calls) = remove(&[&tree, &file], REMOVE_RECURSIVE);
+
+    result?;
+    assert_eq!(
+        calls,
+        vec![(path_str(&tree), None), (path_str(&file), None)],
+        "callback should be called once per path"
+    );
+    assert!(fs::symlink_metadata(&tree).is_err());
+    assert!(fs::symlink_metadata(&file).is_err());
+    Ok(())
+}
+
+#[test]
+fn does_not_follow_symlinks() -> Result<(), Box<dyn Error>> {
+    let dir = create_test_dir("does_not_follow_symlinks")?;
+    let outside = dir.join("outside");
+    fs::create_dir(&outside)?;
+    File::create(outside.join("precious.txt"))?;
+
+    let tree = dir.join("tree");
+    fs::create_dir(&tree)?;
+    symlink(&outside, tree.join("link"))?;
+    let top_level_link = dir.join("top_level_link");
+    symlink(&outside, &top_level_link)?;
+
+    let (result, _) = remove(&[&tree, &top_level_link], REMOVE_RECURSIVE);
+
+    result?;
+    assert!(fs::symlink_metadata(&tree).is_err());
+    assert!(fs::symlink_metadata(&top_level_link).is_err());
+    assert!(
+        outside.join("precious.txt").exists(),
+        "symlink targets should be left alone"
+    );
+    Ok(())
+}
+
+#[test]
+fn checks_all_paths_before_removing_anything() -> Result<(), Box<dyn Error>> {
+    let dir = create_test_dir("checks_all_paths_before_removing_anything")?;
+    let file = dir.join("file.txt");
+    File::create(&file)?;
+    let subdir = dir.join("subdir");
+    fs::create_dir(&subdir)?;
+    let missing = dir.join("missing.txt");
+
+    let (result, calls) = remove(&[&file, &subdir], REMOVE_NORMAL);
+    assert!(matches!(result, Err(RemoveError::IsDirectory(x)) if x == path_str(&subdir)));
+
+    let (result, _) = remove(&[&file, &missing], REMOVE_RECURSIVE);
+    assert!(matches!(result, Err(RemoveError::NotFound(x)) if x == path_str(&missing)));
+
+    let (result, _) = remove(&[&file, &dir.join(".")], REMOVE_RECURSIVE);
+    assert!(matches!(result, Err(RemoveError::InvalidPath(_))));
+
+    assert!(calls.is_empty());
+    assert!(file.exists(), "nothing should have been removed");
+
+    let (result, calls) = remove(&[&file, &missing], REMOVE_IGNORE_NOT_FOUND);
+    result?;
+    assert_eq!(calls, vec![(path_str(&file), None)]);
+    Ok(())
+}
+
+#[test]
+fn reports_errors_per_entry() -> Result<(), Box<dyn Error>> {
+    // Root can delete from read-only directories
+    if unsafe { libc::geteuid() } == 0 {
+        return Ok(());
+    }
+
+    let dir = create_test_dir("reports_errors_per_entry")?;
+    let tree = dir.join("tree");
+    let locked = tree.join("locked");
+    fs::create_dir_all(&locked)?;
+    File::create(locked.join("file.txt"))?;
+    File::create(tree.join("other.txt"))?;
+    fs::set_permissions(&locked, fs::Permissions::from_mode(0o555))?;
+
+    let (result, calls) = remove(&[&tree], REMOVE_RECURSIVE);
+    fs::set_permissions(&locked, fs::Permissions::from_mode(0o755))?;
+
+    assert!(matches!(result, Err(RemoveError::Incomplete)));
+    assert_eq!(calls.len(), 1, "only the failed entry should be reported");
+    assert_eq!(calls[0].0, path_str(&locked.join("file.txt")));
+    assert!(calls[0].1.is_some());
+    assert!(
+        !tree.join("other.txt").exists(),
+        "siblings should still be removed"
+    );
+    Ok(())
+}
+
+#[test]
+fn one_file_system_on_the_same_device() -> Result<(), Box<dyn Error>> {
+    // Setting up a second mount requires root, so this only checks that the option doesn't skip
+    // anything it shouldn't
+    let dir = create_test_dir("one_file_system_on_the_same_device")?;
+    let tree = dir.join("tree");
+    fs::create_dir_all(tree.join("a/b"))?;
+
+    let (result, _) = remove(&[&tree], REMOVE_RECURSIVE | REMOVE_ONE_FILE_SYSTEM);
+
+    result?;
+    assert!(fs::symlink_metadata(&tree).is_err());
+    Ok(())
+}
+
+#[test]
+fn removes_in_parallel_and_reports_in_order() -> Result<(), Box<dyn Error>> {
+    let dir = create_test_dir("removes_in_parallel_and_reports_in_order")?;
+    let tree = dir.join("node_modules");
+    for package in 0..20 {
+        let lib = tree.join(format!("package-{package}/lib/nested"));
+        fs::create_dir_all(&lib)?;
+        for file in 0..5 {
+            File::create(lib.join(format!("{file}.js")))?;
+            File::create(lib.parent().unwrap().join(format!("{file}.js")))?;
+        }
+    }
+    let outside = dir.join("outside");
+    fs::create_dir(&outside)?;
+    File::create(outside.join("precious.txt"))?;
+    symlink(&outside, tree.join("package-3/link"))?;
+    let file = dir.join("file.txt");
+    File::create(&file)?;
+    let empty = dir.join("empty");
+    fs::create_dir(&empty)?;
+
+    let (result, calls) = remove_in_parallel(&[&tree, &file, &empty], REMOVE_RECURSIVE);
+
+    result?;
+    assert_eq!(
+        calls,
+        vec![
+            (path_str(&tree), None),
+            (path_str(&file), None),
+            (path_str(&empty), None)
+        ]
+    );
+    assert!(fs::symlink_metadata(&tree).is_err());
+    assert!(fs::symlink_metadata(&file).is_err());
+    assert!(fs::symlink_metadata(&empty).is_err());
+    assert!(outside.join("precious.txt").exists());
+    Ok(())
+}
+
+#[test]
+fn removes_nested_paths_one_at_a_time() -> Result<(), Box<dyn Error>> {
+    let dir = create_test_dir("removes_nested_paths_one_at_a_time")?;
+    let tree = dir.join("tree");
+    fs::create_dir_all(tree.join("a/b"))?;
+
+    // Same as `rm -r tree tree/a`: the second is already gone by the time it's reached
+    let (result, calls) = remove_in_parallel(&[&tree, &tree.join("a")], REMOVE_RECURSIVE);
+
+    assert!(matches!(result, Err(RemoveError::Incomplete)));
+    assert_eq!(calls.len(), 2);
+    assert_eq!(calls[0], (path_str(&tree), None));
+    assert_eq!(calls[1].0, path_str(&tree.join("a")));
+    assert!(fs::symlink_metadata(&tree).is_err());
+    Ok(())
+}
+
+#[test]
+fn reports_errors_per_entry_in_parallel() -> Result<(), Box<dyn Error>> {
+    if unsafe { libc::geteuid() } == 0 {
+        return Ok(());
+    }
+
+    let dir = create_test_dir("reports_errors_per_entry_in_parallel")?;
+    let tree = dir.join("tree");
+    let locked = tree.join("a/locked");
+    fs::create_dir_all(&locked)?;
+    File::create(locked.join("file.txt"))?;
+    fs::create_dir_all(tree.join("b/c"))?;
+    let file = dir.join("file.txt");
+    File::create(&file)?;
+
+    fs::set_permissions(&locked, fs::Permissions::from_mode(0o555))?;
+    let (result, calls) = remove_in_parallel(&[&tree, &file], REMOVE_RECURSIVE);
+    fs::set_permissions(&locked, fs::Permissions::from_mode(0o755))?;
+
+    assert!(matches!(result, Err(RemoveError::Incomplete)));
+    assert_eq!(calls.len(), 2);
+    assert_eq!(calls[0].0, path_str(&locked.join("file.txt")));
+    assert!(calls[0].1.is_some());
+    assert_eq!(calls[1], (path_str(&file), None));
+    assert!(!tree.join("b").exists(), "siblings should still be removed");
+    Ok(())
+}
+
+/// Creates a chain of directories `d/d/d/...` in `dir`, deeper than the fd limit (up to a point),
+/// with a file at the bottom. Returns the depth.
+fn create_deep_tree(dir: &Path) -> Result<usize, Box<dyn Error>> {
+    let mut limit = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    assert_eq!(
+        unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &raw mut limit) },
+        0
+    );
+    let depth = usize::try_from(limit.rlim_cur.min(20_000))? + 100;
+
+    // The full path would be longer than PATH_MAX, so each level is created relative to an fd for
+    // the one above it
+    let mut parent = File::open(dir)?;
+    for _ in 0..depth {
+        let path = format!("/proc/self/fd/{}/d", parent.as_raw_fd());
+        fs::create_dir(&path)?;
+        parent = File::open(&path)?;
+    }
+    File::create(format!("/proc/self/fd/{}/file.txt", parent.as_raw_fd()))?;
+
+    Ok(depth)
+}
+
+#[test]
+fn removes_trees_deeper_than_the_fd_limit() -> Result<(), Box<dyn Error>> {
+    let dir = create_test_dir("removes_trees_deeper_than_the_fd_limit")?;
+    let sequential = dir.join("sequential");
+    let parallel = dir.join("parallel");
+    fs::create_dir(&sequential)?;
+    fs::create_dir(&parallel)?;
+    create_deep_tree(&sequential)?;
+    create_deep_tree(&parallel)?;
+    fs::create_dir_all(parallel.join("shallow/a"))?;
+
+    let (result, calls) = remove(&[&sequential], REMOVE_RECURSIVE);
+    result?;
+    assert_eq!(calls, vec![(path_str(&sequential), None)]);
+    assert!(fs::symlink_metadata(&sequential).is_err());
+
+    let (result, calls) = remove_in_parallel(&[&parallel], REMOVE_RECURSIVE);
+    result?;
+    assert_eq!(calls, vec![(path_str(&parallel), None)]);
+    assert!(fs::symlink_metadata(&parallel).is_err());
+    Ok(())
+}
@@ -6,13 +6,13 @@
 use assert_cmd::prelude::*;
 use std::{
     io::Write,
-    process::{Command, Stdio},
+    process::{Child, Command, Stdio},
 };
 
 /// Test harness for running the xsel binary.
 pub struct XselHarness<'a> {
     cmd: Command,
-    stdin: Option<&'a str>,
+    stdin: Option<&'a [u8]>,
     stdout_is_tty: bool,
 }
 
@@ -42,6 +42,12 @@ impl<'a> XselHarness<'a> {
     /// Sets the text to be fed into stdin when the command is run. This will cause stdin to appear
     /// as a pipe rather than a tty.
     pub fn stdin(&mut self, input: &'a str) -> &mut Self {
+        self.stdin = Some(input.as_bytes());
+        self
+    }
+
+    /// Same as `stdin()` but for binary input.
+    pub fn stdin_bytes(&mut self, input: &'a [u8]) -> &mut Self {
         self.stdin = Some(input);
         self
     }
@@ -61,6 +67,30 @@ impl<'a> XselHarness<'a> {
     /// - Could not write to stdin.
     /// - Output could not be read as UTF-8.
     pub fn run(&mut self) -> String {
+        String::from_utf8(self.run_bytes()).unwrap()
+    }
+
+    /// Same as `run()` but returns the raw output.
+    ///
+    /// # Panics
+    /// - Failed to execute or wait for the command.
+    /// - The command exited with a non-zero exit code.
+    /// - Could not write to stdin.
+    pub fn run_bytes(&mut self) -> Vec<u8> {
+        let child = self.spawn();
+        let output = child.wait_with_output().unwrap();
+        assert!(output.status.success(), "{output:?}");
+
+        output.stdout
+    }
+
+    /// Starts xsel with the configured arguments and pipe setup without waiting for it to exit,
+    /// for modes that keep running. Any stdin is written and closed; stdout is left for the caller.
+    ///
+    /// # Panics
+    /// - Failed to execute the command.
+    /// - Could not write to stdin.
+    pub fn spawn(&mut self) -> Child {
         // Leverage the same hidden flags that the Linux wrapper uses in order to simulate a tty
         self.cmd
             .arg(format!("--stdin-is-tty={}", self.stdin.is_none()))
@@ -68,19 +98,11 @@ impl<'a> XselHarness<'a> {
 
         let mut child = self.cmd.spawn().unwrap();
 
-        if let Some(text) = self.stdin {
-            child
-                .stdin
-                .take()
-                .unwrap()
-                .write_all(text.as_bytes())
-                .unwrap();
+        if let Some(input) = self.stdin {
+            child.stdin.take().unwrap().write_all(input).unwrap();
         }
 
-        let output = child.wait_with_output().unwrap();
-        assert!(output.status.success(), "{output:?}");
-
-        String::from_utf8(output.stdout).unwrap()
+        child
     }
 }
 
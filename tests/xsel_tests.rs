@@ -397,3 +397,306 @@ fn keep_and_exchange_noop_and_exit() {
         "should not have copied to clipboard"
     );
 }
+
+#[test]
+fn copies_and_pastes_html() {
+    let fragment = "<b>鏡音リン</b>";
+
+    clear_clipboard_via_powershell();
+
+    XselHarness::new()
+        .args(&["-t", "text/html"])
+        .stdin(fragment)
+        .run();
+    let raw = get_html_via_powershell();
+    assert!(
+        raw.starts_with("Version:"),
+        "should have the CF_HTML header"
+    );
+    assert!(raw.contains(&format!("<!--StartFragment-->{fragment}<!--EndFragment-->")));
+
+    let actual = XselHarness::new().args(&["-t", "text/html"]).run();
+    assert_eq!(actual, fragment);
+
+    let document = XselHarness::new()
+        .args(&["-t", "text/html", "--document"])
+        .run();
+    assert!(document.starts_with("<html>"));
+    assert!(document.contains(fragment));
+}
+
+#[test]
+fn copies_ansi_colors_as_rich_text() {
+    clear_clipboard_via_powershell();
+
+    XselHarness::new()
+        .args(&["--ansi"])
+        .stdin("test ok ... \x1b[32mok\x1b[0m\n\x1b[1;31merror\x1b[0m: <oops>\n")
+        .run();
+
+    assert_eq!(
+        get_clipboard_via_powershell(),
+        "test ok ... ok\nerror: <oops>\n",
+        "plain text should have the escapes removed"
+    );
+
+    let html = get_html_via_powershell();
+    assert!(html.contains("<span style=\"color: #13a10e\">ok</span>"));
+    assert!(
+        html.contains(
+            "<span style=\"color: #c50f1f; font-weight: bold\">error</span>: &lt;oops&gt;"
+        )
+    );
+}
+
+#[test]
+fn copies_and_pastes_png() {
+    let dib = std::fs::read(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/fixtures/dib/bottom_up_24.dib"
+    ))
+    .unwrap();
+    let png = wsl_tools::clipboard_image::dib_to_png(&dib).unwrap();
+
+    clear_clipboard_via_powershell();
+
+    XselHarness::new()
+        .args(&["-t", "image/png", "-i"])
+        .stdin_bytes(&png)
+        .run();
+    assert!(clipboard_contains_image());
+
+    let actual = XselHarness::new()
+        .args(&["-t", "image/png", "-o"])
+        .stdout_is_tty(false)
+        .run_bytes();
+    assert_eq!(actual, png);
+}
+
+#[test]
+fn copies_and_pastes_files() {
+    clear_clipboard_via_powershell();
+
+    XselHarness::new()
+        .args(&["-t", "text/uri-list", "-i"])
+        .stdin("# Comment\nfile:///C:/Windows/notepad.exe\nC:\\Users\\Public\n")
+        .run();
+
+    assert_eq!(
+        get_file_drop_list_via_powershell(),
+        ["C:\\Windows\\notepad.exe", "C:\\Users\\Public"]
+    );
+
+    let actual = XselHarness::new()
+        .args(&["-t", "text/uri-list", "-o"])
+        .run();
+    assert_eq!(actual, "C:\\Windows\\notepad.exe\nC:\\Users\\Public\n");
+}
+
+#[test]
+fn lists_targets() {
+    clear_clipboard_via_powershell();
+    XselHarness::new()
+        .args(&["-t", "text/html"])
+        .stdin("<i>hi</i>")
+        .run();
+
+    let expected = "TARGETS\ntext/html\n";
+    assert_eq!(XselHarness::new().args(&["--list-targets"]).run(), expected);
+    assert_eq!(
+        XselHarness::new()
+            .args(&["-t", "TARGETS"])
+            .stdin("stdin should be ignored")
+            .run(),
+        expected
+    );
+}
+
+#[test]
+fn sensitive_excludes_from_clipboard_history() {
+    XselHarness::new()
+        .args(&["--sensitive"])
+        .stdin("hunter2")
+        .run();
+
+    assert_eq!(get_clipboard_via_powershell(), "hunter2");
+    assert!(wsl_tools::clipboard::is_sensitive().unwrap());
+}
+
+#[test]
+fn clear_after_clears_the_clipboard() {
+    XselHarness::new()
+        .args(&["--clear-after", "500ms"])
+        .stdin("hunter2")
+        .run();
+
+    assert_eq!(get_clipboard_via_powershell(), "hunter2");
+    std::thread::sleep(std::time::Duration::from_secs(2));
+    assert!(
+        !clipboard_contains_text(),
+        "clipboard should have been cleared"
+    );
+}
+
+#[test]
+fn clear_after_leaves_newer_clipboard_alone() {
+    XselHarness::new()
+        .args(&["--clear-after", "500ms"])
+        .stdin("hunter2")
+        .run();
+
+    set_clipboard_via_powershell("copied afterwards");
+    std::thread::sleep(std::time::Duration::from_secs(2));
+    assert_eq!(get_clipboard_via_powershell(), "copied afterwards");
+}
+
+#[test]
+fn follow_copies_input_until_closed() {
+    set_clipboard_via_powershell("existing ");
+
+    let output = XselHarness::new()
+        .args(&["-f", "-a"])
+        .stdin("line 1\nline 2\n")
+        .stdout_is_tty(false)
+        .run();
+
+    assert_eq!(output, "", "-f implies -i");
+    assert_eq!(get_clipboard_via_powershell(), "existing line 1\nline 2\n");
+}
+
+#[test]
+fn zeroflush_overwrites_after_nul() {
+    XselHarness::new().args(&["-z"]).stdin("one\0two\0").run();
+
+    assert_eq!(get_clipboard_via_powershell(), "two");
+}
+
+#[test]
+fn follow_clears_after_input_ends() {
+    XselHarness::new()
+        .args(&["-f", "--clear-after", "500ms"])
+        .stdin("hunter2")
+        .run();
+
+    assert_eq!(get_clipboard_via_powershell(), "hunter2");
+    std::thread::sleep(std::time::Duration::from_secs(2));
+    assert!(
+        !clipboard_contains_text(),
+        "clipboard should have been cleared"
+    );
+}
+
+#[test]
+fn watch_outputs_each_copy() {
+    use std::io::{BufRead, BufReader};
+    use std::sync::mpsc;
+    use std::time::Duration;
+    use wsl_tools::clipboard::{self, Content};
+
+    let mut child = XselHarness::new()
+        .args(&["--watch", "--json"])
+        .stdout_is_tty(false)
+        .spawn();
+
+    // Read on another thread so that a missed copy fails the test rather than hanging it
+    let stdout = child.stdout.take().unwrap();
+    let (tx, rx) = mpsc::channel();
+    std::thread::spawn(move || {
+        for line in BufReader::new(stdout).lines() {
+            if tx.send(line.unwrap()).is_err() {
+                break;
+            }
+        }
+    });
+    std::thread::sleep(Duration::from_millis(500));
+
+    // Copies within the poll interval coalesce, so leave time for each to be seen
+    set_clipboard_via_powershell("鏡音リン\r\n");
+    std::thread::sleep(Duration::from_millis(500));
+    clipboard::set(&[Content::Text("hunter2"), Content::Sensitive]).unwrap();
+    std::thread::sleep(Duration::from_millis(500));
+    set_clipboard_via_powershell("https://example.com/");
+
+    let next_text = || {
+        let line = rx
+            .recv_timeout(Duration::from_secs(5))
+            .expect("timed out waiting for copy");
+        let record: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert!(record["time"].is_string(), "{line}");
+        record["text"].as_str().unwrap().to_string()
+    };
+
+    let first = next_text();
+    let second = next_text();
+
+    child.kill().unwrap();
+    child.wait().unwrap();
+
+    assert_eq!(first, "鏡音リン\n");
+    assert_eq!(
+        second, "https://example.com/",
+        "sensitive content should have been skipped"
+    );
+}
+
+#[test]
+fn decodes_input_encodings() {
+    let utf16 = b"\xff\xfeR\0i\0n\0";
+    XselHarness::new().stdin_bytes(utf16).run();
+    assert_eq!(get_clipboard_via_powershell(), "Rin", "should detect BOM");
+
+    XselHarness::new()
+        .args(&["--encoding", "cp1252"])
+        .stdin_bytes(b"caf\xe9")
+        .run();
+    assert_eq!(get_clipboard_via_powershell(), "café");
+
+    // ISO-2022-JP doesn't accept MB_ERR_INVALID_CHARS
+    for encoding in ["iso-2022-jp", "50220"] {
+        XselHarness::new()
+            .args(&["--encoding", encoding])
+            .stdin_bytes(b"\x1b$B6@2;%j%s\x1b(B")
+            .run();
+        assert_eq!(get_clipboard_via_powershell(), "鏡音リン");
+    }
+}
+
+#[test]
+fn rejects_invalid_utf8_unless_lossy() {
+    set_clipboard_via_powershell("unchanged");
+
+    let output = XselHarness::new()
+        .stdin_bytes(b"ab\xffc")
+        .spawn()
+        .wait_with_output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert_eq!(get_clipboard_via_powershell(), "unchanged");
+
+    XselHarness::new()
+        .args(&["--lossy"])
+        .stdin_bytes(b"ab\xffc")
+        .run();
+    assert_eq!(get_clipboard_via_powershell(), "ab\u{fffd}c");
+}
+
+#[test]
+fn encodes_output() {
+    set_clipboard_via_powershell("café");
+
+    let actual = XselHarness::new()
+        .args(&["-o", "--output-encoding", "cp1252"])
+        .run_bytes();
+    assert_eq!(actual, b"caf\xe9");
+
+    let actual = XselHarness::new()
+        .args(&["-o", "--output-encoding", "utf-16le"])
+        .run_bytes();
+    assert_eq!(actual, b"c\0a\0f\0\xe9\0");
+
+    // GB18030 doesn't accept the flags used to detect unrepresentable characters
+    let actual = XselHarness::new()
+        .args(&["-o", "--output-encoding", "gb18030"])
+        .run_bytes();
+    assert_eq!(actual, b"caf\xa8\xa6");
+}